@@ -7,6 +7,9 @@ use log::LevelFilter;
 
 use crate::scene::adapters::FitMode;
 use crate::scene::adapters::{winit_adapter, wlr_app};
+use crate::scene::audio::AudioSource;
+use crate::scene::loader::project::Wallpaper;
+use crate::scene::power::{PowerPolicy, Throttle};
 
 // ── Root CLI ─────────────────────────────────────────────────────────────────
 
@@ -16,7 +19,8 @@ struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 
-    // wallpaper .pkg file path
+    // wallpaper path: a .pkg, an .mp4/.webm video, or a Workshop item
+    // directory (or its project.json)
     #[arg(short, default_value = "./scene.pkg", global = false)]
     path: String,
 
@@ -28,6 +32,32 @@ struct Cli {
     #[arg(long, default_value = "cover")]
     fit_mode: String,
 
+    /// Frame rate limit; 0 renders as fast as the compositor allows.
+    #[arg(long, default_value_t = 30)]
+    fps: u32,
+
+    /// Throttle while on battery: `none`, `no-effects`, `freeze`, a frame
+    /// rate, or a comma-separated combination (e.g. `15,no-effects`).
+    #[arg(long, default_value = "15")]
+    on_battery: String,
+
+    /// Battery percentage at or below which `--on-low-battery` applies.
+    #[arg(long, default_value_t = 20)]
+    low_battery: u32,
+
+    /// Throttle on low battery (same values as `--on-battery`).
+    #[arg(long, default_value = "freeze")]
+    on_low_battery: String,
+
+    /// Seconds without input before `--on-idle` applies (wlr mode); 0
+    /// disables idle tracking.
+    #[arg(long, default_value_t = 300)]
+    idle_timeout: u32,
+
+    /// Throttle while idle (same values as `--on-battery`).
+    #[arg(long, default_value = "freeze")]
+    on_idle: String,
+
     // Bypass all post-process effects, render as static image
     #[arg(long, default_value_t = false)]
     no_effects: bool,
@@ -40,6 +70,16 @@ struct Cli {
     /// fallback (e.g. Steam/steamapps/common/wallpaper_engine/assets).
     #[arg(long)]
     assets_path: Option<String>,
+
+    /// Pause the wallpaper's sounds while fullscreen or maximized windows
+    /// cover it (wlr mode).
+    #[arg(long, default_value_t = false)]
+    mute_covered: bool,
+
+    /// Audio source for audio-reactive wallpapers: `monitor` (desktop
+    /// audio), `off`, or a path to a WAV file.
+    #[arg(long, default_value = "monitor")]
+    audio: String,
 }
 
 // ── Subcommands ──────────────────────────────────────────────────────────────
@@ -222,7 +262,13 @@ fn main() {
     // No subcommand → run the wallpaper engine.
     setup_logging(&cli.log_level);
 
-    validate_pkg_path(&cli.path);
+    let Some(wallpaper) = Wallpaper::locate(Path::new(&cli.path)) else {
+        panic!(
+            "Path '{}' is not a .pkg, a video file or a wallpaper project",
+            cli.path
+        );
+    };
+    let path = wallpaper.path().to_string_lossy().into_owned();
 
     let fit_mode = match cli.fit_mode.as_str() {
         "cover" => FitMode::Cover,
@@ -237,9 +283,37 @@ fn main() {
         }
     };
 
+    let audio = AudioSource::parse(&cli.audio);
+
+    let throttle = |arg: &str, value: &str| {
+        Throttle::parse(value).unwrap_or_else(|| {
+            eprintln!(
+                "Invalid --{} '{}'. Valid: none, no-effects, freeze, a frame rate, or a list",
+                arg, value
+            );
+            std::process::exit(1);
+        })
+    };
+    let power = PowerPolicy {
+        fps: cli.fps,
+        on_battery: throttle("on-battery", &cli.on_battery),
+        low_battery: cli.low_battery,
+        on_low_battery: throttle("on-low-battery", &cli.on_low_battery),
+        idle_timeout: cli.idle_timeout,
+        on_idle: throttle("on-idle", &cli.on_idle),
+    };
+
     match cli.modes.as_str() {
-        "winit" => winit_adapter::start(cli.path, cli.no_effects, cli.assets_path),
-        "wlr" => wlr_app::start(cli.path, fit_mode, cli.no_effects, cli.assets_path),
+        "winit" => winit_adapter::start(path, power, cli.no_effects, cli.assets_path, audio),
+        "wlr" => wlr_app::start(
+            path,
+            fit_mode,
+            power,
+            cli.no_effects,
+            cli.assets_path,
+            audio,
+            cli.mute_covered,
+        ),
         _ => {
             eprintln!("Unknown display mode '{}'. Valid: wlr, winit", cli.modes);
         }
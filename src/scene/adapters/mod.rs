@@ -8,6 +8,8 @@
 //! * **`wlr_app`** — Uses the wlr-layer-shell Wayland protocol to render on
 //!   a `Layer::Background` surface behind all windows.  No cursor tracking
 //!   (Wayland's security model does not allow it for background surfaces).
+//!
+//! Both cap their frame rate with [`pacing::FramePacer`].
 
 /// How the wallpaper is fitted to the output.
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -20,5 +22,6 @@ pub enum FitMode {
     Stretch,
 }
 
+pub mod pacing;
 pub mod winit_adapter;
 pub mod wlr_app;
@@ -0,0 +1,81 @@
+//! Frame rate limiting shared by both adapters.
+//!
+//! The adapters already wait for the compositor (frame callbacks, vsync);
+//! [`FramePacer`] additionally spaces frames at least `1 / fps` apart so an
+//! almost static wallpaper does not redraw at the display's full rate.
+
+use std::time::{Duration, Instant};
+
+pub struct FramePacer {
+    /// Time between frames; `None` when unlimited.
+    interval: Option<Duration>,
+    /// When the next frame may start.
+    next: Instant,
+}
+
+impl FramePacer {
+    /// `fps` of 0 means unlimited.
+    pub fn new(fps: u32) -> Self {
+        Self {
+            interval: (fps > 0).then(|| Duration::from_secs(1) / fps),
+            next: Instant::now(),
+        }
+    }
+
+    /// Change the frame rate; the next frame stays due when it was.
+    pub fn set_fps(&mut self, fps: u32) {
+        self.interval = (fps > 0).then(|| Duration::from_secs(1) / fps);
+    }
+
+    /// Time left at `now` before the next frame is due.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.next.saturating_duration_since(now)
+    }
+
+    /// Sleep until the next frame is due.
+    pub fn wait(&self) {
+        let remaining = self.remaining(Instant::now());
+        if !remaining.is_zero() {
+            std::thread::sleep(remaining);
+        }
+    }
+
+    /// Record that a frame started at `now`.  Frames keep a steady cadence;
+    /// after a stall longer than one interval the schedule restarts from
+    /// `now` instead of catching up with a burst.
+    pub fn frame_started(&mut self, now: Instant) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        self.next += interval;
+        if self.next < now {
+            self.next = now + interval;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_pacer() {
+        let ms = Duration::from_millis;
+        let mut pacer = FramePacer::new(50);
+        let start = pacer.next;
+        assert_eq!(pacer.remaining(start), Duration::ZERO);
+
+        // Started a little late: the cadence is kept.
+        pacer.frame_started(start + ms(5));
+        assert_eq!(pacer.remaining(start + ms(5)), ms(15));
+
+        // Stalled: rescheduled one interval from now.
+        pacer.frame_started(start + ms(100));
+        assert_eq!(pacer.remaining(start + ms(100)), ms(20));
+
+        let mut unlimited = FramePacer::new(0);
+        let now = Instant::now();
+        unlimited.frame_started(now);
+        assert_eq!(unlimited.remaining(now), Duration::ZERO);
+    }
+}
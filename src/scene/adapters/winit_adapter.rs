@@ -3,27 +3,57 @@
 //! This adapter works on both X11 and Wayland (via XWayland or native
 //! wayland winit). Cursor tracking works because winit windows receive
 //! pointer events even when stacked behind other windows.
-
-use std::sync::{Arc, Mutex};
+//!
+//! Redraws are requested from `about_to_wait` once the [`FramePacer`]
+//! allows the next frame; until then the event loop sleeps.  Static scenes
+//! (see [`WgpuApp::is_static`]) only redraw after a resize or a cursor move
+//! they react to.  While the scene loads, the window title shows how far
+//! it is.  The battery
+//! part of the power policy applies here too; idle tracking needs the wlr
+//! adapter.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use log;
 use pollster::block_on;
 use winit::{
     application::ApplicationHandler,
     event::WindowEvent,
-    event_loop::EventLoop,
+    event_loop::{ControlFlow, EventLoop},
     window::{Fullscreen, Window},
 };
 
-use crate::scene::renderer::app::WgpuApp;
+use super::pacing::FramePacer;
+use crate::scene::{
+    audio::AudioSource,
+    power::{POWER_POLL, PowerMonitor, PowerPolicy, Throttle},
+    renderer::app::WgpuApp,
+};
+
+/// How often a frozen wallpaper checks whether it may render again.
+const FROZEN_POLL: Duration = Duration::from_millis(500);
+
+const TITLE: &str = "Linux wallpaper engine";
 
 struct WinitApp {
     app: Arc<Mutex<Option<WgpuApp>>>,
     window: Option<Arc<Window>>,
+    pacer: FramePacer,
+    /// A redraw was requested and has not been handled yet.
+    redraw_pending: bool,
+    power: PowerMonitor,
+    /// Last applied power throttle.
+    throttle: Option<Throttle>,
+    /// Frames were skipped (frozen) since the last render.
+    stopped: bool,
 
     pkg_path: String,
     no_effects: bool,
     assets_path: Option<String>,
+    audio: AudioSource,
 }
 
 impl ApplicationHandler for WinitApp {
@@ -37,7 +67,7 @@ impl ApplicationHandler for WinitApp {
             .with_fullscreen(Some(Fullscreen::Borderless(None)))
             .with_window_level(winit::window::WindowLevel::AlwaysOnBottom)
             .with_transparent(true)
-            .with_title("Linux wallpaper engine");
+            .with_title(TITLE);
 
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
 
@@ -49,6 +79,7 @@ impl ApplicationHandler for WinitApp {
             [size.width, size.height],
             self.no_effects,
             self.assets_path.clone(),
+            &self.audio,
         ));
 
         wgpu_app.load();
@@ -71,12 +102,20 @@ impl ApplicationHandler for WinitApp {
                 self.window.as_ref().unwrap().pre_present_notify();
 
                 log::trace!("RedrawRequested: calling render...");
+                self.pacer.frame_started(Instant::now());
+                self.redraw_pending = false;
+                let loading = app.is_loading();
                 let render_result = app.render();
                 if render_result.is_none() {
                     log::warn!("render returned None");
                 }
-                self.window.as_ref().unwrap().request_redraw();
-                log::trace!("requested next redraw");
+                if loading {
+                    let window = self.window.as_ref().unwrap();
+                    match app.load_progress() {
+                        Some(progress) => window.set_title(&format!("{} ({})", TITLE, progress)),
+                        None => window.set_title(TITLE),
+                    }
+                }
             }
             WindowEvent::Resized(physical_size) => {
                 let app = app.as_mut().unwrap();
@@ -94,25 +133,89 @@ impl ApplicationHandler for WinitApp {
                         // Normalize cursor to [0, 1] range, (0,0) = top-left, as expected by g_ParallaxPosition
                         let nx = position.x as f32 / size.width as f32;
                         let ny = position.y as f32 / size.height as f32;
-                        app.user_params = crate::scene::renderer::app::UserParams {
-                            cursor_position: [nx, ny],
-                        };
+                        app.set_cursor([nx, ny]);
                     }
                 }
             }
             _ => {}
         }
     }
+
+    fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        let Some(window) = self.window.as_ref() else {
+            return;
+        };
+        if self.redraw_pending {
+            return;
+        }
+        let now = Instant::now();
+
+        let throttle = self.power.throttle(now);
+        if self.throttle != Some(throttle) {
+            log::info!("power policy: {:?}", throttle);
+            self.pacer.set_fps(throttle.fps.unwrap_or(0));
+            if let Some(app) = self.app.lock().unwrap().as_mut() {
+                app.set_effects_paused(throttle.no_effects);
+            }
+            self.throttle = Some(throttle);
+        }
+        if throttle.freeze {
+            self.stopped = true;
+            event_loop.set_control_flow(ControlFlow::WaitUntil(now + FROZEN_POLL));
+            return;
+        }
+        if self.stopped {
+            self.stopped = false;
+            if let Some(app) = self.app.lock().unwrap().as_mut() {
+                app.resume();
+            }
+        }
+        if self
+            .app
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|app| !app.needs_redraw())
+        {
+            // Static scene already presented: sleep until a resize or
+            // cursor move changes it, or the power policy is due again.
+            event_loop.set_control_flow(ControlFlow::WaitUntil(now + POWER_POLL));
+            return;
+        }
+
+        let remaining = self.pacer.remaining(now);
+        if remaining.is_zero() {
+            event_loop.set_control_flow(ControlFlow::Wait);
+            window.request_redraw();
+            self.redraw_pending = true;
+            log::trace!("requested next redraw");
+        } else {
+            event_loop.set_control_flow(ControlFlow::WaitUntil(now + remaining));
+        }
+    }
 }
 
-pub fn start(pkg_path: String, no_effects: bool, assets_path: Option<String>) {
+/// Start the wallpaper engine in a winit window, throttled by `power`.
+pub fn start(
+    pkg_path: String,
+    power: PowerPolicy,
+    no_effects: bool,
+    assets_path: Option<String>,
+    audio: AudioSource,
+) {
     let event_loop = EventLoop::new().unwrap();
     let mut app = WinitApp {
         pkg_path,
         no_effects,
         assets_path,
+        audio,
         app: Arc::new(Mutex::new(None)),
         window: None,
+        pacer: FramePacer::new(power.fps),
+        redraw_pending: false,
+        power: PowerMonitor::new(power),
+        throttle: None,
+        stopped: false,
     };
 
     event_loop.run_app(&mut app).unwrap();
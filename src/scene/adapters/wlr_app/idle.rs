@@ -0,0 +1,50 @@
+//! User idle state from `ext-idle-notify-v1`.
+//!
+//! One notification is created for the first seat with the power policy's
+//! idle timeout; its `idled`/`resumed` events feed
+//! [`PowerMonitor::set_idle`](crate::scene::power::PowerMonitor::set_idle).
+//! Idle inhibitors (a video player in the foreground, say) are honoured by
+//! the compositor.
+
+use wayland_client::{QueueHandle, protocol::wl_seat::WlSeat};
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1::ExtIdleNotificationV1, ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
+
+use super::WlrState;
+
+/// Opaque data tag for `ext_idle_*` dispatch.
+#[derive(Debug)]
+pub struct IdleData;
+
+pub struct IdleState {
+    /// `ext_idle_notifier_v1` global.
+    notifier: Option<ExtIdleNotifierV1>,
+    /// Notification for the watched seat (kept alive).
+    #[allow(dead_code)]
+    notification: Option<ExtIdleNotificationV1>,
+    timeout_ms: u32,
+}
+
+impl IdleState {
+    /// `timeout` is in seconds; 0 disables idle tracking.
+    pub fn new(notifier: Option<ExtIdleNotifierV1>, timeout: u32) -> Self {
+        Self {
+            notifier: notifier.filter(|_| timeout > 0),
+            notification: None,
+            timeout_ms: timeout.saturating_mul(1000),
+        }
+    }
+
+    /// Watch `seat` unless a seat is watched already.
+    pub fn watch(&mut self, seat: &WlSeat, qh: &QueueHandle<WlrState>) {
+        let Some(notifier) = self.notifier.as_ref() else {
+            return;
+        };
+        if self.notification.is_none() {
+            self.notification =
+                Some(notifier.get_idle_notification(self.timeout_ms, seat, qh, IdleData));
+            log::info!("ext_idle_notification_v1 created ({} ms)", self.timeout_ms);
+        }
+    }
+}
@@ -4,6 +4,24 @@
 //! The WGPU swapchain uses the wallpaper's native resolution; the compositor
 //! handles scaling the layer surface to fill the output.
 //!
+//! ## Frame pacing
+//!
+//! Each frame requests a `wl_surface.frame` callback before presenting, and
+//! the next frame is only rendered once it arrives, so nothing is drawn
+//! while the compositor is not showing the surface.  On top of that a
+//! [`FramePacer`] caps the frame rate (`--fps`), lowered further by the
+//! power policy (see [`crate::scene::power`]), which can also pause effects
+//! or freeze the wallpaper on battery or while the user is idle ([`idle`]).
+//! A static scene (see [`WgpuApp::is_static`]) is rendered once and again
+//! only when the surface is resized or the power policy changes, which is
+//! checked every [`POWER_POLL`].
+//!
+//! ## Covered outputs
+//!
+//! While fullscreen or maximized windows cover the wallpaper (see
+//! [`toplevel`]) nothing is rendered and, with `--mute-covered`, the
+//! wallpaper's sounds pause.
+//!
 //! ## Depth parallax
 //!
 //! Wayland's security model does not allow background (or any non-focused)
@@ -11,15 +29,22 @@
 //! cursor position are therefore unavailable in the wlr adapter.  The winit
 //! adapter should be used when cursor-parallax is desired.
 
+mod idle;
 mod scale;
+mod toplevel;
 
-use std::ptr::NonNull;
+use std::{
+    ptr::NonNull,
+    time::{Duration, Instant},
+};
 
 use log;
+use idle::{IdleData, IdleState};
 use pollster::block_on;
 use raw_window_handle::{
     RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
 };
+use rustix::event::{PollFd, PollFlags, Timespec};
 use scale::{FractionalScaleData, ScaleState};
 use smithay_client_toolkit::{
     compositor::{CompositorHandler, CompositorState},
@@ -36,11 +61,16 @@ use smithay_client_toolkit::{
         },
     },
 };
+use toplevel::{ToplevelData, ToplevelState};
 use wayland_client::{
-    Connection, Dispatch, Proxy, QueueHandle,
+    Connection, Dispatch, DispatchError, EventQueue, Proxy, QueueHandle, event_created_child,
     globals::registry_queue_init,
     protocol::{wl_output, wl_seat, wl_surface},
 };
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
 use wayland_protocols::wp::{
     fractional_scale::v1::client::{
         wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
@@ -48,8 +78,20 @@ use wayland_protocols::wp::{
     },
     viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
 };
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
 
-use crate::scene::renderer::app::{InitAppSurface, WgpuApp};
+use super::pacing::FramePacer;
+use crate::scene::{
+    audio::AudioSource,
+    power::{POWER_POLL, PowerMonitor, PowerPolicy},
+    renderer::app::{InitAppSurface, WgpuApp},
+};
+
+/// How often a frozen wallpaper checks whether it may render again.
+const FROZEN_POLL: Duration = Duration::from_millis(500);
 
 /// Main state for the wlr-layer-shell adapter.
 ///
@@ -61,16 +103,26 @@ pub struct WlrState {
     pub output_state: OutputState,
     pub app: WgpuApp,
     pub fit_mode: super::FitMode,
-    pub wp_resolution: [u32; 2],
+    /// Unknown until the loading scene's `scene.json` is read; the
+    /// surface is stretched over the output meanwhile.
+    pub wp_resolution: Option<[u32; 2]>,
 
     /// Fractional-scale and viewporter management.
     pub scale: ScaleState,
+    /// Toplevels that may cover the wallpaper.
+    pub toplevels: ToplevelState,
+    /// Battery/idle throttling.
+    pub power: PowerMonitor,
+    /// Idle notification feeding `power`.
+    pub idle: IdleState,
 
     // Last configure state, so we can re-apply when scale arrives.
     last_logical: Option<(u32, u32)>,
     last_layer: Option<LayerSurface>,
     /// Track last applied logical size to skip redundant reconfigures.
     last_applied_logical: Option<(u32, u32)>,
+    /// A frame callback was requested and has not fired yet.
+    awaiting_frame: bool,
 }
 
 // ---------------------------------------------------------------------------
@@ -94,22 +146,26 @@ impl CompositorHandler for WlrState {
         _: wl_output::Transform,
     ) {
     }
-    fn frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: u32) {}
+    fn frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: u32) {
+        self.awaiting_frame = false;
+    }
     fn surface_enter(
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
         _: &wl_surface::WlSurface,
-        _: &wl_output::WlOutput,
+        output: &wl_output::WlOutput,
     ) {
+        self.toplevels.surface_enter(output);
     }
     fn surface_leave(
         &mut self,
         _: &Connection,
         _: &QueueHandle<Self>,
         _: &wl_surface::WlSurface,
-        _: &wl_output::WlOutput,
+        output: &wl_output::WlOutput,
     ) {
+        self.toplevels.surface_leave(output);
     }
 }
 
@@ -190,6 +246,73 @@ impl Dispatch<WpViewport, FractionalScaleData, WlrState> for WlrState {
     }
 }
 
+// ---- Foreign-toplevel dispatch --------------------------------------------------
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ToplevelData, WlrState> for WlrState {
+    fn event(
+        _: &mut WlrState,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: <ZwlrForeignToplevelManagerV1 as Proxy>::Event,
+        _: &ToplevelData,
+        _: &Connection,
+        _: &QueueHandle<WlrState>,
+    ) {
+        // New handles are tracked from their first event.
+        if let zwlr_foreign_toplevel_manager_v1::Event::Finished = event {
+            log::info!("zwlr_foreign_toplevel_manager_v1 finished");
+        }
+    }
+
+    event_created_child!(WlrState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ToplevelData)
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ToplevelData, WlrState> for WlrState {
+    fn event(
+        state: &mut WlrState,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: <ZwlrForeignToplevelHandleV1 as Proxy>::Event,
+        _: &ToplevelData,
+        _: &Connection,
+        _: &QueueHandle<WlrState>,
+    ) {
+        state.toplevels.handle_event(handle, event);
+    }
+}
+
+// ---- Idle-notify dispatch --------------------------------------------------------
+
+impl Dispatch<ExtIdleNotifierV1, IdleData, WlrState> for WlrState {
+    fn event(
+        _: &mut WlrState,
+        _: &ExtIdleNotifierV1,
+        _: <ExtIdleNotifierV1 as Proxy>::Event,
+        _: &IdleData,
+        _: &Connection,
+        _: &QueueHandle<WlrState>,
+    ) {
+        unreachable!()
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, IdleData, WlrState> for WlrState {
+    fn event(
+        state: &mut WlrState,
+        _: &ExtIdleNotificationV1,
+        event: <ExtIdleNotificationV1 as Proxy>::Event,
+        _: &IdleData,
+        _: &Connection,
+        _: &QueueHandle<WlrState>,
+    ) {
+        match event {
+            ext_idle_notification_v1::Event::Idled => state.power.set_idle(true),
+            ext_idle_notification_v1::Event::Resumed => state.power.set_idle(false),
+            _ => {}
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Core logic
 // ---------------------------------------------------------------------------
@@ -236,12 +359,11 @@ impl WlrState {
         let Some(ref layer) = self.last_layer else {
             return;
         };
-        let (wp_w, wp_h) = (self.wp_resolution[0] as f32, self.wp_resolution[1] as f32);
-
         // Compute layer-surface size from fit mode + logical size.
-        let (layer_w, layer_h) = match self.fit_mode {
-            super::FitMode::Stretch => (log_w, log_h),
-            _ => {
+        let (layer_w, layer_h) = match (self.fit_mode, self.wp_resolution) {
+            (super::FitMode::Stretch, _) | (_, None) => (log_w, log_h),
+            (_, Some([wp_w, wp_h])) => {
+                let (wp_w, wp_h) = (wp_w as f32, wp_h as f32);
                 let s = match self.fit_mode {
                     super::FitMode::Cover => f32::max(log_w as f32 / wp_w, log_h as f32 / wp_h),
                     super::FitMode::Contain => f32::min(log_w as f32 / wp_w, log_h as f32 / wp_h),
@@ -305,7 +427,9 @@ impl SeatHandler for WlrState {
     fn seat_state(&mut self) -> &mut SeatState {
         &mut self.seat_state
     }
-    fn new_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+    fn new_seat(&mut self, _: &Connection, qh: &QueueHandle<Self>, seat: wl_seat::WlSeat) {
+        self.idle.watch(&seat, qh);
+    }
     fn new_capability(
         &mut self,
         _: &Connection,
@@ -350,12 +474,16 @@ impl ProvidesRegistryState for WlrState {
 ///
 /// Creates a `Layer::Background` surface, binds the required Wayland
 /// globals (compositor, layer-shell, fractional-scale, viewporter), and
-/// enters the render loop.
+/// enters the render loop, throttled by `power`.  With `mute_covered`,
+/// sounds pause while the wallpaper is covered.
 pub fn start(
     pkg_path: String,
     fit_mode: super::FitMode,
+    power: PowerPolicy,
     no_effects: bool,
     assets_path: Option<String>,
+    audio: AudioSource,
+    mute_covered: bool,
 ) {
     let conn = Connection::connect_to_env().unwrap();
     let (globals, mut event_queue) = registry_queue_init(&conn).unwrap();
@@ -391,6 +519,21 @@ pub fn start(
         vp
     });
 
+    // zwlr_foreign_toplevel_manager_v1
+    let toplevel_mgr: Option<ZwlrForeignToplevelManagerV1> =
+        globals.bind(&qh, 1..=3, ToplevelData).ok();
+    if toplevel_mgr.is_some() {
+        log::info!("zwlr_foreign_toplevel_manager_v1 bound");
+    } else {
+        log::info!("zwlr_foreign_toplevel_manager_v1 not available, covered outputs keep rendering");
+    }
+
+    // ext_idle_notifier_v1
+    let idle_notifier: Option<ExtIdleNotifierV1> = globals.bind(&qh, 1..=1, IdleData).ok();
+    if idle_notifier.is_none() {
+        log::info!("ext_idle_notifier_v1 not available, idle throttling disabled");
+    }
+
     let layer = layer_shell.create_layer_surface(
         &qh,
         surface,
@@ -417,9 +560,10 @@ pub fn start(
         [256, 256],
         no_effects,
         assets_path,
+        &audio,
     ));
     app.load();
-    let wp_res = app.resolution.expect("Unknown resolution");
+    let wp_resolution = app.resolution;
 
     let mut state = WlrState {
         registry_state: RegistryState::new(&globals),
@@ -427,22 +571,121 @@ pub fn start(
         output_state: OutputState::new(&globals, &qh),
         app,
         fit_mode,
-        wp_resolution: wp_res,
+        wp_resolution,
         scale: ScaleState::new(frac_mgr, frac_scale, viewporter, viewport),
+        toplevels: ToplevelState::new(toplevel_mgr),
+        power: PowerMonitor::new(power),
+        idle: IdleState::new(idle_notifier, power.idle_timeout),
         last_logical: None,
         last_layer: None,
         last_applied_logical: None,
+        awaiting_frame: false,
     };
 
+    let mut pacer = FramePacer::new(power.fps);
+    let mut throttle = None;
     let mut frame_count: u64 = 0;
+    let mut covered = false;
+    let mut stopped = false;
     loop {
+        let current = state.power.throttle(Instant::now());
+        if throttle != Some(current) {
+            log::info!("power policy: {:?}", current);
+            pacer.set_fps(current.fps.unwrap_or(0));
+            state.app.set_effects_paused(current.no_effects);
+            throttle = Some(current);
+        }
+
+        if state.toplevels.covered() != covered {
+            covered = !covered;
+            log::info!("wallpaper {}", if covered { "covered" } else { "uncovered" });
+            if mute_covered {
+                state.app.audio.set_paused(covered);
+            }
+        }
+        if covered {
+            stopped = true;
+            event_queue.blocking_dispatch(&mut state).unwrap();
+            continue;
+        }
+        if current.freeze {
+            // Battery and idle changes are polled, not events.
+            stopped = true;
+            std::thread::sleep(FROZEN_POLL);
+            event_queue.roundtrip(&mut state).unwrap();
+            continue;
+        }
+        if stopped {
+            stopped = false;
+            state.app.resume();
+        }
+
+        if state.awaiting_frame {
+            log::trace!("frame {}: waiting for frame callback...", frame_count);
+            event_queue.blocking_dispatch(&mut state).unwrap();
+            continue;
+        }
+        if !state.app.needs_redraw() {
+            // A static scene is already on screen; wait for a resize, or
+            // until the power policy is due to be checked again.
+            dispatch_timeout(&mut event_queue, &mut state, POWER_POLL).unwrap();
+            continue;
+        }
         log::trace!("frame {}: dispatching events...", frame_count);
         event_queue.dispatch_pending(&mut state).unwrap();
+        pacer.wait();
+        pacer.frame_started(Instant::now());
+
+        // The callback is attached to the commit wgpu makes on present.
+        let surface = layer.wl_surface();
+        surface.frame(&qh, surface.clone());
+        state.awaiting_frame = true;
+
         log::trace!("frame {}: calling render...", frame_count);
         let render_result = state.app.render();
         if render_result.is_none() {
             log::warn!("frame {}: render returned None", frame_count);
+            // Nothing was committed, so no callback will come.
+            state.awaiting_frame = false;
+        }
+        if state.wp_resolution != state.app.resolution {
+            // The loading scene's size became known: fit it.
+            state.wp_resolution = state.app.resolution;
+            state.last_applied_logical = None;
+            state.reconfigure();
         }
+        event_queue.flush().unwrap();
         frame_count = frame_count.wrapping_add(1);
     }
 }
+
+/// [`EventQueue::blocking_dispatch`] that gives up after `timeout`
+/// without events.
+fn dispatch_timeout(
+    event_queue: &mut EventQueue<WlrState>,
+    state: &mut WlrState,
+    timeout: Duration,
+) -> Result<usize, DispatchError> {
+    let dispatched = event_queue.dispatch_pending(state)?;
+    if dispatched > 0 {
+        return Ok(dispatched);
+    }
+    event_queue.flush()?;
+
+    if let Some(guard) = event_queue.prepare_read() {
+        let fd = guard.connection_fd();
+        let mut fds = [PollFd::new(&fd, PollFlags::IN | PollFlags::ERR)];
+        let timeout = Timespec::try_from(timeout).ok();
+        // Interrupted counts as timed out; the caller comes back anyway.
+        let ready = rustix::event::poll(&mut fds, timeout.as_ref()).unwrap_or(0) > 0;
+        if ready {
+            match guard.read() {
+                Ok(_) => {}
+                Err(wayland_client::backend::WaylandError::Io(e))
+                    if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    event_queue.dispatch_pending(state)
+}
@@ -0,0 +1,117 @@
+//! Detect when windows cover the wallpaper.
+//!
+//! `zwlr_foreign_toplevel_manager_v1` reports every toplevel with the
+//! outputs it is on and its state.  The wallpaper counts as covered when
+//! each output its surface is on shows an activated, fullscreen or
+//! maximized, non-minimized toplevel.  The protocol does not say which
+//! workspace a toplevel is on, so only the activated one is trusted to be
+//! visible; covered outputs without keyboard focus keep rendering.
+//!
+//! `ext-foreign-toplevel-list-v1` only lists toplevels, without their
+//! state, so it cannot tell coverage.  Without the wlr protocol the adapter
+//! relies on the compositor withholding frame callbacks instead.
+
+use std::collections::HashMap;
+
+use wayland_client::{Proxy, backend::ObjectId, protocol::wl_output::WlOutput};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{Event, State, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+};
+
+/// Opaque data tag for `zwlr_foreign_toplevel_*` dispatch.
+#[derive(Debug)]
+pub struct ToplevelData;
+
+#[derive(Default)]
+struct Toplevel {
+    outputs: Vec<WlOutput>,
+    covering: bool,
+}
+
+/// Tracks toplevels and the outputs of the wallpaper surface.
+pub struct ToplevelState {
+    /// `zwlr_foreign_toplevel_manager_v1` global (kept alive).
+    #[allow(dead_code)]
+    pub manager: Option<ZwlrForeignToplevelManagerV1>,
+    toplevels: HashMap<ObjectId, Toplevel>,
+    /// Outputs the wallpaper surface is on.
+    surface_outputs: Vec<WlOutput>,
+}
+
+impl ToplevelState {
+    pub fn new(manager: Option<ZwlrForeignToplevelManagerV1>) -> Self {
+        Self {
+            manager,
+            toplevels: HashMap::new(),
+            surface_outputs: Vec::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, handle: &ZwlrForeignToplevelHandleV1, event: Event) {
+        let toplevel = self.toplevels.entry(handle.id()).or_default();
+        match event {
+            Event::OutputEnter { output } => toplevel.outputs.push(output),
+            Event::OutputLeave { output } => toplevel.outputs.retain(|o| *o != output),
+            Event::State { state } => toplevel.covering = is_covering(&state),
+            Event::Closed => {
+                self.toplevels.remove(&handle.id());
+                handle.destroy();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn surface_enter(&mut self, output: &WlOutput) {
+        self.surface_outputs.push(output.clone());
+    }
+
+    pub fn surface_leave(&mut self, output: &WlOutput) {
+        self.surface_outputs.retain(|o| o != output);
+    }
+
+    /// Every output of the wallpaper is covered by a toplevel.
+    pub fn covered(&self) -> bool {
+        !self.surface_outputs.is_empty()
+            && self.surface_outputs.iter().all(|output| {
+                self.toplevels
+                    .values()
+                    .any(|t| t.covering && t.outputs.contains(output))
+            })
+    }
+}
+
+/// Whether a `state` array describes a visible fullscreen or maximized
+/// toplevel.
+fn is_covering(state: &[u8]) -> bool {
+    let has = |flag: State| {
+        state
+            .chunks_exact(4)
+            .any(|v| u32::from_ne_bytes([v[0], v[1], v[2], v[3]]) == flag as u32)
+    };
+    has(State::Activated)
+        && (has(State::Fullscreen) || has(State::Maximized))
+        && !has(State::Minimized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_covering() {
+        let state = |flags: &[State]| -> Vec<u8> {
+            flags.iter().flat_map(|f| (*f as u32).to_ne_bytes()).collect()
+        };
+        assert!(is_covering(&state(&[State::Activated, State::Fullscreen])));
+        assert!(is_covering(&state(&[State::Maximized, State::Activated])));
+        // Not focused: may be on a hidden workspace.
+        assert!(!is_covering(&state(&[State::Fullscreen])));
+        assert!(!is_covering(&state(&[State::Activated])));
+        assert!(!is_covering(&state(&[
+            State::Activated,
+            State::Maximized,
+            State::Minimized
+        ])));
+    }
+}
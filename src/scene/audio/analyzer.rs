@@ -0,0 +1,187 @@
+//! FFT-based spectrum analysis of interleaved PCM samples.
+
+use std::{collections::VecDeque, f32::consts::PI, sync::Arc};
+
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+use super::{AudioSpectrum, BAND_RESOLUTIONS};
+
+/// Samples per FFT window (~43 ms at 48 kHz).
+const FFT_SIZE: usize = 2048;
+/// Lowest and highest frequency mapped onto the bands.
+const MIN_FREQ: f32 = 20.0;
+const MAX_FREQ: f32 = 16_000.0;
+/// Magnitudes at or below this level map to 0.
+const FLOOR_DB: f32 = -60.0;
+/// Per-update falloff so bars drop smoothly instead of flickering.
+const DECAY: f32 = 0.85;
+
+pub struct SpectrumAnalyzer {
+    sample_rate: u32,
+    channels: usize,
+    /// Most recent `FFT_SIZE` samples of the left and right channel.
+    history: [VecDeque<f32>; 2],
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    window_sum: f32,
+    previous: AudioSpectrum,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        // Hann window
+        let window: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / (FFT_SIZE - 1) as f32).cos())
+            .collect();
+        let window_sum = window.iter().sum();
+
+        Self {
+            sample_rate: sample_rate.max(1),
+            channels: channels.max(1) as usize,
+            history: [
+                VecDeque::from(vec![0.0; FFT_SIZE]),
+                VecDeque::from(vec![0.0; FFT_SIZE]),
+            ],
+            fft,
+            window,
+            window_sum,
+            previous: AudioSpectrum::default(),
+        }
+    }
+
+    /// Append interleaved samples.  Mono input feeds both channels;
+    /// channels beyond the second are ignored.
+    pub fn push(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            let left = frame[0];
+            let right = if self.channels > 1 { frame[1] } else { left };
+            for (history, sample) in self.history.iter_mut().zip([left, right]) {
+                if history.len() == FFT_SIZE {
+                    history.pop_front();
+                }
+                history.push_back(sample);
+            }
+        }
+    }
+
+    /// Analyze the current window and return the smoothed spectrum.
+    pub fn spectrum(&mut self) -> AudioSpectrum {
+        let mut out = AudioSpectrum::default();
+
+        for channel in 0..2 {
+            let magnitudes = self.magnitudes(channel);
+            for (i, &count) in BAND_RESOLUTIONS.iter().enumerate() {
+                let bands = self.bands(&magnitudes, count);
+                let (target, previous) = if channel == 0 {
+                    (&mut out.left[i], &self.previous.left[i])
+                } else {
+                    (&mut out.right[i], &self.previous.right[i])
+                };
+                for ((t, v), p) in target.iter_mut().zip(bands).zip(previous) {
+                    *t = v.max(p * DECAY);
+                }
+            }
+        }
+
+        self.previous = out.clone();
+        out
+    }
+
+    /// Amplitude of each FFT bin up to Nyquist, normalized so a full-scale
+    /// sine reads ~1.0.
+    fn magnitudes(&self, channel: usize) -> Vec<f32> {
+        let mut buffer: Vec<Complex<f32>> = self.history[channel]
+            .iter()
+            .zip(&self.window)
+            .map(|(&s, &w)| Complex::new(s * w, 0.0))
+            .collect();
+        self.fft.process(&mut buffer);
+
+        let scale = 2.0 / self.window_sum;
+        buffer[..FFT_SIZE / 2]
+            .iter()
+            .map(|c| c.norm() * scale)
+            .collect()
+    }
+
+    /// Group bins into `count` logarithmically spaced bands.
+    fn bands(&self, magnitudes: &[f32], count: usize) -> Vec<f32> {
+        let bin_hz = self.sample_rate as f32 / FFT_SIZE as f32;
+        let max_freq = MAX_FREQ.min(self.sample_rate as f32 / 2.0);
+        let ratio = (max_freq / MIN_FREQ).powf(1.0 / count as f32);
+        let last_bin = magnitudes.len() - 1;
+
+        (0..count)
+            .map(|band| {
+                let lo = MIN_FREQ * ratio.powi(band as i32);
+                let hi = lo * ratio;
+                let lo_bin = ((lo / bin_hz) as usize).min(last_bin);
+                let hi_bin = ((hi / bin_hz).ceil() as usize).clamp(lo_bin + 1, last_bin + 1);
+
+                let peak = magnitudes[lo_bin..hi_bin]
+                    .iter()
+                    .fold(0.0f32, |acc, &m| acc.max(m));
+                let db = 20.0 * peak.max(1e-9).log10();
+                ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (2.0 * PI * freq * i as f32 / rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_silence_is_zero() {
+        let mut analyzer = SpectrumAnalyzer::new(48_000, 2);
+        analyzer.push(&vec![0.0; FFT_SIZE * 2]);
+        let spectrum = analyzer.spectrum();
+        assert!(spectrum.left.iter().flatten().all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn test_sine_peaks_in_matching_band() {
+        let mut analyzer = SpectrumAnalyzer::new(48_000, 2);
+        // Signal on the left channel only.
+        let stereo: Vec<f32> = sine(1_000.0, 48_000, FFT_SIZE)
+            .into_iter()
+            .flat_map(|s| [s, 0.0])
+            .collect();
+        analyzer.push(&stereo);
+        let spectrum = analyzer.spectrum();
+
+        // 1 kHz on a 20 Hz..16 kHz log scale falls in band 9 of 16.
+        let (left, right) = (&spectrum.left[0], &spectrum.right[0]);
+        let peak = left
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .unwrap();
+        assert_eq!(peak, 9);
+        assert!(left[peak] > 0.9);
+        assert!(right.iter().all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn test_bands_decay() {
+        let mut analyzer = SpectrumAnalyzer::new(48_000, 1);
+        analyzer.push(&sine(1_000.0, 48_000, FFT_SIZE));
+        let loud = analyzer.spectrum();
+        analyzer.push(&vec![0.0; FFT_SIZE]);
+        let quiet = analyzer.spectrum();
+
+        assert_eq!(loud.left, loud.right);
+        for (a, b) in loud.left[2].iter().zip(&quiet.left[2]) {
+            assert!((b - a * DECAY).abs() < 1e-6);
+        }
+    }
+}
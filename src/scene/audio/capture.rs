@@ -0,0 +1,340 @@
+//! Audio sources feeding the spectrum analyzer.
+//!
+//! The desktop monitor is recorded by spawning `parec` (PulseAudio and
+//! pipewire-pulse) or `pw-record` (native PipeWire) with raw f32 output on
+//! stdout, which keeps the engine free of a client-library dependency.
+//! A WAV file can stand in for the monitor; it is played back in real time
+//! and looped.
+
+use std::{
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use rodio::Source;
+
+use super::{AudioSpectrum, SpectrumAnalyzer};
+
+const MONITOR_RATE: u32 = 48_000;
+const MONITOR_CHANNELS: u16 = 2;
+/// Granularity of simulated capture for file sources.
+const FILE_CHUNK: Duration = Duration::from_millis(10);
+
+/// Where audio-reactive uniforms take their samples from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioSource {
+    /// No capture; the spectrum stays zero.
+    Off,
+    /// The default output's monitor (what the desktop is playing).
+    Monitor,
+    /// Loop a WAV file.
+    File(PathBuf),
+}
+
+impl AudioSource {
+    /// Parse a CLI value: `off`, `monitor`, or a path to a WAV file.
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "off" | "none" => AudioSource::Off,
+            "monitor" => AudioSource::Monitor,
+            path => AudioSource::File(PathBuf::from(path)),
+        }
+    }
+}
+
+pub struct AudioCapture {
+    analyzer: Arc<Mutex<SpectrumAnalyzer>>,
+    child: Option<Child>,
+    stop: Arc<AtomicBool>,
+}
+
+impl AudioCapture {
+    /// Start capturing.  Returns `None` for [`AudioSource::Off`] or when
+    /// the source cannot be opened.
+    pub fn start(source: &AudioSource) -> Option<Self> {
+        match source {
+            AudioSource::Off => None,
+            AudioSource::Monitor => Self::monitor(),
+            AudioSource::File(path) => Self::file(path),
+        }
+    }
+
+    /// Analyze the most recent samples.
+    pub fn spectrum(&self) -> AudioSpectrum {
+        match self.analyzer.lock() {
+            Ok(mut analyzer) => analyzer.spectrum(),
+            Err(_) => AudioSpectrum::default(),
+        }
+    }
+
+    fn monitor() -> Option<Self> {
+        let rate = MONITOR_RATE.to_string();
+        let channels = MONITOR_CHANNELS.to_string();
+        let commands: [(&str, Vec<&str>); 2] = [
+            (
+                "parec",
+                vec![
+                    "--device=@DEFAULT_MONITOR@",
+                    "--format=float32le",
+                    "--rate",
+                    rate.as_str(),
+                    "--channels",
+                    channels.as_str(),
+                    "--latency-msec=20",
+                    "--raw",
+                ],
+            ),
+            (
+                "pw-record",
+                vec![
+                    "-P",
+                    "{ stream.capture.sink = true }",
+                    "--format",
+                    "f32",
+                    "--rate",
+                    rate.as_str(),
+                    "--channels",
+                    channels.as_str(),
+                    "-",
+                ],
+            ),
+        ];
+
+        for (program, args) in commands {
+            let mut child = match Command::new(program)
+                .args(&args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+            {
+                Ok(child) => child,
+                // Not installed: try the next tool.
+                Err(e) => {
+                    log::debug!("audio: cannot spawn {}: {}", program, e);
+                    continue;
+                }
+            };
+            let Some(stdout) = child.stdout.take() else {
+                let _ = child.kill();
+                continue;
+            };
+
+            log::info!("audio: capturing desktop audio via {}", program);
+            let analyzer = Arc::new(Mutex::new(SpectrumAnalyzer::new(
+                MONITOR_RATE,
+                MONITOR_CHANNELS,
+            )));
+            let reader_analyzer = Arc::clone(&analyzer);
+            let name = program.to_string();
+            thread::spawn(move || {
+                read_pcm(stdout, &reader_analyzer, MONITOR_CHANNELS, &name)
+            });
+
+            return Some(Self {
+                analyzer,
+                child: Some(child),
+                stop: Arc::new(AtomicBool::new(false)),
+            });
+        }
+
+        log::warn!("audio: no capture tool found (parec, pw-record), spectrum disabled");
+        None
+    }
+
+    fn file(path: &Path) -> Option<Self> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                log::warn!("audio: cannot read '{}': {}", path.display(), e);
+                return None;
+            }
+        };
+        let Some((samples, channels, rate)) = decode(bytes) else {
+            log::warn!("audio: cannot decode '{}'", path.display());
+            return None;
+        };
+        if samples.is_empty() {
+            return None;
+        }
+
+        log::info!(
+            "audio: looping '{}' ({} Hz, {} channels)",
+            path.display(),
+            rate,
+            channels
+        );
+        let analyzer = Arc::new(Mutex::new(SpectrumAnalyzer::new(rate, channels)));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let feed_analyzer = Arc::clone(&analyzer);
+        let feed_stop = Arc::clone(&stop);
+        let chunk = (rate as f32 * FILE_CHUNK.as_secs_f32()) as usize * channels as usize;
+        thread::spawn(move || {
+            for piece in samples.chunks(chunk.max(1)).cycle() {
+                if feed_stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                if let Ok(mut analyzer) = feed_analyzer.lock() {
+                    analyzer.push(piece);
+                }
+                thread::sleep(FILE_CHUNK);
+            }
+        });
+
+        Some(Self {
+            analyzer,
+            child: None,
+            stop,
+        })
+    }
+}
+
+impl Drop for AudioCapture {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(child) = self.child.as_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Feed raw little-endian f32 PCM of `channels` interleaved channels from
+/// `reader` into the analyzer until EOF.
+fn read_pcm(
+    mut reader: impl Read,
+    analyzer: &Mutex<SpectrumAnalyzer>,
+    channels: u16,
+    name: &str,
+) {
+    // Only whole frames are pushed, so a split read cannot swap channels.
+    let frame = 4 * channels.max(1) as usize;
+    let mut buf = [0u8; 4096];
+    let mut pending: Vec<u8> = Vec::with_capacity(buf.len() + frame);
+    let mut samples: Vec<f32> = Vec::with_capacity(buf.len() / 4);
+
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => {
+                log::warn!("audio: {} read failed: {}", name, e);
+                break;
+            }
+        };
+        pending.extend_from_slice(&buf[..n]);
+
+        let whole = pending.len() / frame * frame;
+        samples.clear();
+        samples.extend(
+            pending[..whole]
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        );
+        pending.drain(..whole);
+
+        if let Ok(mut analyzer) = analyzer.lock() {
+            analyzer.push(&samples);
+        }
+    }
+    log::info!("audio: {} stopped", name);
+}
+
+/// Decode an audio file into interleaved f32 samples, channel count and
+/// sample rate.
+pub fn decode(bytes: Vec<u8>) -> Option<(Vec<f32>, u16, u32)> {
+    let decoder = rodio::Decoder::new(Cursor::new(bytes)).ok()?;
+    let channels = decoder.channels();
+    let rate = decoder.sample_rate();
+    Some((decoder.collect(), channels, rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal 16-bit PCM WAV.
+    fn wav(samples: &[i16], channels: u16, rate: u32) -> Vec<u8> {
+        let data_len = (samples.len() * 2) as u32;
+        let mut out = Vec::with_capacity(44 + data_len as usize);
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + data_len).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&channels.to_le_bytes());
+        out.extend_from_slice(&rate.to_le_bytes());
+        out.extend_from_slice(&(rate * channels as u32 * 2).to_le_bytes());
+        out.extend_from_slice(&(channels * 2).to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+        for s in samples {
+            out.extend_from_slice(&s.to_le_bytes());
+        }
+        out
+    }
+
+    /// Reader returning its chunks one `read` at a time.
+    struct Chunks(Vec<Vec<u8>>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let chunk = self.0.remove(0);
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn test_read_pcm_keeps_channels_across_split_reads() {
+        // Left silent, right a 440 Hz tone; reads split inside a frame.
+        let pcm: Vec<u8> = (0..4096)
+            .flat_map(|i| {
+                let t = i as f32 / MONITOR_RATE as f32;
+                [0.0f32, (2.0 * std::f32::consts::PI * 440.0 * t).sin()]
+            })
+            .flat_map(f32::to_le_bytes)
+            .collect();
+        let chunks = pcm.chunks(12).map(<[u8]>::to_vec).collect();
+        let analyzer = Mutex::new(SpectrumAnalyzer::new(MONITOR_RATE, 2));
+        read_pcm(Chunks(chunks), &analyzer, 2, "test");
+
+        let spectrum = analyzer.lock().unwrap().spectrum();
+        assert!(spectrum.left[1].iter().all(|&v| v == 0.0));
+        assert!(spectrum.right[1].iter().any(|&v| v > 0.9));
+    }
+
+    #[test]
+    fn test_wav_feeds_analyzer() {
+        let rate = 44_100;
+        let tone: Vec<i16> = (0..4096)
+            .flat_map(|i| {
+                let s = (2.0 * std::f32::consts::PI * 440.0 * i as f32 / rate as f32).sin();
+                let s = (s * i16::MAX as f32) as i16;
+                [s, s]
+            })
+            .collect();
+
+        let (samples, channels, decoded_rate) = decode(wav(&tone, 2, rate)).unwrap();
+        assert_eq!((channels, decoded_rate), (2, rate));
+        assert_eq!(samples.len(), tone.len());
+
+        let mut analyzer = SpectrumAnalyzer::new(decoded_rate, channels);
+        analyzer.push(&samples);
+        let spectrum = analyzer.spectrum();
+        assert_eq!(spectrum.left, spectrum.right);
+        assert!(spectrum.left[1].iter().any(|&v| v > 0.9));
+    }
+}
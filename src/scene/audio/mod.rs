@@ -0,0 +1,54 @@
+//! System audio capture and spectrum analysis.
+//!
+//! Audio-visualizer wallpapers read a per-frame frequency spectrum through
+//! the `g_AudioSpectrum{16,32,64}{Left,Right}` shader uniforms and
+//! `engine.registerAudioBuffers()` in SceneScript.  [`AudioCapture`]
+//! records what the desktop is playing (or a WAV file, for testing) on a
+//! background thread and [`AudioCapture::spectrum`] turns the most recent
+//! samples into an [`AudioSpectrum`].  Capture only runs while the loaded
+//! scene reads the spectrum.
+//!
+//! [`AudioPlayback`] plays the wallpaper's own sounds.
+
+mod analyzer;
+mod capture;
+mod playback;
+
+use serde::Serialize;
+
+use analyzer::SpectrumAnalyzer;
+pub use capture::{AudioCapture, AudioSource};
+pub use playback::AudioPlayback;
+
+/// Band counts Wallpaper Engine exposes, in the order stored in
+/// [`AudioSpectrum`].
+pub const BAND_RESOLUTIONS: [usize; 3] = [16, 32, 64];
+
+/// Per-channel spectrum at every supported resolution, values in [0, 1].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AudioSpectrum {
+    pub left: [Vec<f32>; 3],
+    pub right: [Vec<f32>; 3],
+}
+
+impl Default for AudioSpectrum {
+    fn default() -> Self {
+        let zeros = BAND_RESOLUTIONS.map(|n| vec![0.0; n]);
+        Self {
+            left: zeros.clone(),
+            right: zeros,
+        }
+    }
+}
+
+impl AudioSpectrum {
+    /// Shader uniform name and values for every channel and resolution.
+    pub fn uniforms(&self) -> impl Iterator<Item = (String, &[f32])> {
+        BAND_RESOLUTIONS.iter().enumerate().flat_map(move |(i, n)| {
+            [
+                (format!("g_AudioSpectrum{}Left", n), self.left[i].as_slice()),
+                (format!("g_AudioSpectrum{}Right", n), self.right[i].as_slice()),
+            ]
+        })
+    }
+}
@@ -0,0 +1,46 @@
+//! Playback of the wallpaper's own sounds.
+//!
+//! Scene sounds and the audio track of video wallpapers are mixed into one
+//! sub-mixer that plays through a [`Sink`], so they can be paused together
+//! (while the wallpaper is covered, say) without touching the output
+//! stream.
+
+use rodio::{OutputStream, Sink, Source, mixer::Mixer, source::Zero};
+
+pub struct AudioPlayback {
+    /// The default output device (kept alive).
+    #[allow(dead_code)]
+    stream: OutputStream,
+    mixer: Mixer,
+    sink: Sink,
+}
+
+impl AudioPlayback {
+    pub fn open() -> Self {
+        let stream = rodio::OutputStreamBuilder::open_default_stream().unwrap();
+        let config = stream.config();
+        let (mixer, source) = rodio::mixer::mixer(config.channel_count(), config.sample_rate());
+        // An empty mixer ends at once and would be dropped from the sink.
+        mixer.add(Zero::new(config.channel_count(), config.sample_rate()));
+        let sink = Sink::connect_new(stream.mixer());
+        sink.append(source);
+        Self {
+            stream,
+            mixer,
+            sink,
+        }
+    }
+
+    /// Start playing `source` alongside the others.
+    pub fn add(&self, source: impl Source + Send + 'static) {
+        self.mixer.add(source);
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        if paused {
+            self.sink.pause();
+        } else {
+            self.sink.play();
+        }
+    }
+}
@@ -3,8 +3,8 @@
 //!
 //! Usage:
 //!
-//! 1. Load a `.pkg` file normally via `Scene::new()` (populates buckets
-//!    from the package).
+//! 1. Read a `.pkg` file with `ScenePackage::read()` and turn it into a
+//!    `Scene` (populates buckets from the package).
 //! 2. Call `scene.set_assets_path()` to point to the Wallpaper Engine
 //!    `assets/` directory.
 //! 3. When code calls `scene.textures.get(key)`, `scene.jsons.get(key)`,
@@ -38,6 +38,11 @@ use std::{
 
 use pkg_parser::pkg_parser::{mdl_parser::MdlFile, tex_parser::Tex};
 
+use super::{
+    tex_bc::{self, BcTexture},
+    tex_frames::TexFrames,
+};
+
 /// Generic helper: check the in-memory cache, then lazy-load from disk.
 fn load_cached<T: Clone>(
     map: &RefCell<BTreeMap<String, T>>,
@@ -62,12 +67,31 @@ fn load_cached<T: Clone>(
 /// Lazily-loaded bucket of `.tex` textures.
 pub struct TextureBucket {
     pub(crate) map: RefCell<BTreeMap<String, Rc<Tex>>>,
+    /// Frame tables of animated textures, by the same key.
+    frames: RefCell<BTreeMap<String, Rc<TexFrames>>>,
+    /// Blocks of textures kept compressed, by the same key.  Their `Tex`
+    /// has no payload.
+    compressed: RefCell<BTreeMap<String, Rc<BcTexture>>>,
+    /// Keep block-compressed textures loaded later compressed too.
+    keep_bc: bool,
     assets_path: Option<PathBuf>,
 }
 
 impl TextureBucket {
-    pub fn new(map: BTreeMap<String, Rc<Tex>>, assets_path: Option<PathBuf>) -> Self {
-        Self { map: RefCell::new(map), assets_path }
+    pub fn new(
+        map: BTreeMap<String, Rc<Tex>>,
+        frames: BTreeMap<String, Rc<TexFrames>>,
+        compressed: BTreeMap<String, Rc<BcTexture>>,
+        keep_bc: bool,
+        assets_path: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            map: RefCell::new(map),
+            frames: RefCell::new(frames),
+            compressed: RefCell::new(compressed),
+            keep_bc,
+            assets_path,
+        }
     }
 
     pub fn set_assets_path(&mut self, path: Option<PathBuf>) {
@@ -76,12 +100,37 @@ impl TextureBucket {
 
     pub fn get(&self, key: &str) -> Option<Rc<Tex>> {
         load_cached(&self.map, &self.assets_path, key, |bytes| {
-            let mut tex = Tex::new(bytes)?;
-            tex.parse_to_rgba()?;
+            let (tex, bc) = match tex_bc::load_tex(bytes, self.keep_bc) {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    log::warn!("assets: cannot decode tex '{}': {}", key, e);
+                    return None;
+                }
+            };
+            if let Some(bc) = bc {
+                self.compressed.borrow_mut().insert(key.to_string(), Rc::new(bc));
+            }
             log::debug!("assets: loaded tex '{}' ({}x{})", key, tex.dimension[0], tex.dimension[1]);
+            if let Some(frames) = TexFrames::parse(bytes) {
+                self.frames.borrow_mut().insert(key.to_string(), Rc::new(frames));
+            }
             Some(Rc::new(tex))
         })
     }
+
+    /// Frame table of an animated texture.  Loads the texture first if
+    /// needed.
+    pub fn frames(&self, key: &str) -> Option<Rc<TexFrames>> {
+        self.get(key)?;
+        self.frames.borrow().get(key).cloned()
+    }
+
+    /// Compressed blocks of a texture kept compressed.  Loads the texture
+    /// first if needed.
+    pub fn compressed(&self, key: &str) -> Option<Rc<BcTexture>> {
+        self.get(key)?;
+        self.compressed.borrow().get(key).cloned()
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -2,5 +2,8 @@ pub mod assets_loader;
 pub mod model;
 pub mod object;
 pub mod object_loader;
+pub mod project;
 pub mod scene;
 pub mod scene_loader;
+pub mod tex_bc;
+pub mod tex_frames;
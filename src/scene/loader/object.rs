@@ -3,7 +3,10 @@ use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use super::scene::Vectors;
+use super::{
+    project::UserProperties,
+    scene::{BindUserProperty, Vectors},
+};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -28,7 +31,7 @@ pub struct Object {
     pub scale: Option<Vectors>,
     pub size: Option<Vectors>,
     pub solid: Option<bool>,
-    pub visible: Option<super::scene::BindUserProperty<bool>>,
+    pub visible: Option<BindUserProperty<bool>>,
     pub instanceoverride: Option<Instanceoverride>,
     pub particle: Option<String>,
     pub model: Option<Value>,
@@ -91,7 +94,17 @@ pub struct Effect {
     pub id: i64,
     pub name: String,
     pub passes: Vec<Pass>,
-    pub visible: Value,
+    pub visible: Option<BindUserProperty<bool>>,
+}
+
+impl Effect {
+    /// Visibility at load time, with user-property bindings resolved.
+    pub fn is_visible(&self, properties: &UserProperties) -> bool {
+        self.visible
+            .as_ref()
+            .and_then(|v| v.resolve(properties))
+            .unwrap_or(true)
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -102,7 +115,9 @@ pub struct Pass {
     #[serde(default)]
     pub textures: Vec<Option<String>>,
     pub combos: Option<BTreeMap<String, i64>>,
-    pub usertextures: Option<(Value, Value)>,
+    /// User-provided textures by unit, usually bound to a user property.
+    #[serde(default)]
+    pub usertextures: Vec<Value>,
 }
 
 
@@ -123,8 +138,10 @@ pub struct Instanceoverride {
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Instance {
+    #[serde(default)]
     pub combos: BTreeMap<String, Value>,
     pub id: i64,
+    #[serde(default)]
     pub textures: Vec<String>,
     #[serde(default)]
     pub usertextures: Vec<Value>,
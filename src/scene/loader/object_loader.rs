@@ -4,22 +4,47 @@ use glam::{Vec2, Vec3};
 use pkg_parser::pkg_parser::tex_parser::Tex;
 use serde_json::Value;
 
-use crate::scene::loader::{
-    model::Model,
-    scene::{Effect, Object, Vectors},
-    scene_loader::Scene,
+use crate::scene::{
+    loader::{
+        model::Model,
+        scene::{Effect, Instance, Object, Vectors},
+        scene_loader::Scene,
+        tex_bc::BcTexture,
+        tex_frames::TexFrames,
+    },
+    script::{LayerProperty, PropertyScript},
 };
 
 #[derive(Debug, Clone)]
 pub struct TextureObject {
+    pub id: i64,
+    pub name: String,
     pub texture: Rc<Tex>,
     pub origin: Vec3,
     pub angles: Vec3,
     pub size: Vec2,
     pub scale: Vec3,
+    /// Transform as authored, before parent propagation.  Scripts see
+    /// and modify these values.
+    pub local_origin: Vec3,
+    pub local_angles: Vec3,
+    pub local_scale: Vec3,
+    pub alpha: f32,
     pub parent: Option<i64>,
     pub effects: Vec<Effect>,
+    /// Per-instance texture overrides for the layer's effects.
+    pub instance: Option<Instance>,
     pub visible: bool,
+    /// Frame table when the texture is an animated sprite sheet.
+    pub frames: Option<Rc<TexFrames>>,
+    /// BC blocks when the texture is kept compressed for the GPU; the
+    /// `texture` payload is then empty.
+    pub compressed: Option<Rc<BcTexture>>,
+    /// The layer is drawn from the scene rendered beneath it instead of
+    /// its own image.
+    pub copy_background: bool,
+    /// SceneScripts bound to layer properties.
+    pub scripts: Vec<(LayerProperty, PropertyScript)>,
 }
 
 pub struct AudioObject {
@@ -131,7 +156,13 @@ impl ObjectMap {
                 continue;
             };
             let obj = Rc::into_inner(tex_obj).unwrap().into_inner();
-            if !obj.visible {
+            // Keep hidden layers whose visibility is scripted, they may
+            // become visible at runtime.
+            let scripted_visibility = obj
+                .scripts
+                .iter()
+                .any(|(prop, _)| *prop == LayerProperty::Visible);
+            if !obj.visible && !scripted_visibility {
                 continue;
             }
             texture_vec.push(obj);
@@ -170,8 +201,8 @@ impl ObjectMap {
             // Texture
             let visible = object
                 .visible
-                .clone()
-                .and_then(|v| v.value())
+                .as_ref()
+                .and_then(|v| v.resolve(&scene.user_properties))
                 .unwrap_or(true);
 
             let size = object
@@ -188,7 +219,8 @@ impl ObjectMap {
             let model_path = object.image.clone().unwrap_or_default();
 
             // Helper: build a 1×1 solid-colour fallback texture using the
-            // object's `color` / `alpha` properties.
+            // object's `color` property.  Its `alpha` applies when drawing,
+            // as for any layer.
             let make_solid = || -> Rc<Tex> {
                 let color_vec = object
                     .color
@@ -196,18 +228,12 @@ impl ObjectMap {
                     .and_then(|c| c.parse())
                     .unwrap_or(clear_color)
                     .max(Vec3::ZERO);
-                let alpha_val = object
-                    .alpha
-                    .as_ref()
-                    .and_then(|v| v.as_f64())
-                    .unwrap_or(1.0);
                 let r = (color_vec.x.clamp(0.0, 1.0) * 255.0) as u8;
                 let g = (color_vec.y.clamp(0.0, 1.0) * 255.0) as u8;
                 let b = (color_vec.z.clamp(0.0, 1.0) * 255.0) as u8;
-                let a = (alpha_val.clamp(0.0, 1.0) * 255.0) as u8;
                 log::debug!(
-                    "solidlayer fallback for '{}': 1x1 rgba({},{},{},{})",
-                    object.name, r, g, b, a
+                    "solidlayer fallback for '{}': 1x1 rgb({},{},{})",
+                    object.name, r, g, b
                 );
                 Rc::new(Tex {
                     texv: String::new(),
@@ -220,14 +246,14 @@ impl ObjectMap {
                     lz4: false,
                     decompressed_size: 4,
                     extension: "solid".into(),
-                    payload: vec![r, g, b, a],
+                    payload: vec![r, g, b, 255],
                 })
             };
 
             // Resolve the texture:
             //   model JSON → material JSON → texture reference (tex file)
             // Falls back to a solid-colour placeholder at every step.
-            let texture: Rc<Tex> = (|| -> Option<Rc<Tex>> {
+            let resolved = (|| -> Option<(Rc<Tex>, String)> {
                 let model_raw = scene.jsons.get(&model_path)?;
                 let model = serde_json::from_str::<Model>(&model_raw[..]).ok()?;
                 let material_raw = scene.jsons.get(&model.material)?;
@@ -240,7 +266,7 @@ impl ObjectMap {
                     .as_str()?;
                 let tex_key = format!("materials/{}.tex", tex_name);
                 match scene.textures.get(&tex_key) {
-                    Some(t) => Some(t),
+                    Some(t) => Some((t, tex_key)),
                     None => {
                         log::debug!(
                             "cannot get texture '{}' for material '{}'",
@@ -249,18 +275,43 @@ impl ObjectMap {
                         None
                     }
                 }
-            })()
-            .unwrap_or_else(make_solid);
+            })();
+            let (texture, frames, compressed) = match resolved {
+                Some((texture, key)) => (
+                    texture,
+                    scene.textures.frames(&key),
+                    scene.textures.compressed(&key),
+                ),
+                None => (make_solid(), None, None),
+            };
+
+            let alpha = match object.alpha.as_ref() {
+                Some(serde_json::Value::Object(obj)) => obj.get("value").and_then(|v| v.as_f64()),
+                Some(v) => v.as_f64(),
+                None => None,
+            }
+            .unwrap_or(1.0) as f32;
 
             return Some(ObjectType::Texture(TextureObject {
+                id: object.id,
+                name: object.name.clone(),
                 origin,
                 angles,
                 size,
                 scale,
+                local_origin: origin,
+                local_angles: angles,
+                local_scale: scale,
+                alpha,
                 parent: object.parent,
                 texture: Rc::clone(&texture),
                 effects: object.effects.clone(),
+                instance: object.instance.clone(),
                 visible,
+                frames,
+                compressed,
+                copy_background: object.copybackground == Some(true),
+                scripts: Self::layer_scripts(object),
             }));
         }
 
@@ -284,4 +335,28 @@ impl ObjectMap {
             parent: object.parent,
         }))
     }
+
+    /// Collect the SceneScripts attached to an object's layer properties.
+    fn layer_scripts(object: &Object) -> Vec<(LayerProperty, PropertyScript)> {
+        let mut scripts = Vec::new();
+
+        let vectors = [
+            (LayerProperty::Origin, &object.origin),
+            (LayerProperty::Scale, &object.scale),
+            (LayerProperty::Angles, &object.angles),
+        ];
+        for (prop, value) in vectors {
+            if let Some(script) = value.as_ref().and_then(|v| v.script()) {
+                scripts.push((prop, script));
+            }
+        }
+        if let Some(script) = object.visible.as_ref().and_then(|v| v.script()) {
+            scripts.push((LayerProperty::Visible, script));
+        }
+        if let Some(script) = object.alpha.as_ref().and_then(PropertyScript::from_value) {
+            scripts.push((LayerProperty::Alpha, script));
+        }
+
+        scripts
+    }
 }
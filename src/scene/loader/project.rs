@@ -0,0 +1,209 @@
+//! User properties declared in the wallpaper's `project.json`.
+//!
+//! Authors expose switches, sliders and combos under
+//! `general.properties`; scene values bind to them with
+//! `{"user": "name", "value": ...}`, or with
+//! `{"user": {"name": ..., "condition": ...}}` to compare a combo
+//! property against one of its options.
+//!
+//! `project.json` also names the wallpaper itself: `type` is `scene` or
+//! `video`, and `file` the video to play for video wallpapers.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::scene::video;
+
+/// Packed scene next to a scene project's `project.json`.
+const SCENE_PKG: &str = "scene.pkg";
+
+/// The file a wallpaper is rendered from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Wallpaper {
+    /// A packed scene (`.pkg`).
+    Scene(PathBuf),
+    /// A video played full-screen.
+    Video(PathBuf),
+}
+
+impl Wallpaper {
+    /// Resolve the `-p` argument: a `.pkg`, a video file, or a Workshop
+    /// item (its directory or its `project.json`).
+    pub fn locate(path: &Path) -> Option<Self> {
+        if path.is_file() {
+            if path.extension().is_some_and(|e| e == "pkg") {
+                return Some(Self::Scene(path.to_path_buf()));
+            }
+            if video::is_video_file(path) {
+                return Some(Self::Video(path.to_path_buf()));
+            }
+            if path.file_name().is_some_and(|n| n == "project.json") {
+                return Self::from_project(path.parent()?);
+            }
+            return None;
+        }
+        if path.is_dir() {
+            return Self::from_project(path);
+        }
+        None
+    }
+
+    fn from_project(dir: &Path) -> Option<Self> {
+        let raw = match std::fs::read_to_string(dir.join("project.json")) {
+            Ok(raw) => raw,
+            Err(e) => {
+                log::warn!("project.json: cannot read in '{}': {}", dir.display(), e);
+                return None;
+            }
+        };
+        let wallpaper = Self::parse(&raw, dir)?;
+        match &wallpaper {
+            Self::Scene(path) | Self::Video(path) if !path.is_file() => {
+                log::warn!("project.json: '{}' does not exist", path.display());
+                None
+            }
+            _ => Some(wallpaper),
+        }
+    }
+
+    /// Read `type` and `file` of a `project.json` in `dir`.
+    pub fn parse(raw: &str, dir: &Path) -> Option<Self> {
+        let project: Value = serde_json::from_str(raw).ok()?;
+        let kind = project["type"].as_str().unwrap_or("scene");
+        match kind.to_ascii_lowercase().as_str() {
+            "scene" => Some(Self::Scene(dir.join(SCENE_PKG))),
+            "video" => Some(Self::Video(dir.join(project["file"].as_str()?))),
+            _ => {
+                log::warn!("project.json: unsupported wallpaper type '{}'", kind);
+                None
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Scene(path) | Self::Video(path) => path,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(transparent)]
+pub struct UserProperties {
+    values: BTreeMap<String, Value>,
+}
+
+impl UserProperties {
+    /// Read `project.json` next to the `.pkg`, falling back to a copy
+    /// packed inside it.  Missing or malformed files yield no properties.
+    pub fn load(pkg_path: &Path, packed: Option<&String>) -> Self {
+        let beside = pkg_path
+            .parent()
+            .map(|dir| dir.join("project.json"))
+            .and_then(|p| std::fs::read_to_string(p).ok());
+
+        match beside.as_ref().or(packed) {
+            Some(raw) => Self::parse(raw).unwrap_or_else(|| {
+                log::warn!("project.json: malformed, user properties ignored");
+                Self::default()
+            }),
+            None => Self::default(),
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        let project: Value = serde_json::from_str(raw).ok()?;
+        let values = match project["general"]["properties"].as_object() {
+            Some(props) => props
+                .iter()
+                .filter_map(|(name, prop)| Some((name.clone(), prop.get("value")?.clone())))
+                .collect(),
+            None => BTreeMap::new(),
+        };
+        Some(Self { values })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    /// Current value of a `"user"` binding.  A `{name, condition}`
+    /// binding yields whether the property equals the condition.
+    pub fn resolve(&self, binding: &Value) -> Option<Value> {
+        match binding {
+            Value::String(name) => self.get(name).cloned(),
+            Value::Object(obj) => {
+                let value = self.get(obj.get("name")?.as_str()?)?;
+                match obj.get("condition") {
+                    Some(condition) => Some(Value::Bool(as_text(value) == as_text(condition))),
+                    None => Some(value.clone()),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Combo values and conditions are compared as text, since either side
+/// may be stored as a number or a string.
+fn as_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const PROJECT: &str = r#"{
+        "general": {
+            "properties": {
+                "showclock": { "type": "bool", "value": false },
+                "style": { "type": "combo", "value": 2 },
+                "schemecolor": { "type": "color", "value": "0.1 0.2 0.3" }
+            }
+        }
+    }"#;
+
+    #[test]
+    fn test_resolve_user_bindings() {
+        let props = UserProperties::parse(PROJECT).unwrap();
+        assert_eq!(props.resolve(&json!("showclock")), Some(json!(false)));
+        assert_eq!(
+            props.resolve(&json!("schemecolor")),
+            Some(json!("0.1 0.2 0.3"))
+        );
+        assert_eq!(
+            props.resolve(&json!({ "name": "style", "condition": "2" })),
+            Some(json!(true))
+        );
+        assert_eq!(
+            props.resolve(&json!({ "name": "style", "condition": 1 })),
+            Some(json!(false))
+        );
+        assert_eq!(props.resolve(&json!("missing")), None);
+    }
+
+    #[test]
+    fn test_parse_wallpaper_type() {
+        let dir = Path::new("/workshop/123");
+        assert_eq!(
+            Wallpaper::parse(r#"{"type": "Video", "file": "clip.mp4"}"#, dir),
+            Some(Wallpaper::Video(dir.join("clip.mp4")))
+        );
+        assert_eq!(
+            Wallpaper::parse(r#"{"type": "scene", "file": "scene.json"}"#, dir),
+            Some(Wallpaper::Scene(dir.join("scene.pkg")))
+        );
+        assert_eq!(Wallpaper::parse(r#"{"type": "web"}"#, dir), None);
+        assert_eq!(Wallpaper::parse(r#"{"type": "video"}"#, dir), None);
+    }
+}
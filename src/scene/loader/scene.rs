@@ -3,6 +3,10 @@ use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::scene::script::PropertyScript;
+
+use super::project::UserProperties;
+
 pub use super::object::*;
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -119,7 +123,20 @@ impl Vectors {
                     _ => None,
                 }
             }
-            Vectors::Object(_) => None,
+            // Scripted values: {"script": "...", "value": "x y z"}
+            Vectors::Object(val) => match val.get("value")? {
+                Value::String(s) => Vectors::Vectors(s.clone()).parse(),
+                Value::Number(n) => Vectors::Scaler(n.as_f64()?).parse(),
+                _ => None,
+            },
+        }
+    }
+
+    /// The SceneScript attached to this value, if any.
+    pub fn script(&self) -> Option<PropertyScript> {
+        match self {
+            Vectors::Object(val) => PropertyScript::from_value(val),
+            _ => None,
         }
     }
 }
@@ -141,3 +158,27 @@ impl<T: DeserializeOwned> BindUserProperty<T> {
         }
     }
 }
+
+impl<T: DeserializeOwned + Clone> BindUserProperty<T> {
+    /// Like [`value`](Self::value), but a `"user"` binding takes the
+    /// current value of the bound user property.
+    pub fn resolve(&self, properties: &UserProperties) -> Option<T> {
+        if let BindUserProperty::Object(obj) = self
+            && let Some(bound) = obj.get("user").and_then(|u| properties.resolve(u))
+            && let Ok(val) = serde_json::from_value::<T>(bound)
+        {
+            return Some(val);
+        }
+        self.clone().value()
+    }
+}
+
+impl<T> BindUserProperty<T> {
+    /// The SceneScript attached to this value, if any.
+    pub fn script(&self) -> Option<PropertyScript> {
+        match self {
+            BindUserProperty::Value(_) => None,
+            BindUserProperty::Object(obj) => PropertyScript::from_value(&Value::Object(obj.clone())),
+        }
+    }
+}
@@ -2,94 +2,212 @@ use indicatif::ProgressBar;
 use pkg_parser::pkg_parser::{parser::Pkg, tex_parser::Tex};
 use std::{
     collections::BTreeMap,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     rc::Rc,
-    sync::{Arc, Mutex},
-    thread::{self, JoinHandle},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
 };
 
-use super::assets_loader::{JsonBucket, MdlBucket, MiscBucket, TextureBucket};
+use super::{
+    assets_loader::{JsonBucket, MdlBucket, MiscBucket, TextureBucket},
+    project::UserProperties,
+    scene::Root,
+    tex_bc::{self, BcTexture, TexError},
+    tex_frames::TexFrames,
+};
 
 pub struct Scene {
-    pub root: crate::scene::loader::scene::Root,
+    pub root: Root,
     pub textures: TextureBucket,
     pub mdls: MdlBucket,
     pub jsons: JsonBucket,
     pub misc: MiscBucket,
+    /// Values of the user properties declared in `project.json`.
+    pub user_properties: UserProperties,
 }
 
-impl Scene {
-    pub fn new(path: String) -> Self {
-        let path = Path::new(&path);
+/// The decoded contents of a `.pkg`.  Unlike [`Scene`] it is `Send`, so
+/// a package can be read on a worker thread and handed to the renderer.
+pub struct ScenePackage {
+    root: Root,
+    texs: BTreeMap<String, Tex>,
+    frames: BTreeMap<String, TexFrames>,
+    compressed: BTreeMap<String, BcTexture>,
+    jsons: BTreeMap<String, String>,
+    misc: BTreeMap<String, Vec<u8>>,
+    user_properties: UserProperties,
+    keep_bc: bool,
+}
+
+/// What [`ScenePackage::read`] reports while it runs.
+pub enum ReadEvent<'a> {
+    /// `scene.json` is parsed; the textures are still being decoded.
+    Parsed(&'a Root),
+    /// `done` of the package's `total` files are read.
+    Progress { done: usize, total: usize },
+    /// A texture could not be decoded and is left out of the scene.
+    Failed(&'a str, TexError),
+}
+
+impl ScenePackage {
+    /// Read and decode a `.pkg`.  With `keep_bc`, block-compressed
+    /// textures are kept compressed for the GPU instead of being decoded
+    /// to RGBA; RGBA textures with stored mips always keep their levels.
+    /// Textures are decoded by one worker per CPU, while `report`
+    /// hears about progress on the calling thread.
+    ///
+    /// `None` when `scene.json` is missing or unsupported, or when `cancel`
+    /// is set before the package is read; workers stop after the texture
+    /// they are decoding.
+    pub fn read(
+        path: &Path,
+        keep_bc: bool,
+        cancel: &AtomicBool,
+        mut report: impl FnMut(ReadEvent),
+    ) -> Option<Self> {
         let pkg = Pkg::new(path);
+        let total = pkg.files.len();
+        let pb = ProgressBar::new(total as u64);
 
-        let texs: Arc<Mutex<BTreeMap<String, Tex>>> = Arc::new(Mutex::new(BTreeMap::new()));
+        let mut jobs: Vec<(String, Vec<u8>)> = Vec::new();
         let mut jsons: BTreeMap<String, String> = BTreeMap::new();
         let mut misc: BTreeMap<String, Vec<u8>> = BTreeMap::new();
-
-        let mut handles: Vec<JoinHandle<()>> = Vec::new();
-        let pb = ProgressBar::new(pkg.files.len() as u64);
-
         for (key, val) in pkg.files.into_iter() {
             let file_path = Path::new(&key);
-            match file_path.extension().unwrap().to_str().unwrap() {
-                "tex" => {
-                    let key_clone = key.clone();
-                    let texs = Arc::clone(&texs);
-
-                    let handle = thread::spawn(move || {
-                        let mut tex = Tex::new(&val).unwrap();
-
-                        match tex.parse_to_rgba() {
-                            Some(_) => {}
-                            None => return,
-                        };
-
-                        texs.lock().unwrap().insert(key_clone, tex);
-                    });
-
-                    log::debug!("pkg: enqueued tex: {}", key);
-                    handles.push(handle);
-                }
-
-                "json" => {
-                    pb.inc(1);
+            match file_path.extension().and_then(|e| e.to_str()) {
+                Some("tex") => jobs.push((key, val)),
+                Some("json") => {
                     log::debug!("pkg: loaded json: {}", key);
                     jsons.insert(key, String::from_utf8_lossy(&val).to_string());
                 }
                 _ => {
-                    pb.inc(1);
                     log::debug!("pkg: loaded misc: {}", key);
                     misc.insert(key, val);
                 }
             }
         }
-
-        for handle in handles {
-            handle.join().unwrap();
-            pb.inc(1);
-        }
-
+        let mut done = total - jobs.len();
+        pb.inc(done as u64);
+        report(ReadEvent::Progress { done, total });
+
+        let Some(scene_string) = jsons.get("scene.json") else {
+            log::error!("pkg: no scene.json in '{}'", path.display());
+            return None;
+        };
+        let root: Root = match serde_json::from_str(scene_string) {
+            Ok(root) => root,
+            Err(e) => {
+                log::error!("pkg: unsupported scene.json: {}", e);
+                return None;
+            }
+        };
+        report(ReadEvent::Parsed(&root));
+        let user_properties = UserProperties::load(path, jsons.get("project.json"));
+
+        let mut texs = BTreeMap::new();
+        let mut frames = BTreeMap::new();
+        let mut compressed = BTreeMap::new();
+        let mut failed = 0;
+        let workers = thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .min(jobs.len());
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            let (sender, results) = mpsc::channel();
+            for _ in 0..workers {
+                let (jobs, next, sender) = (&jobs, &next, sender.clone());
+                scope.spawn(move || {
+                    while !cancel.load(Ordering::Relaxed) {
+                        let Some((key, val)) = jobs.get(next.fetch_add(1, Ordering::Relaxed))
+                        else {
+                            break;
+                        };
+                        let decoded = tex_bc::load_tex(val, keep_bc)
+                            .map(|(tex, bc)| (tex, bc, TexFrames::parse(val)));
+                        if sender.send((key.as_str(), decoded)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            for (key, decoded) in results {
+                match decoded {
+                    Ok((tex, bc, table)) => {
+                        if let Some(bc) = bc {
+                            compressed.insert(key.to_string(), bc);
+                        }
+                        if let Some(table) = table {
+                            log::debug!("pkg: {} has {} frames", key, table.frames.len());
+                            frames.insert(key.to_string(), table);
+                        }
+                        texs.insert(key.to_string(), tex);
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        report(ReadEvent::Failed(key, e));
+                    }
+                }
+                done += 1;
+                pb.inc(1);
+                report(ReadEvent::Progress { done, total });
+            }
+        });
         pb.finish_and_clear();
 
-        let scene_string = jsons.get("scene.json").unwrap();
-        let root: crate::scene::loader::scene::Root =
-            serde_json::from_str(scene_string).expect("Unsupported scene.json");
-        let mut texs_locked = texs.lock().unwrap();
-        let texs = std::mem::take(&mut *texs_locked)
-            .into_iter()
-            .map(|(k, v)| (k, Rc::new(v)))
-            .collect::<BTreeMap<String, Rc<Tex>>>();
+        if cancel.load(Ordering::Relaxed) {
+            log::info!("pkg: reading '{}' cancelled", path.display());
+            return None;
+        }
+        log::debug!(
+            "pkg: {} textures decoded by {} workers, {} failed",
+            jobs.len(),
+            workers,
+            failed
+        );
+
+        Some(Self {
+            root,
+            texs,
+            frames,
+            compressed,
+            jsons,
+            misc,
+            user_properties,
+            keep_bc,
+        })
+    }
+}
 
+impl From<ScenePackage> for Scene {
+    fn from(package: ScenePackage) -> Self {
         Self {
-            root,
-            textures: TextureBucket::new(texs, None),
+            root: package.root,
+            textures: TextureBucket::new(
+                shared(package.texs),
+                shared(package.frames),
+                shared(package.compressed),
+                package.keep_bc,
+                None,
+            ),
             mdls: MdlBucket::new(BTreeMap::new(), None),
-            jsons: JsonBucket::new(jsons, None),
-            misc: MiscBucket::new(misc, None),
+            jsons: JsonBucket::new(package.jsons, None),
+            misc: MiscBucket::new(package.misc, None),
+            user_properties: package.user_properties,
         }
     }
+}
+
+fn shared<T>(map: BTreeMap<String, T>) -> BTreeMap<String, Rc<T>> {
+    map.into_iter().map(|(k, v)| (k, Rc::new(v))).collect()
+}
 
+impl Scene {
     /// Set the Wallpaper Engine assets directory for lazy-loading fallback.
     ///
     /// When a requested asset is not found in the in-memory buckets
@@ -0,0 +1,369 @@
+//! Block-compressed `.tex` textures kept compressed for the GPU.
+//!
+//! [`Tex::parse_to_rgba`] decodes DXT blocks to RGBA on the CPU and keeps
+//! only the top level.  When the GPU samples BC formats natively the blocks
+//! are uploaded as they are, so the whole stored mip chain is read here from
+//! the raw file (LZ4-compressed levels included) and the RGBA decode is
+//! skipped.  Raw RGBA textures with a stored mip chain are kept the same
+//! way, so their authored levels are used instead of generated ones.
+//!
+//! [`Tex::parse_to_rgba`]: pkg_parser::pkg_parser::tex_parser::Tex::parse_to_rgba
+
+use std::{
+    fmt,
+    io::{Cursor, Read},
+};
+
+use pkg_parser::pkg_parser::tex_parser::Tex;
+
+use super::tex_frames::{read_magic, read_u32};
+
+/// Block encodings, by their `TEXI` format id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BcFormat {
+    /// Raw RGBA8888, format 0: one-pixel blocks.
+    Rgba8,
+    /// DXT1, format 7.
+    Bc1,
+    /// DXT3, format 6.
+    Bc2,
+    /// DXT5, format 4.
+    Bc3,
+}
+
+impl BcFormat {
+    fn from_id(id: u32) -> Option<Self> {
+        match id {
+            0 => Some(Self::Rgba8),
+            4 => Some(Self::Bc3),
+            6 => Some(Self::Bc2),
+            7 => Some(Self::Bc1),
+            _ => None,
+        }
+    }
+
+    /// Bytes per block.
+    pub fn block_bytes(self) -> usize {
+        match self {
+            Self::Rgba8 => 4,
+            Self::Bc1 => 8,
+            Self::Bc2 | Self::Bc3 => 16,
+        }
+    }
+
+    /// Width and height of a block, in pixels.
+    pub fn block_size(self) -> u32 {
+        match self {
+            Self::Rgba8 => 1,
+            Self::Bc1 | Self::Bc2 | Self::Bc3 => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BcLevel {
+    pub size: [u32; 2],
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BcTexture {
+    pub format: BcFormat,
+    /// Mip levels of the first image, largest first.
+    pub levels: Vec<BcLevel>,
+}
+
+impl BcTexture {
+    /// Read the stored mip chain of a `.tex`.  `None` for textures that
+    /// are neither block-compressed nor raw RGBA (format 0 also holds
+    /// embedded PNG and JPG files), and for top levels that are not a
+    /// whole number of blocks (which GPUs reject).
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let mut r = Cursor::new(bytes);
+        if !read_magic(&mut r)?.starts_with("TEXV") || !read_magic(&mut r)?.starts_with("TEXI") {
+            return None;
+        }
+        let format = BcFormat::from_id(read_u32(&mut r)?)?;
+        // flags, texture size, image size, unknown
+        for _ in 0..6 {
+            read_u32(&mut r)?;
+        }
+
+        let version = match read_magic(&mut r)?.as_str() {
+            "TEXB0001" => 1,
+            "TEXB0002" => 2,
+            "TEXB0003" => 3,
+            "TEXB0004" => 4,
+            _ => return None,
+        };
+        let _image_count = read_u32(&mut r)?;
+        // FreeImage format of embedded files; -1 for raw pixels.
+        if version >= 3 && read_u32(&mut r)? != u32::MAX && format == BcFormat::Rgba8 {
+            return None;
+        }
+        if version == 4 && read_u32(&mut r)? == 1 {
+            return None;
+        }
+
+        let mipmap_count = read_u32(&mut r)?;
+        let mut levels = Vec::new();
+        for _ in 0..mipmap_count {
+            let size = [read_u32(&mut r)?, read_u32(&mut r)?];
+            let (lz4, decompressed) = if version >= 2 {
+                (read_u32(&mut r)? == 1, read_u32(&mut r)? as usize)
+            } else {
+                (false, 0)
+            };
+            let len = read_u32(&mut r)? as usize;
+            let mut data = vec![0u8; len];
+            r.read_exact(&mut data).ok()?;
+            let data = if lz4 { lz4_block(&data, decompressed)? } else { data };
+
+            if data.len() < level_bytes(format, size) {
+                log::debug!("tex: short BC level {:?}, mip chain cut", size);
+                break;
+            }
+            levels.push(BcLevel { size, data });
+        }
+
+        let top = levels.first()?.size;
+        let block = format.block_size();
+        if top[0] % block != 0 || top[1] % block != 0 {
+            return None;
+        }
+        Some(Self { format, levels })
+    }
+}
+
+/// Why a `.tex` could not be loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TexError {
+    /// Not a `.tex`, or its headers are cut short.
+    BadHeader,
+    /// A `TEXI` format id (or `TEXB` version) that cannot be decoded.
+    UnsupportedFormat(u32),
+    /// The first image is LZ4-compressed and does not decompress.
+    Lz4,
+}
+
+impl fmt::Display for TexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadHeader => write!(f, "bad header"),
+            Self::UnsupportedFormat(id) => write!(f, "unsupported format {}", id),
+            Self::Lz4 => write!(f, "LZ4 decompression failed"),
+        }
+    }
+}
+
+impl std::error::Error for TexError {}
+
+/// Parse a `.tex`.  With `keep_bc`, block-compressed textures keep their
+/// blocks, and raw RGBA textures with stored mips always keep their levels
+/// (returned alongside a `Tex` whose payload is dropped); everything else
+/// is decoded to RGBA.
+pub fn load_tex(bytes: &[u8], keep_bc: bool) -> Result<(Tex, Option<BcTexture>), TexError> {
+    let mut tex = Tex::new(bytes).ok_or(TexError::BadHeader)?;
+    let kept = BcTexture::parse(bytes).filter(|bc| match bc.format {
+        BcFormat::Rgba8 => bc.levels.len() > 1,
+        BcFormat::Bc1 | BcFormat::Bc2 | BcFormat::Bc3 => keep_bc,
+    });
+    if let Some(bc) = kept {
+        tex.payload = Vec::new();
+        return Ok((tex, Some(bc)));
+    }
+    tex.parse_to_rgba().ok_or_else(|| diagnose(bytes))?;
+    Ok((tex, None))
+}
+
+/// Why [`Tex::parse_to_rgba`] rejected `bytes`, found by walking the
+/// headers up to the first image.
+fn diagnose(bytes: &[u8]) -> TexError {
+    let walk = |r: &mut Cursor<&[u8]>| -> Option<TexError> {
+        if !read_magic(r)?.starts_with("TEXV") || !read_magic(r)?.starts_with("TEXI") {
+            return None;
+        }
+        let format = read_u32(r)?;
+        for _ in 0..6 {
+            read_u32(r)?;
+        }
+        let version = match read_magic(r)?.as_str() {
+            "TEXB0001" => 1,
+            "TEXB0002" => 2,
+            "TEXB0003" => 3,
+            "TEXB0004" => 4,
+            _ => return Some(TexError::UnsupportedFormat(format)),
+        };
+        let _image_count = read_u32(r)?;
+        if version >= 3 {
+            let _image_format = read_u32(r)?;
+        }
+        if version == 4 {
+            let _is_video = read_u32(r)?;
+        }
+        let _mipmap_count = read_u32(r)?;
+        let _size = [read_u32(r)?, read_u32(r)?];
+        let (lz4, decompressed) = if version >= 2 {
+            (read_u32(r)? == 1, read_u32(r)? as usize)
+        } else {
+            (false, 0)
+        };
+        let mut data = vec![0u8; read_u32(r)? as usize];
+        r.read_exact(&mut data).ok()?;
+        if lz4 && lz4_block(&data, decompressed).is_none() {
+            return Some(TexError::Lz4);
+        }
+        Some(TexError::UnsupportedFormat(format))
+    };
+    walk(&mut Cursor::new(bytes)).unwrap_or(TexError::BadHeader)
+}
+
+fn level_bytes(format: BcFormat, size: [u32; 2]) -> usize {
+    let block = format.block_size();
+    let blocks = size[0].div_ceil(block) as usize * size[1].div_ceil(block) as usize;
+    blocks * format.block_bytes()
+}
+
+/// Decompress an LZ4 block of known decompressed length.
+fn lz4_block(src: &[u8], len: usize) -> Option<Vec<u8>> {
+    fn extend_len(src: &[u8], i: &mut usize, mut n: usize) -> Option<usize> {
+        if n == 15 {
+            loop {
+                let b = *src.get(*i)?;
+                *i += 1;
+                n += b as usize;
+                if b != 255 {
+                    break;
+                }
+            }
+        }
+        Some(n)
+    }
+
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < src.len() {
+        let token = src[i];
+        i += 1;
+
+        let literals = extend_len(src, &mut i, (token >> 4) as usize)?;
+        out.extend_from_slice(src.get(i..i + literals)?);
+        i += literals;
+        // The last sequence has literals only.
+        if i >= src.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes([*src.get(i)?, *src.get(i + 1)?]) as usize;
+        i += 2;
+        if offset == 0 || offset > out.len() {
+            return None;
+        }
+        let matched = extend_len(src, &mut i, (token & 0x0F) as usize)? + 4;
+        let start = out.len() - offset;
+        // Matches may overlap their own output.
+        for k in 0..matched {
+            out.push(out[start + k]);
+        }
+        if out.len() > len {
+            return None;
+        }
+    }
+    (out.len() == len).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lz4_block() {
+        // "abc", then a 9-byte match 3 back, then the literal "!".
+        let src = [0x35, b'a', b'b', b'c', 3, 0, 0x10, b'!'];
+        assert_eq!(lz4_block(&src, 13).as_deref(), Some(&b"abcabcabcabc!"[..]));
+        assert_eq!(lz4_block(&src, 12), None);
+        // Offset past the start of the output.
+        assert_eq!(lz4_block(&[0x10, b'a', 2, 0], 5), None);
+    }
+
+    #[test]
+    fn test_parse_bc1_chain() {
+        let u = |out: &mut Vec<u8>, v: u32| out.extend_from_slice(&v.to_le_bytes());
+        let mut tex = b"TEXV0005\0TEXI0001\0".to_vec();
+        for v in [7, 0, 8, 8, 8, 8, 0] {
+            u(&mut tex, v);
+        }
+        tex.extend_from_slice(b"TEXB0003\0");
+        u(&mut tex, 1); // images
+        u(&mut tex, 0); // image format
+        u(&mut tex, 2); // mipmaps
+        for (size, blocks) in [(8, 4), (4, 1)] {
+            for v in [size, size, 0, 0, blocks * 8] {
+                u(&mut tex, v);
+            }
+            tex.extend(std::iter::repeat_n(0xAB, blocks as usize * 8));
+        }
+
+        let bc = BcTexture::parse(&tex).unwrap();
+        assert_eq!(bc.format, BcFormat::Bc1);
+        assert_eq!(bc.levels.len(), 2);
+        assert_eq!(bc.levels[1].size, [4, 4]);
+        assert_eq!(bc.levels[1].data.len(), 8);
+
+        // RG88 is not block-compressed.
+        tex[18..22].copy_from_slice(&8u32.to_le_bytes());
+        assert_eq!(BcTexture::parse(&tex), None);
+    }
+
+    #[test]
+    fn test_parse_rgba_chain() {
+        let u = |out: &mut Vec<u8>, v: u32| out.extend_from_slice(&v.to_le_bytes());
+        let tex = |image_format: u32| {
+            let mut tex = b"TEXV0005\0TEXI0001\0".to_vec();
+            for v in [0, 0, 2, 2, 2, 2, 0] {
+                u(&mut tex, v);
+            }
+            tex.extend_from_slice(b"TEXB0003\0");
+            for v in [1, image_format, 2] {
+                u(&mut tex, v);
+            }
+            for size in [2, 1] {
+                let len = size * size * 4;
+                for v in [size, size, 0, 0, len] {
+                    u(&mut tex, v);
+                }
+                tex.extend(std::iter::repeat_n(size as u8, len as usize));
+            }
+            tex
+        };
+
+        let rgba = BcTexture::parse(&tex(u32::MAX)).unwrap();
+        assert_eq!(rgba.format, BcFormat::Rgba8);
+        assert_eq!(rgba.levels[1].size, [1, 1]);
+        assert_eq!(rgba.levels[1].data, [1; 4]);
+        // An embedded PNG (FreeImage format 13) is not raw pixels.
+        assert_eq!(BcTexture::parse(&tex(13)), None);
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let u = |out: &mut Vec<u8>, v: u32| out.extend_from_slice(&v.to_le_bytes());
+        let tex = |lz4: u32| {
+            let mut tex = b"TEXV0005\0TEXI0001\0".to_vec();
+            for v in [99, 0, 4, 4, 4, 4, 0] {
+                u(&mut tex, v);
+            }
+            tex.extend_from_slice(b"TEXB0003\0");
+            for v in [1, 0, 1, 4, 4, lz4, 64, 2] {
+                u(&mut tex, v);
+            }
+            // An LZ4 match before any output.
+            tex.extend_from_slice(&[0x00, 0x00]);
+            tex
+        };
+        assert_eq!(diagnose(&tex(0)), TexError::UnsupportedFormat(99));
+        assert_eq!(diagnose(&tex(1)), TexError::Lz4);
+        assert_eq!(diagnose(&tex(0)[..30]), TexError::BadHeader);
+        assert_eq!(diagnose(b"PNG"), TexError::BadHeader);
+    }
+}
@@ -0,0 +1,326 @@
+//! Frame table of animated `.tex` files.
+//!
+//! Sprite sheets and GIF-sourced textures set the `IsGif` flag in the
+//! `TEXI` header and append a `TEXS000N` block after the image data.  The
+//! block lists, per frame, the image it is cut from, how long it is shown
+//! and its rectangle on the sheet.  [`Tex`] does not expose that block, so
+//! it is read here from the raw file.  Only the first image is decoded, so
+//! frames cut from the others are dropped.
+//!
+//! [`Tex`]: pkg_parser::pkg_parser::tex_parser::Tex
+
+use std::io::{Cursor, Read};
+
+/// `TEXI` flag marking an animated texture.
+const FLAG_IS_GIF: u32 = 4;
+/// Magic strings are NUL-terminated and at most this long.
+const MAGIC_MAX: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TexFrame {
+    /// Index of the image the frame is cut from.
+    pub image: u32,
+    /// Seconds the frame stays on screen.
+    pub duration: f32,
+    /// `[x, y, width, height]` on the sheet, in pixels.
+    pub rect: [f32; 4],
+    /// `[width_x, width_y, height_x, height_y]`: the frame's edges on the
+    /// sheet, in pixels.  Rotated frames have them off the axes.
+    pub axes: [f32; 4],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TexFrames {
+    /// Size of the sheet the frame rectangles refer to.
+    pub sheet: [u32; 2],
+    pub frames: Vec<TexFrame>,
+    /// Sum of all frame durations.
+    pub duration: f32,
+}
+
+impl TexFrames {
+    /// Read the frame table of a `.tex` file.  Returns `None` for still
+    /// textures and for files that cannot be walked (video payloads).
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let mut r = Cursor::new(bytes);
+
+        if !read_magic(&mut r)?.starts_with("TEXV") || !read_magic(&mut r)?.starts_with("TEXI") {
+            return None;
+        }
+        let _format = read_u32(&mut r)?;
+        let flags = read_u32(&mut r)?;
+        let sheet = [read_u32(&mut r)?, read_u32(&mut r)?];
+        let _image_size = [read_u32(&mut r)?, read_u32(&mut r)?];
+        let _unknown = read_u32(&mut r)?;
+        if flags & FLAG_IS_GIF == 0 {
+            return None;
+        }
+
+        skip_images(&mut r)?;
+
+        let magic = read_magic(&mut r)?;
+        let version = match magic.as_str() {
+            "TEXS0001" => 1,
+            "TEXS0002" => 2,
+            "TEXS0003" => 3,
+            other => {
+                log::debug!("tex: unknown frame table '{}'", other);
+                return None;
+            }
+        };
+        let count = read_u32(&mut r)?;
+        if version == 3 {
+            // Size of the source GIF; the rectangles refer to the sheet.
+            let _gif = [read_u32(&mut r)?, read_u32(&mut r)?];
+        }
+
+        let mut frames = Vec::with_capacity(count.min(4096) as usize);
+        let mut other_images = 0;
+        for _ in 0..count {
+            let image = read_u32(&mut r)?;
+            let duration = read_f32(&mut r)?;
+            // x, y, width, width_y, height_x, height
+            let mut v = [0.0f32; 6];
+            for value in v.iter_mut() {
+                *value = if version == 1 {
+                    read_u32(&mut r)? as i32 as f32
+                } else {
+                    read_f32(&mut r)?
+                };
+            }
+            if image != 0 {
+                other_images += 1;
+                continue;
+            }
+            // Rotated frames store their extent in width_y / height_x.
+            let width = if v[2] != 0.0 { v[2] } else { v[4] };
+            let height = if v[5] != 0.0 { v[5] } else { v[3] };
+            frames.push(TexFrame {
+                image,
+                duration,
+                rect: [v[0], v[1], width, height],
+                axes: [v[2], v[3], v[4], v[5]],
+            });
+        }
+        if other_images > 0 {
+            log::warn!(
+                "tex: {} of {} frames are on images other than the first, dropped",
+                other_images,
+                count
+            );
+        }
+        if frames.is_empty() {
+            return None;
+        }
+
+        let duration = frames.iter().map(|f| f.duration.max(0.0)).sum();
+        Some(Self {
+            sheet,
+            frames,
+            duration,
+        })
+    }
+
+    /// Index of the frame shown `seconds` into the loop.
+    pub fn frame_index(&self, seconds: f32) -> usize {
+        if self.duration <= 0.0 {
+            return 0;
+        }
+        let mut t = seconds.rem_euclid(self.duration);
+        for (i, frame) in self.frames.iter().enumerate() {
+            if t < frame.duration {
+                return i;
+            }
+            t -= frame.duration.max(0.0);
+        }
+        self.frames.len() - 1
+    }
+
+    /// Sheet UVs of a frame's top-left, top-right, bottom-right and
+    /// bottom-left corners.  Rotated frames follow their axes.
+    pub fn uv_corners(&self, frame: &TexFrame) -> [[f32; 2]; 4] {
+        let ([width_x, width_y, height_x, height_y], [x, y]) = self.texture_transform(frame);
+        [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]
+            .map(|[u, v]| [x + u * width_x + v * height_x, y + u * width_y + v * height_y])
+    }
+
+    /// Texture coordinate transform mapping the frame's `[0, 1]` UVs onto
+    /// the sheet: a column-major mat2 and an offset, as in
+    /// `g_Texture0Rotation` and `g_Texture0Translation`.
+    pub fn texture_transform(&self, frame: &TexFrame) -> ([f32; 4], [f32; 2]) {
+        let (w, h) = (self.sheet[0].max(1) as f32, self.sheet[1].max(1) as f32);
+        let [width_x, width_y, height_x, height_y] = frame.axes;
+        (
+            [width_x / w, width_y / h, height_x / w, height_y / h],
+            [frame.rect[0] / w, frame.rect[1] / h],
+        )
+    }
+
+    /// Pixel size of the first frame.
+    pub fn frame_size(&self) -> [u32; 2] {
+        let [_, _, w, h] = self.frames[0].rect;
+        [w.abs().max(1.0) as u32, h.abs().max(1.0) as u32]
+    }
+}
+
+/// Skip the `TEXB` image container up to the frame table.
+fn skip_images(r: &mut Cursor<&[u8]>) -> Option<()> {
+    let magic = read_magic(r)?;
+    let version = match magic.as_str() {
+        "TEXB0001" => 1,
+        "TEXB0002" => 2,
+        "TEXB0003" => 3,
+        "TEXB0004" => 4,
+        _ => return None,
+    };
+    let image_count = read_u32(r)?;
+    if version >= 3 {
+        let _image_format = read_u32(r)?;
+    }
+    if version == 4 && read_u32(r)? == 1 {
+        // MP4 payloads are never sprite sheets.
+        return None;
+    }
+
+    for _ in 0..image_count {
+        let mipmap_count = read_u32(r)?;
+        for _ in 0..mipmap_count {
+            let _size = [read_u32(r)?, read_u32(r)?];
+            if version >= 2 {
+                let _lz4 = read_u32(r)?;
+                let _decompressed = read_u32(r)?;
+            }
+            let len = read_u32(r)? as u64;
+            let end = r.position().checked_add(len)?;
+            if end > r.get_ref().len() as u64 {
+                return None;
+            }
+            r.set_position(end);
+        }
+    }
+    Some(())
+}
+
+pub(super) fn read_magic(r: &mut Cursor<&[u8]>) -> Option<String> {
+    let mut out = Vec::with_capacity(9);
+    for _ in 0..MAGIC_MAX {
+        let mut b = [0u8; 1];
+        r.read_exact(&mut b).ok()?;
+        if b[0] == 0 {
+            break;
+        }
+        out.push(b[0]);
+    }
+    String::from_utf8(out).ok()
+}
+
+pub(super) fn read_u32(r: &mut Cursor<&[u8]>) -> Option<u32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b).ok()?;
+    Some(u32::from_le_bytes(b))
+}
+
+fn read_f32(r: &mut Cursor<&[u8]>) -> Option<f32> {
+    read_u32(r).map(f32::from_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sheet of 256×128 with `(image, duration, [x, y, width_x, width_y,
+    /// height_x, height_y])` frames.
+    fn sheet_tex(frames: &[(u32, f32, [f32; 6])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let u = |out: &mut Vec<u8>, v: u32| out.extend_from_slice(&v.to_le_bytes());
+        let f = |out: &mut Vec<u8>, v: f32| out.extend_from_slice(&v.to_le_bytes());
+
+        out.extend_from_slice(b"TEXV0005\0TEXI0001\0");
+        for v in [0, FLAG_IS_GIF, 256, 128, 256, 128, 0] {
+            u(&mut out, v);
+        }
+        out.extend_from_slice(b"TEXB0003\0");
+        u(&mut out, 1); // images
+        u(&mut out, 0); // image format
+        u(&mut out, 1); // mipmaps
+        for v in [256, 128, 0, 4, 4] {
+            u(&mut out, v);
+        }
+        out.extend_from_slice(&[1, 2, 3, 4]);
+
+        out.extend_from_slice(b"TEXS0002\0");
+        u(&mut out, frames.len() as u32);
+        for &(image, duration, values) in frames {
+            u(&mut out, image);
+            f(&mut out, duration);
+            for v in values {
+                f(&mut out, v);
+            }
+        }
+        out
+    }
+
+    fn sprite_tex() -> Vec<u8> {
+        sheet_tex(&[
+            (0, 0.1, [0.0, 0.0, 128.0, 0.0, 0.0, 128.0]),
+            (0, 0.3, [128.0, 0.0, 128.0, 0.0, 0.0, 128.0]),
+        ])
+    }
+
+    #[test]
+    fn test_parse_sprite_sheet() {
+        let frames = TexFrames::parse(&sprite_tex()).unwrap();
+        assert_eq!(frames.sheet, [256, 128]);
+        assert_eq!(frames.frames.len(), 2);
+        assert_eq!(frames.frame_size(), [128, 128]);
+        assert!((frames.duration - 0.4).abs() < 1e-6);
+
+        assert_eq!(frames.frame_index(0.05), 0);
+        assert_eq!(frames.frame_index(0.2), 1);
+        // Loops after the last frame.
+        assert_eq!(frames.frame_index(0.45), 0);
+        assert_eq!(
+            frames.uv_corners(&frames.frames[1]),
+            [[0.5, 0.0], [1.0, 0.0], [1.0, 1.0], [0.5, 1.0]]
+        );
+        assert_eq!(
+            frames.texture_transform(&frames.frames[1]),
+            ([0.5, 0.0, 0.0, 1.0], [0.5, 0.0])
+        );
+    }
+
+    #[test]
+    fn test_frames_on_other_images_are_dropped() {
+        let tex = sheet_tex(&[
+            (0, 0.1, [0.0, 0.0, 128.0, 0.0, 0.0, 128.0]),
+            (1, 0.2, [0.0, 0.0, 128.0, 0.0, 0.0, 128.0]),
+            (0, 0.3, [128.0, 0.0, 128.0, 0.0, 0.0, 128.0]),
+        ]);
+        let frames = TexFrames::parse(&tex).unwrap();
+        assert_eq!(frames.frames.len(), 2);
+        assert_eq!(frames.frames[1].rect[0], 128.0);
+        assert!((frames.duration - 0.4).abs() < 1e-6);
+
+        let tex = sheet_tex(&[(2, 0.1, [0.0, 0.0, 128.0, 0.0, 0.0, 128.0])]);
+        assert_eq!(TexFrames::parse(&tex), None);
+    }
+
+    #[test]
+    fn test_rotated_frame_corners() {
+        // 128×64 cell turned a quarter: its width runs down the sheet and
+        // its height runs to the left.
+        let tex = sheet_tex(&[(0, 0.1, [64.0, 0.0, 0.0, 128.0, -64.0, 0.0])]);
+        let frames = TexFrames::parse(&tex).unwrap();
+        assert_eq!(
+            frames.uv_corners(&frames.frames[0]),
+            [[0.25, 0.0], [0.25, 1.0], [0.0, 1.0], [0.0, 0.0]]
+        );
+    }
+
+    #[test]
+    fn test_still_texture_has_no_frames() {
+        let mut tex = sprite_tex();
+        tex[22..26].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(TexFrames::parse(&tex), None);
+    }
+}
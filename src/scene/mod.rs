@@ -1,3 +1,7 @@
 pub mod adapters;
+pub mod audio;
 pub mod loader;
+pub mod power;
 pub mod renderer;
+pub mod script;
+pub mod video;
@@ -0,0 +1,252 @@
+//! Battery- and idle-aware render throttling.
+//!
+//! A [`PowerPolicy`] maps conditions (running on battery, battery below a
+//! threshold, user idle) to a [`Throttle`]: a lower frame rate, effects
+//! skipped, or the last frame kept on screen.  [`PowerMonitor`] reads the
+//! battery from sysfs `power_supply` and merges the throttles of every
+//! condition that holds.  Idle state comes from the adapter (the wlr
+//! adapter uses `ext-idle-notify-v1`).
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// How often sysfs is re-read.
+pub const POWER_POLL: Duration = Duration::from_secs(5);
+
+/// What to give up under a condition.  Merging keeps the strongest of each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Throttle {
+    /// Frame rate cap; `None` for unlimited.
+    pub fps: Option<u32>,
+    /// Skip post-processing effects.
+    pub no_effects: bool,
+    /// Stop rendering and keep the last frame.
+    pub freeze: bool,
+}
+
+impl Throttle {
+    /// Parse a comma-separated list of `none`, `no-effects`, `freeze` and
+    /// frame rates, e.g. `15,no-effects`.
+    pub fn parse(s: &str) -> Option<Self> {
+        s.split(',').try_fold(Self::default(), |throttle, item| {
+            let item = match item.trim() {
+                "none" => Self::default(),
+                "no-effects" => Self {
+                    no_effects: true,
+                    ..Default::default()
+                },
+                "freeze" => Self {
+                    freeze: true,
+                    ..Default::default()
+                },
+                fps => Self {
+                    fps: Some(fps.parse().ok().filter(|fps| *fps > 0)?),
+                    ..Default::default()
+                },
+            };
+            Some(throttle.merge(item))
+        })
+    }
+
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            fps: match (self.fps, other.fps) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            no_effects: self.no_effects || other.no_effects,
+            freeze: self.freeze || other.freeze,
+        }
+    }
+}
+
+/// User-configured throttling.
+#[derive(Debug, Clone, Copy)]
+pub struct PowerPolicy {
+    /// Frame rate cap when nothing else applies; 0 is unlimited.
+    pub fps: u32,
+    pub on_battery: Throttle,
+    /// Battery percentage at or below which `on_low_battery` applies.
+    pub low_battery: u32,
+    pub on_low_battery: Throttle,
+    /// Seconds without input before `on_idle` applies; 0 disables it.
+    pub idle_timeout: u32,
+    pub on_idle: Throttle,
+}
+
+/// Battery state read from sysfs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PowerSupply {
+    /// A system battery is discharging.
+    pub on_battery: bool,
+    /// Charge of the emptiest system battery.
+    pub battery_percent: Option<u32>,
+}
+
+impl PowerSupply {
+    /// Read every supply under `dir` (`/sys/class/power_supply`).  Batteries
+    /// of peripherals (`scope` = `Device`) are ignored.
+    pub fn read(dir: &Path) -> Self {
+        let mut supply = Self::default();
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            let read = |name: &str| {
+                fs::read_to_string(path.join(name))
+                    .map(|s| s.trim().to_string())
+                    .unwrap_or_default()
+            };
+            if read("type") != "Battery" || read("scope") == "Device" {
+                continue;
+            }
+            supply.on_battery |= read("status") == "Discharging";
+            if let Ok(percent) = read("capacity").parse::<u32>() {
+                supply.battery_percent =
+                    Some(supply.battery_percent.map_or(percent, |p| p.min(percent)));
+            }
+        }
+        supply
+    }
+}
+
+pub struct PowerMonitor {
+    policy: PowerPolicy,
+    dir: PathBuf,
+    supply: PowerSupply,
+    last_read: Option<Instant>,
+    idle: bool,
+}
+
+impl PowerMonitor {
+    pub fn new(policy: PowerPolicy) -> Self {
+        Self {
+            policy,
+            dir: PathBuf::from(POWER_SUPPLY_DIR),
+            supply: PowerSupply::default(),
+            last_read: None,
+            idle: false,
+        }
+    }
+
+    pub fn set_idle(&mut self, idle: bool) {
+        self.idle = idle;
+    }
+
+    /// Throttle for the conditions at `now`.  The battery is re-read at
+    /// most every [`POWER_POLL`].
+    pub fn throttle(&mut self, now: Instant) -> Throttle {
+        if self
+            .last_read
+            .is_none_or(|last| now.duration_since(last) >= POWER_POLL)
+        {
+            let supply = PowerSupply::read(&self.dir);
+            if supply != self.supply {
+                log::info!("power supply: {:?}", supply);
+            }
+            self.supply = supply;
+            self.last_read = Some(now);
+        }
+        self.policy.throttle(self.supply, self.idle)
+    }
+}
+
+impl PowerPolicy {
+    fn throttle(&self, supply: PowerSupply, idle: bool) -> Throttle {
+        let mut throttle = Throttle {
+            fps: (self.fps > 0).then_some(self.fps),
+            ..Default::default()
+        };
+        if supply.on_battery {
+            throttle = throttle.merge(self.on_battery);
+            if supply.battery_percent.is_some_and(|p| p <= self.low_battery) {
+                throttle = throttle.merge(self.on_low_battery);
+            }
+        }
+        if idle {
+            throttle = throttle.merge(self.on_idle);
+        }
+        throttle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_throttle() {
+        assert_eq!(Throttle::parse("none"), Some(Throttle::default()));
+        assert_eq!(
+            Throttle::parse("15, no-effects,30"),
+            Some(Throttle {
+                fps: Some(15),
+                no_effects: true,
+                freeze: false,
+            })
+        );
+        assert_eq!(Throttle::parse("0"), None);
+        assert_eq!(Throttle::parse("slow"), None);
+    }
+
+    #[test]
+    fn test_policy_throttle() {
+        let policy = PowerPolicy {
+            fps: 30,
+            on_battery: Throttle::parse("15").unwrap(),
+            low_battery: 20,
+            on_low_battery: Throttle::parse("no-effects").unwrap(),
+            idle_timeout: 300,
+            on_idle: Throttle::parse("freeze").unwrap(),
+        };
+        let battery = |percent| PowerSupply {
+            on_battery: true,
+            battery_percent: Some(percent),
+        };
+        let ac = PowerSupply::default();
+
+        assert_eq!(policy.throttle(ac, false).fps, Some(30));
+        assert_eq!(policy.throttle(battery(50), false).fps, Some(15));
+        assert!(!policy.throttle(battery(50), false).no_effects);
+        assert!(policy.throttle(battery(20), false).no_effects);
+        assert!(policy.throttle(ac, true).freeze);
+    }
+
+    #[test]
+    fn test_read_power_supply() {
+        let dir = std::env::temp_dir().join(format!("lwe-power-{}", std::process::id()));
+        let supply = |name: &str, files: &[(&str, &str)]| {
+            fs::create_dir_all(dir.join(name)).unwrap();
+            for (file, value) in files {
+                fs::write(dir.join(name).join(file), format!("{}\n", value)).unwrap();
+            }
+        };
+        supply("AC", &[("type", "Mains"), ("online", "0")]);
+        supply(
+            "BAT0",
+            &[("type", "Battery"), ("status", "Discharging"), ("capacity", "42")],
+        );
+        supply(
+            "hidpp_battery_0",
+            &[
+                ("type", "Battery"),
+                ("scope", "Device"),
+                ("status", "Discharging"),
+                ("capacity", "5"),
+            ],
+        );
+
+        let read = PowerSupply::read(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            read,
+            PowerSupply {
+                on_battery: true,
+                battery_percent: Some(42),
+            }
+        );
+    }
+}
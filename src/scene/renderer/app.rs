@@ -2,19 +2,37 @@
 //!
 //! [`WgpuApp`] owns all GPU resources and drives the per-frame
 //! render loop: time tracking, parallax computation, uniform upload,
-//! intermediate effect passes, and the final swapchain render pass.
+//! intermediate effect passes, the final render pass into the scene frame
+//! target, and presentation to the swapchain.  Until a scene has loaded,
+//! frames show its clear color and whatever of it is ready.
 
-use std::time::Instant;
+use std::{rc::Rc, time::Instant};
 
-use glam::Vec3;
+use glam::{Mat4, Vec3};
 use log;
 use wgpu::*;
 
-use crate::{MAX_INDEX, MAX_TEXTURE, MAX_VERTEX};
+use crate::{
+    MAX_INDEX, MAX_TEXTURE, MAX_VERTEX,
+    scene::{
+        audio::{AudioCapture, AudioPlayback, AudioSource, AudioSpectrum},
+        script::FrameInfo,
+    },
+};
 
 use super::{
-    buffer::Buffers, draw::DrawQueue, intermediate_pass, post_process::PostProcess,
-    projection::ProjectionBindGroups, render_pass, surface::AppSurface,
+    buffer::Buffers,
+    draw::{DrawQueue, FrameInputs},
+    load::Loading,
+    post_process::PostProcess,
+    post_processor::shader_cache::ShaderCache,
+    projection::ProjectionBindGroups,
+    render_graph::{FrameResources, RenderGraph},
+    render_pass,
+    render_targets::{SceneTargets, TargetViews},
+    scripting::SceneScripts,
+    surface::AppSurface,
+    video_wallpaper::VideoWallpaper,
 };
 
 pub use super::surface::InitAppSurface;
@@ -24,6 +42,8 @@ pub use super::surface::InitAppSurface;
 pub struct UserParams {
     /// Normalized cursor position in [0, 1] range (0,0) = top-left, (1,1) = bottom-right
     pub cursor_position: [f32; 2],
+    /// Latest spectrum from the audio capture (zeros when capture is off)
+    pub audio_spectrum: AudioSpectrum,
 }
 
 impl Default for UserParams {
@@ -32,6 +52,7 @@ impl Default for UserParams {
             // Center by default.  On Wayland (wlr adapter) cursor tracking
             // is unavailable, so staying at centre means no parallax shift.
             cursor_position: [0.5, 0.5],
+            audio_spectrum: AudioSpectrum::default(),
         }
     }
 }
@@ -46,15 +67,40 @@ pub struct WgpuApp {
     pub clear_color: Vec3,
     pub device: Device,
     pub queue: Queue,
-    pub audio_stream: rodio::OutputStream,
+    /// Scene sounds and the video audio track.
+    pub audio: AudioPlayback,
+    /// Where the spectrum comes from once a scene reads it.
+    audio_source: AudioSource,
+    /// Started when the loaded scene reads the audio spectrum.
+    pub audio_capture: Option<AudioCapture>,
     pub draw_queue: Option<DrawQueue>,
     pub post_process: Option<PostProcess>,
+    /// Scene frame and `_rt_*` targets, created with the draw queue.
+    pub targets: Option<SceneTargets>,
     pub resolution: Option<[u32; 2]>,
     pub start_time: Instant,
     pub elapsed_ms: u64,
     pub projection_matrix: [[f32; 4]; 4],
     pub no_effects: bool,
+    /// Skip effects at render time (power policy); unlike `no_effects`
+    /// they stay loaded.
+    effects_paused: bool,
+    /// The device samples BC1–BC3 textures, so they are uploaded
+    /// compressed.
+    pub bc_textures: bool,
+    /// Shared by every scene this app loads.
+    pub shader_cache: Rc<ShaderCache>,
     pub user_params: UserParams,
+    /// SceneScript runtime, `None` when the scene has no scripts.
+    pub(super) scripts: Option<SceneScripts>,
+    /// Set instead of the draw queue for video wallpapers.
+    pub(super) video: Option<VideoWallpaper>,
+    /// What the loaded scene's frames depend on.
+    pub(super) frame_inputs: FrameInputs,
+    /// The scene load in progress.
+    pub(super) loading: Option<Loading>,
+    /// The last presented frame is out of date.
+    pub(super) dirty: bool,
 }
 
 impl WgpuApp {
@@ -71,6 +117,7 @@ impl WgpuApp {
         size: [u32; 2],
         no_effects: bool,
         assets_path: Option<String>,
+        audio: &AudioSource,
     ) -> Self {
         let instance = Instance::new(&InstanceDescriptor {
             backends: Backends::VULKAN | Backends::METAL,
@@ -86,11 +133,19 @@ impl WgpuApp {
             .await
             .unwrap();
 
+        // BC textures stay compressed when the adapter can sample them.
+        let bc_textures = adapter
+            .features()
+            .contains(Features::TEXTURE_COMPRESSION_BC);
+        log::info!("BC texture compression: {}", bc_textures);
+
         let (device, queue) = adapter
             .request_device(&DeviceDescriptor {
                 label: None,
                 required_features: Features::TEXTURE_BINDING_ARRAY
-                    | Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+                    | Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+                    | (adapter.features()
+                        & (Features::TEXTURE_COMPRESSION_BC | Features::PIPELINE_CACHE)),
                 required_limits: Limits {
                     max_binding_array_elements_per_shader_stage: MAX_TEXTURE,
                     ..Default::default()
@@ -102,10 +157,11 @@ impl WgpuApp {
             .await
             .unwrap();
 
+        let shader_cache = Rc::new(ShaderCache::open(&device, &adapter.get_info()));
         let surface = AppSurface::new(surface, &instance, &adapter, size);
         let buffers = Buffers::new(&device, MAX_INDEX as u64, MAX_VERTEX as u64);
         let projection_bindgroup = ProjectionBindGroups::new(&device);
-        let audio_stream = rodio::OutputStreamBuilder::open_default_stream().unwrap();
+        let playback = AudioPlayback::open();
 
         Self {
             surface,
@@ -116,15 +172,64 @@ impl WgpuApp {
             clear_color: Vec3::ZERO,
             device,
             queue,
-            audio_stream,
+            audio: playback,
+            audio_source: audio.clone(),
+            audio_capture: None,
             draw_queue: None,
             resolution: None,
             post_process: None,
+            targets: None,
             start_time: Instant::now(),
             elapsed_ms: 0,
             projection_matrix: [[1.0; 4]; 4],
             no_effects: no_effects,
+            effects_paused: false,
+            bc_textures,
+            shader_cache,
             user_params: UserParams::default(),
+            scripts: None,
+            video: None,
+            frame_inputs: FrameInputs::default(),
+            loading: None,
+            dirty: true,
+        }
+    }
+
+    /// Capture audio while the loaded scene reads the spectrum, and only
+    /// then.
+    pub(super) fn update_audio_capture(&mut self) {
+        if !self.frame_inputs.audio {
+            self.audio_capture = None;
+        } else if self.audio_capture.is_none() {
+            self.audio_capture = AudioCapture::start(&self.audio_source);
+        }
+    }
+
+    /// The scene looks the same every frame until it is resized or gets
+    /// input: nothing reads the time or the audio spectrum.
+    pub fn is_static(&self) -> bool {
+        let audio = self.frame_inputs.audio && self.audio_capture.is_some();
+        !self.frame_inputs.time && !audio
+    }
+
+    /// Whether the next frame differs from the one on screen.  Frames
+    /// also advance a scene that is still loading.
+    pub fn needs_redraw(&self) -> bool {
+        self.dirty || !self.is_static() || self.is_loading()
+    }
+
+    /// Move the cursor (normalized, see [`UserParams`]).
+    pub fn set_cursor(&mut self, position: [f32; 2]) {
+        if self.user_params.cursor_position != position {
+            self.user_params.cursor_position = position;
+            self.dirty |= self.frame_inputs.cursor;
+        }
+    }
+
+    pub fn set_effects_paused(&mut self, paused: bool) {
+        if self.effects_paused != paused {
+            self.effects_paused = paused;
+            self.dirty = true;
         }
     }
 
@@ -140,6 +245,35 @@ impl WgpuApp {
 
         log::trace!("frame start: elapsed={:.2}s", elapsed);
 
+        if let Some(capture) = self.audio_capture.as_ref() {
+            self.user_params.audio_spectrum = capture.spectrum();
+        }
+
+        if self.video.is_some() {
+            return self.render_video();
+        }
+
+        // Nothing to draw yet, or the scene failed to load.
+        self.poll_load();
+        if self.draw_queue.is_none() {
+            return self.render_clear();
+        }
+
+        // --- SceneScripts: update transforms, visibility, constants ---
+        if let (Some(scripts), Some(draw_queue)) =
+            (self.scripts.as_mut(), self.draw_queue.as_mut())
+        {
+            let frame = FrameInfo {
+                runtime: self.elapsed_ms as f32 / 1000.0,
+                frametime: delta.as_secs_f32(),
+                screen: [self.surface.config.width, self.surface.config.height],
+                canvas: self.resolution.unwrap_or([1, 1]),
+                cursor: self.user_params.cursor_position,
+                audio: self.user_params.audio_spectrum.clone(),
+            };
+            scripts.update(&self.queue, &self.buffers, draw_queue, &frame);
+        }
+
         let draw_queue = match self.draw_queue.as_ref() {
             Some(dq) => dq,
             None => {
@@ -147,13 +281,6 @@ impl WgpuApp {
                 return None;
             }
         };
-        let post_process = match self.post_process.as_ref() {
-            Some(pp) => pp,
-            None => {
-                log::error!("ABORT: post_process is None");
-                return None;
-            }
-        };
         let screen_res = [self.surface.config.width, self.surface.config.height];
         log::trace!(
             "screen_res={:?} n_objects={}",
@@ -165,61 +292,124 @@ impl WgpuApp {
         let mut params = self.user_params.clone();
         params.cursor_position = self.compute_parallax_cursor();
 
-        // --- Upload per-frame uniforms to all effect bind groups ---
-        log::trace!("writing effect uniforms...");
-        render_pass::write_effect_uniforms(
-            &self.queue,
-            draw_queue.queue.as_ref(),
-            elapsed,
-            &self.projection_matrix,
-            screen_res,
-            &params,
-        );
+        // --- Advance sprite-sheet animations and videos; the uniforms
+        //     below read the current sprite frame ---
+        for sprite in draw_queue.queue.iter().filter_map(|o| o.sprite.as_ref()) {
+            sprite.update(&self.queue, elapsed);
+        }
+        let camera = Mat4::from_cols_array_2d(&self.projection_matrix);
+        for object in draw_queue.queue.iter().filter(|o| o.visible) {
+            object.update_background(&self.queue, &camera);
+        }
+        let clock = self.elapsed_ms as f64 / 1000.0;
+        for video in draw_queue.queue.iter().filter_map(|o| o.video.as_ref()) {
+            video.update(&self.queue, clock);
+        }
 
-        // --- Run intermediate post-process passes (effects) ---
-        let has_intermediates = draw_queue.queue.iter().any(|o| o.intermediates.is_some());
-        log::trace!("has_intermediates={}", has_intermediates);
-        if has_intermediates {
-            intermediate_pass::render_intermediate_passes(
-                &self.device,
+        // --- Upload the uniforms of all effect steps ---
+        if !self.effects_paused {
+            log::trace!("writing effect uniforms...");
+            render_pass::write_effect_uniforms(
                 &self.queue,
-                &self.buffers,
-                &self.projection_bindgroup,
-                &self.projection_matrix,
                 draw_queue,
-                post_process,
                 elapsed,
+                &self.projection_matrix,
                 screen_res,
                 &params,
             );
-            log::trace!("intermediate passes done");
         }
 
-        // --- Final render pass to swapchain ---
-        log::trace!("starting final render pass...");
-        let result = render_pass::render_final_pass(
-            &self.device,
-            &self.queue,
-            &self.surface,
-            &self.buffers,
-            &self.projection_bindgroup,
-            draw_queue,
-            post_process,
-            self.clear_color,
-        );
-        if result.is_some() {
-            log::trace!("final render pass OK");
-        } else {
-            log::warn!("final render pass FAILED");
+        let Some(targets) = self.targets.as_ref() else {
+            log::error!("ABORT: targets is None");
+            return None;
+        };
+        let graph = RenderGraph::build(draw_queue.queue.as_ref(), !self.effects_paused);
+
+        let Some((output, view)) = render_pass::acquire(&self.device, &self.surface) else {
+            log::warn!("present FAILED");
+            return None;
+        };
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor::default());
+        for gif in draw_queue.queue.iter().filter_map(|o| o.gif.as_ref()) {
+            gif.update(&mut encoder, &self.queue, elapsed);
         }
-        result
+
+        // --- Record the whole frame and submit it at once ---
+        let target_views = TargetViews::new(targets, draw_queue.queue.as_ref());
+        graph.execute(
+            &mut encoder,
+            &FrameResources {
+                draw_queue,
+                buffers: &self.buffers,
+                projection: &self.projection_bindgroup,
+                targets,
+                views: &target_views,
+                clear_color: self.clear_color,
+                surface: &view,
+            },
+        );
+        self.queue.submit(Some(encoder.finish()));
+        log::trace!("presenting...");
+        output.present();
+        self.dirty = false;
+        Some(())
+    }
+
+    /// Video wallpaper frame: draw the current video frame and present.
+    fn render_video(&self) -> Option<()> {
+        let (Some(video), Some(targets)) = (self.video.as_ref(), self.targets.as_ref()) else {
+            log::error!("ABORT: video wallpaper not loaded");
+            return None;
+        };
+        let (output, view) = render_pass::acquire(&self.device, &self.surface)?;
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor::default());
+        let clock = self.elapsed_ms as f64 / 1000.0;
+        video.render(&self.device, &self.queue, &mut encoder, targets, clock);
+        targets.blit(&mut encoder, &view);
+        self.queue.submit(Some(encoder.finish()));
+        output.present();
+        Some(())
+    }
+
+    /// Frame of a scene whose package is still being read: the clear
+    /// color, once known.
+    fn render_clear(&mut self) -> Option<()> {
+        let (output, view) = render_pass::acquire(&self.device, &self.surface)?;
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor::default());
+        render_pass::clear(&mut encoder, &view, self.clear_color);
+        self.queue.submit(Some(encoder.finish()));
+        output.present();
+        self.dirty = false;
+        Some(())
+    }
+
+    /// Continue after the adapter stopped rendering (wallpaper covered or
+    /// frozen).  The skipped time is not played back, so animations and
+    /// videos go on from where they stopped.
+    pub fn resume(&mut self) {
+        self.start_time = Instant::now();
     }
 
     pub fn resize(&mut self, size: [u32; 2]) {
+        self.dirty = true;
         self.surface.config.width = size[0];
         self.surface.config.height = size[1];
         self.surface
             .surface
             .configure(&self.device, &self.surface.config);
+        if let Some(targets) = self.targets.as_mut()
+            && targets.resize(&self.device, size)
+            && let (Some(draw_queue), Some(post_process)) =
+                (self.draw_queue.as_mut(), self.post_process.as_ref())
+        {
+            // The step bind groups reference the recreated background.
+            draw_queue.bind_targets(&self.device, targets, &post_process.sampler);
+        }
     }
 }
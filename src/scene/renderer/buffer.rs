@@ -6,6 +6,8 @@ use super::vertex::Vertex;
 pub struct Buffers {
     pub vertex: Buffer,
     pub index: Buffer,
+    /// Alpha of each quad, drawn as its instance index.
+    pub alpha: Buffer,
     pub projection: Buffer,
 
     pub vertex_len: u32,
@@ -26,6 +28,12 @@ impl Buffers {
             mapped_at_creation: false,
             size: (std::mem::size_of::<u32>() as u64 * index_len),
         });
+        let alpha = device.create_buffer(&BufferDescriptor {
+            label: Some("alpha buffer"),
+            usage: BufferUsages::COPY_DST | BufferUsages::VERTEX,
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<f32>() as u64 * vertex_len.div_ceil(4)),
+        });
         let projection = device.create_buffer(&BufferDescriptor {
             label: Some("projection buffer"),
             usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
@@ -36,6 +44,7 @@ impl Buffers {
         Self {
             vertex,
             index,
+            alpha,
             projection,
             vertex_len: 0,
             index_len: 0,
@@ -87,29 +96,69 @@ impl Buffers {
         angles: Vec3,
         scale: Vec3,
         size: Vec2,
+        alpha: f32,
     ) {
-        let size_scaled = size
-            * Vec2 {
-                x: scale.x,
-                y: scale.y,
-            };
-        let z = origin.z - 1.0;
-
-        let rotation_mat = Mat2::from_angle(angles.z);
-        let half = Vec2::new(size_scaled.x / 2.0, size_scaled.y / 2.0);
-        let corners = [
-            Vec2::new(-half.x, half.y),
-            Vec2::new(half.x, half.y),
-            Vec2::new(half.x, -half.y),
-            Vec2::new(-half.x, -half.y),
-        ];
+        self.update_alpha(queue, self.vertex_len, alpha);
+        self.draw_rect(queue, texture_corners(origin, angles, scale, size));
+    }
 
-        let pos_offset = Vec2::new(origin.x, origin.y);
-        let rect = corners.map(|v| {
-            let rotated = rotation_mat * v + pos_offset;
-            Vec3::new(rotated.x, rotated.y, z)
+    /// Rewrite the four vertices of a quad previously emitted by
+    /// [`draw_texture`](Self::draw_texture), e.g. after a script moved it.
+    pub fn update_texture(
+        &self,
+        queue: &Queue,
+        first_vertex: u32,
+        origin: Vec3,
+        angles: Vec3,
+        scale: Vec3,
+        size: Vec2,
+    ) {
+        let pos = texture_corners(origin, angles, scale, size);
+        let uvs = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let rect: [Vertex; 4] = std::array::from_fn(|i| Vertex {
+            pos: pos[i].to_array(),
+            uv: uvs[i],
         });
 
-        self.draw_rect(queue, rect);
+        queue.write_buffer(
+            &self.vertex,
+            std::mem::size_of::<Vertex>() as BufferAddress * first_vertex as BufferAddress,
+            bytemuck::bytes_of(&rect),
+        );
     }
+
+    /// Set the alpha of the quad starting at `first_vertex`.
+    pub fn update_alpha(&self, queue: &Queue, first_vertex: u32, alpha: f32) {
+        queue.write_buffer(
+            &self.alpha,
+            std::mem::size_of::<f32>() as BufferAddress * (first_vertex / 4) as BufferAddress,
+            bytemuck::bytes_of(&alpha),
+        );
+    }
+}
+
+/// Corners of a rotated, scaled quad centred on `origin`, in the
+/// winding order expected by [`Buffers::draw_rect`].
+pub(super) fn texture_corners(origin: Vec3, angles: Vec3, scale: Vec3, size: Vec2) -> [Vec3; 4] {
+    let size_scaled = size
+        * Vec2 {
+            x: scale.x,
+            y: scale.y,
+        };
+    let z = origin.z - 1.0;
+
+    let rotation_mat = Mat2::from_angle(angles.z);
+    let half = Vec2::new(size_scaled.x / 2.0, size_scaled.y / 2.0);
+    let corners = [
+        Vec2::new(-half.x, half.y),
+        Vec2::new(half.x, half.y),
+        Vec2::new(half.x, -half.y),
+        Vec2::new(-half.x, -half.y),
+    ];
+
+    let pos_offset = Vec2::new(origin.x, origin.y);
+    corners.map(|v| {
+        let rotated = rotation_mat * v + pos_offset;
+        Vec3::new(rotated.x, rotated.y, z)
+    })
 }
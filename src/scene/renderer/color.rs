@@ -0,0 +1,61 @@
+//! Colour space of textures, render targets and the surface.
+//!
+//! Wallpaper Engine does no colour management: textures are sampled as
+//! stored, blending and effects work on the encoded values, and the result
+//! is presented as is.  Matching it means every colour-carrying format here
+//! is UNORM, never sRGB; an sRGB format anywhere in the chain would decode
+//! or encode a gamma curve the authored colours never went through, and
+//! shift gradients and blends away from what the Windows build shows.
+
+use glam::Vec3;
+use wgpu::*;
+
+/// Format of colour textures, FBOs and ping-pong targets.
+pub const COLOR_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+
+/// Upload format of a decoded `.tex` payload by its extension.  R8 and RG88
+/// (masks, flow maps) keep their channel count; everything else is RGBA.
+pub fn texture_format(extension: &str) -> TextureFormat {
+    match extension {
+        "r8" => TextureFormat::R8Unorm,
+        "rg88" => TextureFormat::Rg8Unorm,
+        _ => COLOR_FORMAT,
+    }
+}
+
+/// Pick the surface format and the format its frames are viewed (and
+/// rendered) as.  A non-sRGB 8-bit format is preferred; when the surface
+/// only offers sRGB ones, frames are viewed through the UNORM variant so
+/// no encoding is applied on write.
+pub fn surface_formats(formats: &[TextureFormat]) -> (TextureFormat, TextureFormat) {
+    let format = formats
+        .iter()
+        .copied()
+        .find(|format| matches!(format, TextureFormat::Bgra8Unorm | TextureFormat::Rgba8Unorm))
+        .unwrap_or(formats[0]);
+    (format, format.remove_srgb_suffix())
+}
+
+/// Clear colour of a scene; `clearcolor` is authored in 0..1.
+pub fn clear_color(color: Vec3) -> Color {
+    Color {
+        r: color.x as f64,
+        g: color.y as f64,
+        b: color.z as f64,
+        a: 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_surface_formats() {
+        use TextureFormat::*;
+        assert_eq!(surface_formats(&[Bgra8UnormSrgb, Bgra8Unorm]), (Bgra8Unorm, Bgra8Unorm));
+        assert_eq!(surface_formats(&[Bgra8UnormSrgb]), (Bgra8UnormSrgb, Bgra8Unorm));
+        assert_eq!(surface_formats(&[Rgb10a2Unorm, Rgba8Unorm]), (Rgba8Unorm, Rgba8Unorm));
+        assert_eq!(surface_formats(&[Rgb10a2Unorm]), (Rgb10a2Unorm, Rgb10a2Unorm));
+    }
+}
@@ -0,0 +1,75 @@
+//! Upload of block-compressed textures.
+//!
+//! Used when the adapter supports `TEXTURE_COMPRESSION_BC`; the blocks and
+//! their stored mip chain go to the GPU untouched, at a quarter (BC3) or
+//! an eighth (BC1) of the RGBA size.  BC7 payloads would map to
+//! `Bc7RgbaUnorm` here once the loader reads them.  Raw RGBA textures with
+//! a stored mip chain are uploaded the same way, on any adapter.
+
+use wgpu::*;
+
+use super::color::COLOR_FORMAT;
+use crate::scene::loader::tex_bc::{BcFormat, BcTexture};
+
+/// GPU format of a BC texture.  UNORM like every other colour texture,
+/// see [`color`](super::color).
+pub fn texture_format(format: BcFormat) -> TextureFormat {
+    match format {
+        BcFormat::Rgba8 => COLOR_FORMAT,
+        BcFormat::Bc1 => TextureFormat::Bc1RgbaUnorm,
+        BcFormat::Bc2 => TextureFormat::Bc2RgbaUnorm,
+        BcFormat::Bc3 => TextureFormat::Bc3RgbaUnorm,
+    }
+}
+
+/// Create a texture holding every stored level of `bc`.
+pub fn upload(device: &Device, queue: &Queue, bc: &BcTexture) -> Texture {
+    let [width, height] = bc.levels[0].size;
+    // Only levels of the size the GPU expects can be uploaded.
+    let levels = bc
+        .levels
+        .iter()
+        .enumerate()
+        .take_while(|(i, mip)| mip.size == [(width >> i).max(1), (height >> i).max(1)])
+        .count();
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("bc texture"),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: levels as u32,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: texture_format(bc.format),
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    let block = bc.format.block_size();
+    for (level, mip) in bc.levels.iter().take(levels).enumerate() {
+        let blocks = [mip.size[0].div_ceil(block), mip.size[1].div_ceil(block)];
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: level as u32,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &mip.data,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(blocks[0] * bc.format.block_bytes() as u32),
+                rows_per_image: Some(blocks[1]),
+            },
+            // Copies of BC levels are in whole blocks.
+            Extent3d {
+                width: blocks[0] * block,
+                height: blocks[1] * block,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+    texture
+}
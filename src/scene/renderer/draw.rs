@@ -3,96 +3,356 @@
 //! Each [`TextureObject`] from the scene loader is converted into a
 //! [`DrawObject`] with its texture bind group, a flattened list of
 //! [`EffectStep`]s (unifying single-pass and multi-pass effects), and
-//! optional ping-pong intermediate textures for post-processing.
+//! optional ping-pong intermediate textures for post-processing.  The
+//! uniforms of all steps share one buffer ([`EffectUniforms`]), and the
+//! step bind groups are built once the scene targets exist
+//! ([`DrawQueue::bind_targets`]).
+//!
+//! The queue is built incrementally while a scene loads: every layer is
+//! added first ([`DrawQueue::push_layer`]), then the effects of each
+//! ([`DrawQueue::add_effects`]), and [`DrawQueue::layout`] lays out the
+//! uniforms and targets of what is there so far.
 
 use std::{collections::BTreeMap, rc::Rc};
 
+use glam::{Mat4, Vec2, Vec3};
 use wgpu::*;
 
 use crate::scene::{
     loader::{object_loader::TextureObject, scene_loader::Scene},
     renderer::{
         buffer::Buffers,
+        color::{self, COLOR_FORMAT},
+        compressed,
+        effect_uniforms::EffectUniforms,
+        gif_texture::GifTexture,
         ping_pong::PingPongTextures,
         post_process::PostProcess,
         post_processor::{
-            effect_step::{self, EffectStep, FboTexture},
+            effect_param::{TEXTURE_IDENTITY, UniformLayout},
+            effect_step::{self, EffectStep},
             pipeline_handler::{self},
+            texture_slots::SlotTexture,
         },
+        render_graph,
+        render_targets::{self, FULL_FRAME_BUFFER, SceneTargets, TargetViews},
+        sprite::SpriteAnimation,
+        vertex::{NDC_VERTICES, Vertex, pass_vertices},
+        video_texture::VideoTexture,
     },
 };
 
+/// World-space placement of a layer quad.
+#[derive(Debug, Clone, Copy)]
+pub struct LayerTransform {
+    pub origin: Vec3,
+    pub angles: Vec3,
+    pub scale: Vec3,
+    pub size: Vec2,
+}
+
+/// What the frames of a draw queue change with, besides the output size.
+/// A queue that depends on none of these renders the same frame forever.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameInputs {
+    /// `g_Time`, or a sprite-sheet, GIF or video layer.
+    pub time: bool,
+    /// `g_ParallaxPosition`.
+    pub cursor: bool,
+    /// Any `g_AudioSpectrum*` uniform, or `engine.registerAudioBuffers`.
+    pub audio: bool,
+}
+
+impl FrameInputs {
+    /// Per-frame uniforms an effect step's layout reads.
+    pub fn of_layout(layout: &UniformLayout) -> Self {
+        let mut inputs = Self::default();
+        for name in layout.names() {
+            match name {
+                "g_Time" => inputs.time = true,
+                "g_ParallaxPosition" => inputs.cursor = true,
+                _ if name.starts_with("g_AudioSpectrum") => inputs.audio = true,
+                _ => {}
+            }
+        }
+        inputs
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            time: self.time || other.time,
+            cursor: self.cursor || other.cursor,
+            audio: self.audio || other.audio,
+        }
+    }
+}
+
 pub struct DrawObject {
+    /// Scene object id and name (for scripts and diagnostics).
+    pub id: i64,
+    pub name: String,
+    pub visible: bool,
+    /// Draw the scene beneath this layer (`copybackground`) instead of
+    /// its own texture.
+    pub copy_background: bool,
+    pub transform: LayerTransform,
+    /// First vertex of this object's quad in the shared vertex buffer.
+    pub vertex_start: u32,
     pub index_range: [u32; 2],
     pub bindgroup: BindGroup,
     /// All effect steps (single-pass and multi-pass flattened together).
     pub effect_steps: Vec<EffectStep>,
-    /// Named FBOs allocated for multi-pass effect chains.
-    pub fbos: BTreeMap<String, FboTexture>,
+    /// Size of the layer texture (one frame, for sprite sheets), reported
+    /// by the `g_Texture*Resolution` uniforms.
+    pub texture_size: [u32; 2],
+    /// Resolution of the layer's effect targets, see [`footprint`].
+    pub target_size: [u32; 2],
+    /// Sizes of the named FBOs of multi-pass effect chains.
+    pub fbo_sizes: BTreeMap<String, [u32; 2]>,
+    /// Views of those FBOs, assigned by [`render_graph::allocate_targets`].
+    pub fbos: BTreeMap<String, TextureView>,
+    /// Size of the ping-pong pair, when the layer is staged through one.
+    pub intermediate_size: Option<[u32; 2]>,
+    /// NDC quad whose UVs cut the frame under the layer out of the
+    /// background, for `copybackground` and `_rt_FullFrameBuffer`; see
+    /// [`update_background`](Self::update_background).
+    pub background_vbuf: Option<Buffer>,
+    /// Quad the effect steps are drawn with, in pass space (see
+    /// [`pass_vertices`]).
+    pub pass_vbuf: Option<Buffer>,
+    /// The ping-pong pair, from the shared target pool (see
+    /// [`render_graph::allocate_targets`]).
     pub intermediates: Option<PingPongTextures>,
+    /// Frame selection for sprite-sheet textures.
+    pub sprite: Option<SpriteAnimation>,
+    /// Decoder feeding the layer texture when it is a video.
+    pub video: Option<VideoTexture>,
+    /// Frames of the layer texture when it is an animated GIF.
+    pub gif: Option<GifTexture>,
 }
 
 pub struct DrawQueue {
     pub queue: Rc<Vec<DrawObject>>,
+    /// Uniform blocks of every effect step.
+    pub uniforms: EffectUniforms,
     #[allow(dead_code)]
     pub render_pipelines: BTreeMap<String, pipeline_handler::EffectPipelineData>,
     pub image_pipeline: RenderPipeline,
 }
 
 impl DrawQueue {
-    pub fn new(
+    /// A queue without objects.
+    pub fn empty(device: &Device, image_pipeline: RenderPipeline) -> Self {
+        Self {
+            queue: Rc::new(Vec::new()),
+            uniforms: EffectUniforms::new(device, &mut []),
+            render_pipelines: BTreeMap::new(),
+            image_pipeline,
+        }
+    }
+
+    /// Add the layer of a texture object, without its effects.
+    /// `surface_scale` is the output size over the scene size, per axis.
+    pub fn push_layer(
+        &mut self,
         device: &Device,
         queue: &Queue,
         buffers: &mut Buffers,
+        texture_object: &TextureObject,
+        post_process: &PostProcess,
+        surface_scale: Vec2,
+    ) {
+        if let Some(objects) = self.objects_mut() {
+            objects.push(DrawObject::build(
+                device,
+                queue,
+                texture_object,
+                post_process,
+                buffers,
+                surface_scale,
+            ));
+        }
+    }
+
+    /// Build the effects of the object at `index`, added from
+    /// `texture_object`.
+    pub fn add_effects(
+        &mut self,
+        index: usize,
+        device: &Device,
+        queue: &Queue,
         scene: &Scene,
-        texture_objects: Vec<TextureObject>,
-        image_pipeline: RenderPipeline,
+        texture_object: &TextureObject,
         post_process: &PostProcess,
         projection_bgl: &BindGroupLayout,
         no_effects: bool,
-    ) -> Self {
-        let mut render_pipelines = BTreeMap::new();
-
-        let draw_objects: Vec<DrawObject> = texture_objects
-            .into_iter()
-            .map(|tex_obj| {
-                DrawObject::build(
-                    device,
-                    queue,
-                    scene,
-                    tex_obj,
-                    post_process,
-                    &mut render_pipelines,
-                    buffers,
-                    projection_bgl,
-                    no_effects,
-                )
-            })
-            .collect();
+    ) {
+        let Some(objects) = Rc::get_mut(&mut self.queue) else {
+            log::warn!("draw queue is shared, effects of '{}' not added", texture_object.name);
+            return;
+        };
+        let Some(object) = objects.get_mut(index) else {
+            return;
+        };
+        object.build_effects(
+            device,
+            queue,
+            scene,
+            texture_object,
+            post_process,
+            &mut self.render_pipelines,
+            projection_bgl,
+            no_effects,
+        );
+    }
 
-        Self {
-            queue: Rc::new(draw_objects),
-            render_pipelines,
-            image_pipeline,
+    /// Lay out the uniforms of every effect step and allocate the effect
+    /// targets, after objects or effects were added.  The step bind groups
+    /// must be rebuilt with [`bind_targets`](Self::bind_targets) after.
+    pub fn layout(&mut self, device: &Device, queue: &Queue, post_process: &PostProcess) {
+        let Some(objects) = self.objects_mut() else {
+            return;
+        };
+        let uniforms = EffectUniforms::new(device, objects);
+        render_graph::allocate_targets(device, queue, post_process, objects);
+        self.uniforms = uniforms;
+    }
+
+    fn objects_mut(&mut self) -> Option<&mut Vec<DrawObject>> {
+        let objects = Rc::get_mut(&mut self.queue);
+        if objects.is_none() {
+            log::warn!("draw queue is shared, not modified");
+        }
+        objects
+    }
+
+    /// Build the bind groups of every effect step against `targets`.
+    /// Called once the targets exist and again whenever they are
+    /// recreated.
+    pub fn bind_targets(&mut self, device: &Device, targets: &SceneTargets, sampler: &Sampler) {
+        let Some(objects) = Rc::get_mut(&mut self.queue) else {
+            log::warn!("draw queue is shared, effect bind groups not rebuilt");
+            return;
+        };
+
+        let bindgroups: Vec<Vec<[BindGroup; 2]>> = {
+            let views = TargetViews::new(targets, objects);
+            objects
+                .iter()
+                .map(|object| {
+                    let Some(pp) = object.intermediates.as_ref() else {
+                        return Vec::new();
+                    };
+                    object
+                        .effect_steps
+                        .iter()
+                        .map(|step| {
+                            [&pp.view_a, &pp.view_b].map(|source| {
+                                effect_step::make_step_bindgroup(
+                                    device,
+                                    step,
+                                    source,
+                                    &object.fbos,
+                                    &views,
+                                    sampler,
+                                    &self.uniforms,
+                                )
+                            })
+                        })
+                        .collect()
+                })
+                .collect()
+        };
+
+        for (object, groups) in objects.iter_mut().zip(bindgroups) {
+            for (step, groups) in object.effect_steps.iter_mut().zip(groups) {
+                step.bindgroups = Some(groups);
+            }
         }
     }
+
+    /// What the queue's frames depend on.  Steps disabled at load time are
+    /// included, since scripts may enable them later.
+    pub fn frame_inputs(&self) -> FrameInputs {
+        self.queue.iter().fold(FrameInputs::default(), |inputs, object| {
+            let animated =
+                object.sprite.is_some() || object.gif.is_some() || object.video.is_some();
+            object
+                .effect_steps
+                .iter()
+                .map(|step| FrameInputs::of_layout(&step.bindgroup.uniform_layout))
+                .fold(inputs, FrameInputs::union)
+                .union(FrameInputs {
+                    time: animated,
+                    ..Default::default()
+                })
+        })
+    }
 }
 
 impl DrawObject {
+    /// Show or hide every step of the effect with the given scene id.
+    pub fn set_effect_visible(&mut self, effect_id: i64, visible: bool) {
+        for step in self.effect_steps.iter_mut().filter(|s| s.effect_id == effect_id) {
+            step.enabled = visible;
+        }
+    }
+
+    /// `g_Texture0Rotation` and `g_Texture0Translation`: where the current
+    /// sprite frame lies on its sheet, or the whole texture.
+    pub fn texture_transform(&self) -> ([f32; 4], [f32; 2]) {
+        match self.sprite.as_ref() {
+            Some(sprite) => sprite.frames.texture_transform(sprite.current_frame()),
+            None => TEXTURE_IDENTITY,
+        }
+    }
+
+    /// Whether rendering this layer needs everything drawn before it.
+    pub fn samples_background(&self) -> bool {
+        self.copy_background
+            || self.effect_steps.iter().filter(|s| s.enabled).any(samples_frame_buffer)
+    }
+
+    /// Point the background quad at the frame under the layer, as drawn
+    /// with the scene camera `view_projection`.
+    pub fn update_background(&self, queue: &Queue, view_projection: &Mat4) {
+        let Some(vbuf) = self.background_vbuf.as_ref() else {
+            return;
+        };
+        let uvs = render_targets::screen_uvs(&self.transform, view_projection);
+        let quad: [Vertex; 4] = std::array::from_fn(|i| Vertex {
+            pos: NDC_VERTICES[i].pos,
+            uv: uvs[i],
+        });
+        queue.write_buffer(vbuf, 0, bytemuck::bytes_of(&quad));
+    }
+
+    /// The layer of a texture object; its effects are added by
+    /// [`build_effects`](Self::build_effects).
     fn build(
         device: &Device,
         queue: &Queue,
-        scene: &Scene,
-        texture_object: TextureObject,
+        texture_object: &TextureObject,
         post_process: &PostProcess,
-        pipelines: &mut BTreeMap<String, pipeline_handler::EffectPipelineData>,
         buffers: &mut Buffers,
-        projection_bgl: &BindGroupLayout,
-        no_effects: bool,
+        surface_scale: Vec2,
     ) -> Self {
         let index_start = buffers.index_len;
+        let vertex_start = buffers.vertex_len;
 
-        let texture = Self::upload_texture(device, queue, &texture_object);
+        let video = match texture_object.texture.extension.as_str() {
+            "mp4" | "webm" => VideoTexture::open(device, &texture_object.texture.payload),
+            _ => None,
+        };
+        let gif = match texture_object.texture.extension.as_str() {
+            "gif" => GifTexture::open(device, queue, &texture_object.texture.payload),
+            _ => None,
+        };
+        let texture = match (video.as_ref(), gif.as_ref()) {
+            (Some(video), _) => video.texture.clone(),
+            (_, Some(gif)) => gif.texture.clone(),
+            _ => Self::upload_texture(device, queue, post_process, texture_object),
+        };
         let source_view = texture.create_view(&Default::default());
 
         let bindgroup = device.create_bind_group(&BindGroupDescriptor {
@@ -110,36 +370,29 @@ impl DrawObject {
             ],
         });
 
-        let tex_w = texture_object.texture.dimension[0];
-        let tex_h = texture_object.texture.dimension[1];
+        // Sprite sheets are processed one frame at a time.  A decoded GIF
+        // already is a single frame.
+        let frames = texture_object.frames.clone().filter(|_| gif.is_none());
+        let [tex_w, tex_h] = match frames.as_ref() {
+            Some(frames) => frames.frame_size(),
+            None => [texture.width(), texture.height()],
+        };
 
-        let (effect_steps, fbos, has_steps) = effect_step::build_effect_steps(
-            device,
-            queue,
-            &texture_object.effects,
-            scene,
-            post_process,
-            pipelines,
-            projection_bgl,
-            &source_view,
-            tex_w,
-            tex_h,
-            no_effects,
+        // Effects run at the layer's size on the output, not the scene's.
+        let target_size = footprint(
+            texture_object.size,
+            texture_object.scale,
+            [tex_w, tex_h],
+            surface_scale,
+            device.limits().max_texture_dimension_2d,
         );
 
-        let intermediates = if has_steps {
-            let max_w = tex_w.max(post_process.blank_texture.width());
-            let max_h = tex_h.max(post_process.blank_texture.height());
-            Some(PingPongTextures::new(
-                device,
-                queue,
-                post_process,
-                max_w,
-                max_h,
-            ))
-        } else {
-            None
-        };
+        // A background copy or sprite frame is staged in the ping-pong
+        // pair like an effect result, even when the layer has no effects.
+        let sprite = frames.map(|frames| SpriteAnimation::new(device, queue, frames));
+        let intermediate_size =
+            (texture_object.copy_background || sprite.is_some()).then_some(target_size);
+        let background_vbuf = texture_object.copy_background.then(|| quad_buffer(device));
 
         buffers.draw_texture(
             queue,
@@ -147,59 +400,214 @@ impl DrawObject {
             texture_object.angles,
             texture_object.scale,
             texture_object.size,
+            texture_object.alpha,
         );
 
         Self {
+            id: texture_object.id,
+            name: texture_object.name.clone(),
+            visible: texture_object.visible,
+            copy_background: texture_object.copy_background,
+            transform: LayerTransform {
+                origin: texture_object.origin,
+                angles: texture_object.angles,
+                scale: texture_object.scale,
+                size: texture_object.size,
+            },
+            vertex_start,
             index_range: [index_start, buffers.index_len],
             bindgroup,
-            effect_steps,
-            fbos,
-            intermediates,
+            effect_steps: Vec::new(),
+            texture_size: [tex_w, tex_h],
+            target_size,
+            fbo_sizes: BTreeMap::new(),
+            fbos: BTreeMap::new(),
+            intermediate_size,
+            background_vbuf,
+            pass_vbuf: None,
+            intermediates: None,
+            sprite,
+            video,
+            gif,
         }
     }
 
-    fn upload_texture(device: &Device, queue: &Queue, tex_obj: &TextureObject) -> Texture {
-        let (format, bpp) = match tex_obj.texture.extension.as_str() {
-            "r8" => (TextureFormat::R8Unorm, 1u32),
-            "rg88" => (TextureFormat::Rg8Unorm, 2u32),
-            _ => (TextureFormat::Rgba8UnormSrgb, 4u32),
-        };
+    /// Build the effect steps of the layer, replacing any it had.
+    fn build_effects(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        texture_object: &TextureObject,
+        post_process: &PostProcess,
+        pipelines: &mut BTreeMap<String, pipeline_handler::EffectPipelineData>,
+        projection_bgl: &BindGroupLayout,
+        no_effects: bool,
+    ) {
+        let [tex_w, tex_h] = self.texture_size;
+        let (effect_steps, fbo_sizes, has_steps) = effect_step::build_effect_steps(
+            device,
+            queue,
+            &texture_object.effects,
+            texture_object.instance.as_ref(),
+            scene,
+            post_process,
+            pipelines,
+            projection_bgl,
+            tex_w,
+            tex_h,
+            self.target_size,
+            no_effects,
+        );
+        self.effect_steps = effect_steps;
+        self.fbo_sizes = fbo_sizes;
+        self.fbos.clear();
+        self.pass_vbuf = has_steps.then(|| {
+            let vbuf = quad_buffer(device);
+            queue.write_buffer(&vbuf, 0, bytemuck::bytes_of(&pass_vertices(self.texture_size)));
+            vbuf
+        });
+        if has_steps {
+            self.intermediate_size = Some(self.target_size);
+        }
+        // The background under the layer is staged like an FBO of the
+        // layer's size, which shadows the whole-frame target.
+        if self.effect_steps.iter().any(samples_frame_buffer) {
+            self.fbo_sizes.insert(FULL_FRAME_BUFFER.to_string(), self.target_size);
+            self.background_vbuf.get_or_insert_with(|| quad_buffer(device));
+        }
+    }
+
+    fn upload_texture(
+        device: &Device,
+        queue: &Queue,
+        post_process: &PostProcess,
+        tex_obj: &TextureObject,
+    ) -> Texture {
+        if let Some(bc) = tex_obj.compressed.as_ref() {
+            return compressed::upload(device, queue, bc);
+        }
+        if matches!(tex_obj.texture.extension.as_str(), "mp4" | "webm" | "gif") {
+            // Undecodable video or GIF: the payload is not pixels.
+            return Self::placeholder_texture(device, queue);
+        }
+        post_process.mipmaps.upload(
+            device,
+            queue,
+            color::texture_format(&tex_obj.texture.extension),
+            tex_obj.texture.dimension,
+            &tex_obj.texture.payload,
+        )
+    }
 
+    /// 1×1 transparent texture for layers whose image cannot be shown.
+    fn placeholder_texture(device: &Device, queue: &Queue) -> Texture {
         let texture = device.create_texture(&TextureDescriptor {
             label: None,
             size: Extent3d {
-                width: tex_obj.texture.dimension[0],
-                height: tex_obj.texture.dimension[1],
+                width: 1,
+                height: 1,
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
             sample_count: 1,
             dimension: TextureDimension::D2,
-            format,
+            format: COLOR_FORMAT,
             usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
             view_formats: &[],
         });
-
         queue.write_texture(
-            TexelCopyTextureInfo {
-                texture: &texture,
-                mip_level: 0,
-                origin: Origin3d::ZERO,
-                aspect: TextureAspect::All,
-            },
-            &tex_obj.texture.payload,
+            texture.as_image_copy(),
+            &[0, 0, 0, 0],
             TexelCopyBufferLayout {
                 offset: 0,
-                bytes_per_row: Some(tex_obj.texture.dimension[0] * bpp),
+                bytes_per_row: Some(4),
                 rows_per_image: None,
             },
-            Extent3d {
-                width: tex_obj.texture.dimension[0],
-                height: tex_obj.texture.dimension[1],
-                depth_or_array_layers: 1,
-            },
+            texture.size(),
         );
-
         texture
     }
 }
+
+/// Whether an effect step samples `_rt_FullFrameBuffer`.
+fn samples_frame_buffer(step: &EffectStep) -> bool {
+    step.bind_inputs.iter().any(|(name, _)| name == FULL_FRAME_BUFFER)
+        || step
+            .bindgroup
+            .textures
+            .iter()
+            .any(|t| matches!(t, SlotTexture::Target(name) if name == FULL_FRAME_BUFFER))
+}
+
+/// Vertex buffer for one quad, written before each use.
+fn quad_buffer(device: &Device) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: None,
+        size: std::mem::size_of::<Vertex>() as u64 * 4,
+        usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Resolution of a layer's effect targets: its footprint on the output,
+/// `size × scale` (or `texture` when the layer has no size) in canvas
+/// pixels times `surface_scale`, the output pixels per canvas pixel.
+/// Each side is clamped to `1..=max`.
+fn footprint(
+    size: Vec2,
+    scale: Vec3,
+    texture: [u32; 2],
+    surface_scale: Vec2,
+    max: u32,
+) -> [u32; 2] {
+    let size = if size.x > 0.0 && size.y > 0.0 {
+        size
+    } else {
+        Vec2::new(texture[0] as f32, texture[1] as f32)
+    };
+    let scaled = size * scale.truncate().abs() * surface_scale;
+    [scaled.x, scaled.y].map(|side| (side.round() as u32).clamp(1, max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_inputs_of_layout() {
+        let layout = |names: &[&str]| {
+            let decls: Vec<(String, String)> = names
+                .iter()
+                .map(|n| (n.to_string(), "vec4".to_string()))
+                .collect();
+            FrameInputs::of_layout(&UniformLayout::new(&decls))
+        };
+        assert_eq!(
+            layout(&["g_ModelViewProjectionMatrix", "g_Texture0Resolution"]),
+            FrameInputs::default()
+        );
+        assert_eq!(
+            layout(&["g_Time", "g_AudioSpectrum16Left"]),
+            FrameInputs {
+                time: true,
+                cursor: false,
+                audio: true,
+            }
+        );
+        let cursor = layout(&["g_ParallaxPosition"]);
+        assert!(cursor.cursor && !cursor.time);
+        assert!(cursor.union(layout(&["g_Time"])).time);
+    }
+
+    #[test]
+    fn test_footprint() {
+        let scale = Vec3::new(0.5, -0.25, 1.0);
+        assert_eq!(footprint(Vec2::new(1920.0, 1080.0), scale, [64, 64], Vec2::ONE, 8192), [960, 270]);
+        assert_eq!(footprint(Vec2::ZERO, Vec3::ONE, [300, 200], Vec2::ONE, 8192), [300, 200]);
+        assert_eq!(footprint(Vec2::new(4000.0, 10.0), Vec3::new(4.0, 0.0, 1.0), [1, 1], Vec2::ONE, 8192), [8192, 1]);
+        // A 1080p scene on a 4K output.
+        let uhd = footprint(Vec2::new(1920.0, 1080.0), Vec3::ONE, [64, 64], Vec2::splat(2.0), 8192);
+        assert_eq!(uhd, [3840, 2160]);
+    }
+}
@@ -0,0 +1,67 @@
+//! Uniform blocks of every effect step in one buffer.
+//!
+//! Each step with uniforms gets its own range of a single buffer, aligned
+//! for binding, so a frame's uniforms are filled into one staging vector
+//! and uploaded with one `write_buffer`.
+
+use std::{cell::RefCell, num::NonZeroU64};
+
+use wgpu::*;
+
+use super::draw::DrawObject;
+
+pub struct EffectUniforms {
+    /// `None` when no step has uniforms.
+    buffer: Option<Buffer>,
+    /// Reused between frames.
+    staging: RefCell<Vec<u8>>,
+}
+
+impl EffectUniforms {
+    /// Assign every step with uniforms its offset and create the buffer.
+    pub fn new(device: &Device, objects: &mut [DrawObject]) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let mut size: u64 = 0;
+        for step in objects.iter_mut().flat_map(|o| o.effect_steps.iter_mut()) {
+            if step.pipedata.layout.uniform_decls.is_empty() {
+                continue;
+            }
+            let offset = size.next_multiple_of(alignment);
+            step.bindgroup.uniform_offset = Some(offset);
+            size = offset + step.bindgroup.uniform_layout.total_size();
+        }
+
+        let buffer = (size > 0).then(|| {
+            device.create_buffer(&BufferDescriptor {
+                label: Some("effect uniforms"),
+                size,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        });
+        log::debug!("effect uniforms: {} bytes", size);
+        Self {
+            buffer,
+            staging: RefCell::new(vec![0; size as usize]),
+        }
+    }
+
+    /// The range of the buffer holding the block at `offset`.
+    pub fn binding(&self, offset: u64, size: u64) -> Option<BindingResource<'_>> {
+        Some(BindingResource::Buffer(BufferBinding {
+            buffer: self.buffer.as_ref()?,
+            offset,
+            size: NonZeroU64::new(size),
+        }))
+    }
+
+    /// Fill the staging copy of the buffer with `fill`, then upload it.
+    pub fn write(&self, queue: &Queue, fill: impl FnOnce(&mut [u8])) {
+        let Some(buffer) = self.buffer.as_ref() else {
+            return;
+        };
+        let mut staging = self.staging.borrow_mut();
+        fill(&mut staging);
+        queue.write_buffer(buffer, 0, &staging);
+    }
+}
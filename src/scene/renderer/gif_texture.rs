@@ -0,0 +1,157 @@
+//! Animated GIF layer textures.
+//!
+//! All frames are uploaded once into a texture array and the current one
+//! is copied into the layer texture on the GPU when it changes.  GIFs too
+//! large for [`RESIDENT_BUDGET`] (or for the array layer limit) are
+//! decoded on demand instead, by a [`GifStream`], and each frame is
+//! uploaded as it comes due.
+
+use std::cell::{Cell, RefCell};
+
+use wgpu::*;
+
+use crate::scene::video::gif::{GifFrames, GifStream};
+
+use super::color::COLOR_FORMAT;
+
+/// Most bytes of GIF frames kept resident on the GPU per layer.
+const RESIDENT_BUDGET: u64 = 64 << 20;
+
+enum FrameSource {
+    /// Every frame, one array layer each.
+    Resident(Texture),
+    /// Frames decoded and uploaded on demand.
+    Streamed(RefCell<GifStream>),
+}
+
+pub struct GifTexture {
+    /// The texture the layer samples; holds the current frame.
+    pub texture: Texture,
+    source: FrameSource,
+    /// Frame delays; its frames, if any, have been moved into `source`.
+    timing: GifFrames,
+    current: Cell<Option<usize>>,
+}
+
+impl GifTexture {
+    /// Decode a GIF payload.  `None` when it is not a GIF or has no
+    /// decodable frames.
+    pub fn open(device: &Device, queue: &Queue, payload: &[u8]) -> Option<Self> {
+        let probed = GifFrames::probe(payload)?;
+        let [width, height] = probed.size;
+        let layers = probed.delays.len() as u32;
+        let bytes = width as u64 * height as u64 * 4 * layers as u64;
+
+        let (mut gif, source) =
+            if bytes <= RESIDENT_BUDGET && layers <= device.limits().max_texture_array_layers {
+                let mut gif = GifFrames::decode(payload)?;
+                let frames = std::mem::take(&mut gif.frames);
+                let array = create_texture(
+                    device,
+                    [width, height],
+                    frames.len() as u32,
+                    TextureUsages::COPY_SRC,
+                );
+                for (layer, frame) in frames.iter().enumerate() {
+                    write_frame(queue, &array, layer as u32, frame);
+                }
+                (gif, FrameSource::Resident(array))
+            } else {
+                log::debug!("gif: {} frames ({} bytes) streamed", layers, bytes);
+                let stream = GifStream::spawn(payload.to_vec(), &probed)?;
+                (probed, FrameSource::Streamed(RefCell::new(stream)))
+            };
+        gif.frames = Vec::new();
+        let texture = create_texture(device, [width, height], 1, TextureUsages::TEXTURE_BINDING);
+
+        let gif = Self {
+            texture,
+            source,
+            timing: gif,
+            current: Cell::new(None),
+        };
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+        gif.update(&mut encoder, queue, 0.0);
+        queue.submit(Some(encoder.finish()));
+        Some(gif)
+    }
+
+    /// Show the frame due `seconds` into the animation.  Resident frames
+    /// are copied by `encoder`.
+    pub fn update(&self, encoder: &mut CommandEncoder, queue: &Queue, seconds: f32) {
+        let index = self.timing.frame_index(seconds);
+        if self.current.get() == Some(index) {
+            return;
+        }
+
+        match &self.source {
+            FrameSource::Resident(array) => {
+                encoder.copy_texture_to_texture(
+                    TexelCopyTextureInfo {
+                        texture: array,
+                        mip_level: 0,
+                        origin: Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: index as u32,
+                        },
+                        aspect: TextureAspect::All,
+                    },
+                    self.texture.as_image_copy(),
+                    self.texture.size(),
+                );
+            }
+            FrameSource::Streamed(stream) => {
+                // Until the frame is decoded the previous one stays up.
+                let Some(rgba) = stream.borrow_mut().frame(index) else {
+                    return;
+                };
+                write_frame(queue, &self.texture, 0, &rgba);
+            }
+        }
+        self.current.set(Some(index));
+    }
+}
+
+fn create_texture(device: &Device, size: [u32; 2], layers: u32, usage: TextureUsages) -> Texture {
+    device.create_texture(&TextureDescriptor {
+        label: Some("gif texture"),
+        size: Extent3d {
+            width: size[0],
+            height: size[1],
+            depth_or_array_layers: layers,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: COLOR_FORMAT,
+        usage: usage | TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+fn write_frame(queue: &Queue, texture: &Texture, layer: u32, rgba: &[u8]) {
+    queue.write_texture(
+        TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: Origin3d {
+                x: 0,
+                y: 0,
+                z: layer,
+            },
+            aspect: TextureAspect::All,
+        },
+        rgba,
+        TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(texture.width() * 4),
+            rows_per_image: None,
+        },
+        Extent3d {
+            width: texture.width(),
+            height: texture.height(),
+            depth_or_array_layers: 1,
+        },
+    );
+}
@@ -1,27 +1,247 @@
 //! Asset loading: parses the .pkg scene, uploads textures and geometry,
-//! creates render pipelines, and builds the draw queue.
+//! creates render pipelines, and builds the draw queue.  Video wallpapers
+//! only open the video and the frame targets.
+//!
+//! Scenes load without blocking the adapter's event loop.  A worker
+//! thread reads and decodes the package while frames show the clear
+//! color; the draw queue is then built on the render thread a little per
+//! frame ([`BUILD_BUDGET`]), all layers first and their effects after, so
+//! the scene fills in as its parts become ready.  Adapters can follow
+//! along with [`WgpuApp::load_progress`].
 
-use std::{io::Cursor, path::Path};
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+    fmt,
+    rc::Rc,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use glam::{Vec2, Vec3};
 
 use crate::scene::{
+    audio::AudioPlayback,
     loader::{
-        object_loader::{AudioObject, ObjectMap, PlaybackMode},
-        scene_loader::Scene,
+        object_loader::{AudioObject, ObjectMap, PlaybackMode, TextureObject},
+        scene_loader::{ReadEvent, Scene, ScenePackage},
     },
     renderer::{
-        app::WgpuApp, draw::DrawQueue, post_process::PostProcess, projection::Projection,
-        vertex::Vertex,
+        app::WgpuApp,
+        color::COLOR_FORMAT,
+        draw::{DrawQueue, FrameInputs},
+        post_process::PostProcess,
+        projection::Projection,
+        render_targets::SceneTargets,
+        scripting::SceneScripts,
+        vertex::{self, Vertex},
+        video_wallpaper::VideoWallpaper,
     },
+    video,
 };
 
 use wgpu::*;
 
-use rodio::{OutputStream, Source};
+use rodio::Source;
+
+/// Time spent building the draw queue per frame while a scene loads.  At
+/// least one layer or effect is added per frame, however long it takes.
+const BUILD_BUDGET: Duration = Duration::from_millis(10);
+
+/// How far a scene load is, see [`WgpuApp::load_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadProgress {
+    /// `done` of the package's `total` files are read.
+    Reading { done: usize, total: usize },
+    /// `done` of `total` layers and effects are added to the draw queue.
+    Building { done: usize, total: usize },
+}
+
+impl fmt::Display for LoadProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Reading { done, total } => write!(f, "reading {}/{} files", done, total),
+            Self::Building { done, total } => write!(f, "building {}/{}", done, total),
+        }
+    }
+}
+
+/// Sent by the thread reading the package.
+pub(super) enum LoadEvent {
+    /// `scene.json` is parsed; the textures are still being decoded.
+    Parsed { size: [u32; 2], clear_color: Vec3 },
+    Progress { done: usize, total: usize },
+    Read(Box<ScenePackage>),
+}
+
+/// Cancels reading the package when dropped, so an app dropped while
+/// loading does not leave the workers decoding.
+pub(super) struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A scene being loaded, advanced by [`WgpuApp::poll_load`].
+pub(super) enum Loading {
+    /// The package is read on a worker thread.
+    Reading {
+        events: Receiver<LoadEvent>,
+        progress: LoadProgress,
+        started: Instant,
+        _cancel: CancelOnDrop,
+    },
+    /// The draw queue is being built.
+    Building(Box<SceneBuild>),
+}
+
+pub(super) struct SceneBuild {
+    scene: Scene,
+    objects: Vec<TextureObject>,
+    audios: Vec<AudioObject>,
+    scripts: Option<SceneScripts>,
+    /// Objects whose layer was added to the draw queue.
+    layers: usize,
+    /// Objects whose effects were built, after all layers were added.
+    effects: usize,
+    started: Instant,
+}
 
 impl WgpuApp {
-    /// load assets
+    /// Start loading the wallpaper.  Scenes load in the background while
+    /// [`render`](Self::render) runs; see [`is_loading`](Self::is_loading).
     pub fn load(&mut self) {
-        let mut scene = Scene::new(self.scene_path.clone());
+        if video::is_video_file(Path::new(&self.scene_path)) {
+            self.load_video();
+            return;
+        }
+
+        let path = PathBuf::from(&self.scene_path);
+        let keep_bc = self.bc_textures;
+        let cancel = Arc::new(AtomicBool::new(false));
+        let cancelled = Arc::clone(&cancel);
+        let (sender, events) = mpsc::channel();
+        let spawned = thread::Builder::new()
+            .name("scene loader".to_string())
+            .spawn(move || {
+                let package = ScenePackage::read(&path, keep_bc, &cancelled, |event| {
+                    let event = match event {
+                        ReadEvent::Parsed(root) => LoadEvent::Parsed {
+                            size: [
+                                root.general.orthogonalprojection.width as u32,
+                                root.general.orthogonalprojection.height as u32,
+                            ],
+                            clear_color: root.general.clearcolor.parse().unwrap_or_default(),
+                        },
+                        ReadEvent::Progress { done, total } => LoadEvent::Progress { done, total },
+                        ReadEvent::Failed(key, e) => {
+                            log::warn!(
+                                "cannot decode texture {}: {}, the scene loads without it",
+                                key,
+                                e
+                            );
+                            return;
+                        }
+                    };
+                    let _ = sender.send(event);
+                });
+                // Dropping the sender without a package fails the load.
+                if let Some(package) = package {
+                    let _ = sender.send(LoadEvent::Read(Box::new(package)));
+                }
+            });
+        if let Err(e) = spawned {
+            log::error!("cannot start loading '{}': {}", self.scene_path, e);
+            return;
+        }
+        self.loading = Some(Loading::Reading {
+            events,
+            progress: LoadProgress::Reading { done: 0, total: 0 },
+            started: Instant::now(),
+            _cancel: CancelOnDrop(cancel),
+        });
+    }
+
+    /// Whether a scene is still loading.
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// How far the scene load is; `None` once it is done.
+    pub fn load_progress(&self) -> Option<LoadProgress> {
+        match self.loading.as_ref()? {
+            Loading::Reading { progress, .. } => Some(*progress),
+            Loading::Building(build) => Some(LoadProgress::Building {
+                done: build.layers + build.effects,
+                total: 2 * build.objects.len(),
+            }),
+        }
+    }
+
+    /// Take what the loader thread sent, or add the next layers and
+    /// effects to the draw queue.  Called before every frame.
+    pub(super) fn poll_load(&mut self) {
+        match self.loading.take() {
+            None => {}
+            Some(Loading::Reading {
+                events,
+                mut progress,
+                started,
+                _cancel,
+            }) => loop {
+                match events.try_recv() {
+                    Ok(LoadEvent::Parsed { size, clear_color }) => {
+                        self.resolution = Some(size);
+                        self.clear_color = clear_color;
+                        self.dirty = true;
+                    }
+                    Ok(LoadEvent::Progress { done, total }) => {
+                        progress = LoadProgress::Reading { done, total };
+                    }
+                    Ok(LoadEvent::Read(package)) => {
+                        log::info!("scene read in {:.2?}", started.elapsed());
+                        let build = self.start_build(*package, started);
+                        self.loading = Some(Loading::Building(Box::new(build)));
+                        return;
+                    }
+                    Err(TryRecvError::Empty) => {
+                        self.loading = Some(Loading::Reading {
+                            events,
+                            progress,
+                            started,
+                            _cancel,
+                        });
+                        return;
+                    }
+                    // The loader gave up; the clear color stays on screen.
+                    Err(TryRecvError::Disconnected) => {
+                        log::error!("cannot load scene '{}'", self.scene_path);
+                        self.dirty = true;
+                        return;
+                    }
+                }
+            },
+            Some(Loading::Building(mut build)) => {
+                if self.build_step(&mut build) {
+                    self.loading = Some(Loading::Building(build));
+                } else {
+                    self.finish_load(*build);
+                }
+            }
+        }
+    }
+
+    /// Set up everything but the draw queue's objects: the pipelines, the
+    /// projection and the scene targets.
+    fn start_build(&mut self, package: ScenePackage, started: Instant) -> SceneBuild {
+        let mut scene = Scene::from(package);
 
         // Enable lazy-loading fallback to Wallpaper Engine assets directory.
         if let Some(ref assets_path) = self.assets_path {
@@ -29,7 +249,7 @@ impl WgpuApp {
                 "Using Wallpaper Engine assets path: {}",
                 assets_path
             );
-            scene.set_assets_path(std::path::PathBuf::from(assets_path));
+            scene.set_assets_path(PathBuf::from(assets_path));
         }
 
         let size = [
@@ -37,7 +257,8 @@ impl WgpuApp {
             scene.root.general.orthogonalprojection.height as u32,
         ];
 
-        let post_process = PostProcess::new(&self.device, &self.queue, size);
+        let post_process =
+            PostProcess::new(&self.device, &self.queue, size, Rc::clone(&self.shader_cache));
 
         self.clear_color = scene.root.general.clearcolor.parse().unwrap_or_default();
 
@@ -47,21 +268,8 @@ impl WgpuApp {
             &scene,
             self.clear_color,
         );
-        let draw_queue = DrawQueue::new(
-            &self.device,
-            &self.queue,
-            &mut self.buffers,
-            &scene,
-            objects.texture,
-            pipeline,
-            &post_process,
-            &self.projection_bindgroup.projection_layout,
-            self.no_effects,
-        );
-
-        load_audios(&self.audio_stream, objects.audio, &scene);
-
-        self.draw_queue = Some(draw_queue);
+        let scripts = SceneScripts::new(&objects.texture, &scene.user_properties);
+        self.draw_queue = Some(DrawQueue::empty(&self.device, pipeline));
 
         let camera_uniform = Projection::new(&scene.root).create_camera_uniform();
         self.projection_bindgroup.create_projection_bindgroup(
@@ -74,14 +282,151 @@ impl WgpuApp {
 
         self.resolution = Some(size);
 
+        self.targets = Some(SceneTargets::new(
+            &self.device,
+            self.surface.view_format(),
+            [self.surface.config.width, self.surface.config.height],
+            &post_process.layout,
+            &post_process.sampler,
+        ));
         self.post_process = Some(post_process);
+
+        SceneBuild {
+            scene,
+            objects: objects.texture,
+            audios: objects.audio,
+            scripts,
+            layers: 0,
+            effects: 0,
+            started,
+        }
     }
-}
 
-fn load_audios(audio_stream: &OutputStream, audios: Vec<AudioObject>, scene: &Scene) {
-    let audio_mixer = audio_stream.mixer();
-    let audio_sink = rodio::Sink::connect_new(audio_mixer);
+    /// Add layers, then effects, for up to [`BUILD_BUDGET`].  Returns
+    /// whether any are left.
+    fn build_step(&mut self, build: &mut SceneBuild) -> bool {
+        let (Some(draw_queue), Some(post_process), Some(targets)) = (
+            self.draw_queue.as_mut(),
+            self.post_process.as_ref(),
+            self.targets.as_ref(),
+        ) else {
+            return false;
+        };
+
+        let [scene_w, scene_h] = self.resolution.unwrap_or([1, 1]).map(|side| side.max(1) as f32);
+        let surface_scale = Vec2::new(
+            self.surface.config.width as f32 / scene_w,
+            self.surface.config.height as f32 / scene_h,
+        );
+
+        let deadline = Instant::now() + BUILD_BUDGET;
+        let total = build.objects.len();
+        let layers_before = build.layers;
+        while build.effects < total {
+            if build.layers < total {
+                let object = &build.objects[build.layers];
+                draw_queue.push_layer(
+                    &self.device,
+                    &self.queue,
+                    &mut self.buffers,
+                    object,
+                    post_process,
+                    surface_scale,
+                );
+                build.layers += 1;
+            } else {
+                let object = &build.objects[build.effects];
+                draw_queue.add_effects(
+                    build.effects,
+                    &self.device,
+                    &self.queue,
+                    &build.scene,
+                    object,
+                    post_process,
+                    &self.projection_bindgroup.projection_layout,
+                    self.no_effects,
+                );
+                build.effects += 1;
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+        log::debug!(
+            "loading: {}/{} layers, {}/{} effects",
+            build.layers,
+            total,
+            build.effects,
+            total
+        );
 
+        // Laying out walks the whole queue, so it runs once the layers are
+        // in and once the effects are, not after every step.
+        let layers_done = layers_before < total && build.layers == total;
+        if layers_done || build.effects == total {
+            draw_queue.layout(&self.device, &self.queue, post_process);
+            draw_queue.bind_targets(&self.device, targets, &post_process.sampler);
+        }
+        self.dirty = true;
+        build.effects < total
+    }
+
+    /// Start the scene's sounds and scripts once everything is drawn.
+    fn finish_load(&mut self, build: SceneBuild) {
+        self.shader_cache.save();
+
+        load_audios(&self.audio, build.audios, &build.scene);
+
+        let mut scripts = build.scripts;
+        if let (Some(scripts), Some(draw_queue)) = (scripts.as_mut(), self.draw_queue.as_ref()) {
+            scripts.bind_constants(draw_queue);
+        }
+        self.scripts = scripts.filter(|s| !s.is_empty());
+
+        // Scripts run every frame and may change anything.
+        let queue_inputs = self
+            .draw_queue
+            .as_ref()
+            .map(DrawQueue::frame_inputs)
+            .unwrap_or_default();
+        self.frame_inputs = queue_inputs.union(FrameInputs {
+            time: self.scripts.is_some(),
+            audio: self.scripts.as_ref().is_some_and(SceneScripts::uses_audio),
+            ..Default::default()
+        });
+        log::info!("scene frame inputs: {:?}", self.frame_inputs);
+        self.update_audio_capture();
+        log::info!("scene loaded in {:.2?}", build.started.elapsed());
+    }
+
+    /// Open a video wallpaper; its resolution is the video size.  When
+    /// it cannot be played the clear color stays on screen.
+    fn load_video(&mut self) {
+        let path = Path::new(&self.scene_path);
+        let Some(video) = VideoWallpaper::open(&self.device, path, &self.audio) else {
+            log::error!("cannot play video wallpaper '{}'", path.display());
+            self.dirty = true;
+            return;
+        };
+        let size = video.size();
+        let post_process =
+            PostProcess::new(&self.device, &self.queue, size, Rc::clone(&self.shader_cache));
+
+        self.targets = Some(SceneTargets::new(
+            &self.device,
+            self.surface.view_format(),
+            [self.surface.config.width, self.surface.config.height],
+            &post_process.layout,
+            &post_process.sampler,
+        ));
+        self.resolution = Some(size);
+        self.post_process = Some(post_process);
+        self.frame_inputs.time = true;
+        self.video = Some(video);
+    }
+}
+
+fn load_audios(playback: &AudioPlayback, audios: Vec<AudioObject>, scene: &Scene) {
     for audio in audios {
         for sound in audio.sounds {
             let Some(raw) = scene.misc.remove(&sound) else {
@@ -103,18 +448,12 @@ fn load_audios(audio_stream: &OutputStream, audios: Vec<AudioObject>, scene: &Sc
 
             match audio.playback_mode {
                 PlaybackMode::Loop => {
-                    audio_mixer.add(source.repeat_infinite());
+                    playback.add(source.repeat_infinite());
                 }
                 PlaybackMode::Others => {}
             }
         }
     }
-
-    std::thread::spawn(move || {
-        audio_sink.play();
-        audio_sink.set_volume(1.0);
-        audio_sink.sleep_until_end();
-    });
 }
 
 /// Create default rendering pipeline
@@ -144,7 +483,7 @@ fn create_pipeline(app: &WgpuApp, bindgroup_layout: &BindGroupLayout) -> RenderP
                 module: &shader,
                 entry_point: Some("vs_main"),
                 compilation_options: Default::default(),
-                buffers: &[Vertex::create_buffer_layout()],
+                buffers: &[Vertex::create_buffer_layout(), vertex::create_alpha_layout()],
             },
             primitive: PrimitiveState {
                 topology: PrimitiveTopology::TriangleList,
@@ -166,7 +505,7 @@ fn create_pipeline(app: &WgpuApp, bindgroup_layout: &BindGroupLayout) -> RenderP
                 entry_point: Some("fs_main"),
                 compilation_options: Default::default(),
                 targets: &[Some(ColorTargetState {
-                    format: app.surface.config.format,
+                    format: COLOR_FORMAT,
                     blend: Some(BlendState {
                         color: BlendComponent {
                             src_factor: BlendFactor::SrcAlpha,
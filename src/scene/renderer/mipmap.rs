@@ -0,0 +1,198 @@
+//! Mip chains for layer and effect textures.
+//!
+//! Textures with a stored chain go through [`compressed`](super::compressed)
+//! instead.  For the rest `Tex` only keeps the decoded top level, so the
+//! chain is built on the GPU: each level is drawn from the one above it
+//! with a linearly filtered full-screen triangle, i.e. a 2×2 box filter.
+
+use std::{cell::RefCell, collections::HashMap};
+
+use wgpu::*;
+
+pub struct MipGenerator {
+    shader: ShaderModule,
+    layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    sampler: Sampler,
+    /// Downsampling pipeline per render target format.
+    pipelines: RefCell<HashMap<TextureFormat, RenderPipeline>>,
+}
+
+impl MipGenerator {
+    /// `layout` is the texture + sampler layout of
+    /// [`PostProcess`](super::post_process::PostProcess).
+    pub fn new(device: &Device, layout: &BindGroupLayout) -> Self {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("mipmap"),
+            source: ShaderSource::Wgsl(include_str!("./shader/blit.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("mipmap"),
+            bind_group_layouts: &[layout],
+            immediate_size: 0,
+        });
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("mipmap"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            shader,
+            layout: layout.clone(),
+            pipeline_layout,
+            sampler,
+            pipelines: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Create a texture with a full mip chain from tightly packed
+    /// top-level pixels.
+    pub fn upload(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        format: TextureFormat,
+        size: [u32; 2],
+        data: &[u8],
+    ) -> Texture {
+        let bpp = format.block_copy_size(None).unwrap_or(4);
+        let texture = device.create_texture(&TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: level_count(size),
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            data,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(size[0] * bpp),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+        );
+        self.generate(device, queue, &texture);
+        texture
+    }
+
+    /// Fill levels 1.. of `texture` from level 0.
+    pub fn generate(&self, device: &Device, queue: &Queue, texture: &Texture) {
+        if texture.mip_level_count() < 2 {
+            return;
+        }
+        let format = texture.format();
+        let mut pipelines = self.pipelines.borrow_mut();
+        let pipeline = pipelines
+            .entry(format)
+            .or_insert_with(|| self.create_pipeline(device, format));
+
+        let level_view = |level| {
+            texture.create_view(&TextureViewDescriptor {
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+        for level in 1..texture.mip_level_count() {
+            let source = level_view(level - 1);
+            let target = level_view(level);
+            let bindgroup = device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &self.layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&source),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
+
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("mipmap"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &target,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::TRANSPARENT),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bindgroup, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+
+    fn create_pipeline(&self, device: &Device, format: TextureFormat) -> RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("mipmap"),
+            layout: Some(&self.pipeline_layout),
+            vertex: VertexState {
+                module: &self.shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &self.shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            multiview_mask: None,
+            cache: None,
+        })
+    }
+}
+
+/// Number of mip levels down to 1×1.
+pub fn level_count(size: [u32; 2]) -> u32 {
+    u32::BITS - size[0].max(size[1]).max(1).leading_zeros()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_count() {
+        assert_eq!(level_count([1, 1]), 1);
+        assert_eq!(level_count([256, 256]), 9);
+        assert_eq!(level_count([1920, 1080]), 11);
+        assert_eq!(level_count([0, 0]), 1);
+    }
+}
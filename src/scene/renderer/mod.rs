@@ -1,13 +1,24 @@
 pub mod app;
 mod buffer;
+mod color;
+mod compressed;
 pub mod draw;
 mod effect_bindgroup;
+mod effect_uniforms;
+mod gif_texture;
 mod intermediate_pass;
+mod mipmap;
 pub mod load;
 mod ping_pong;
 mod post_process;
 mod post_processor;
 mod projection;
+mod render_graph;
 pub mod render_pass;
+mod render_targets;
+mod scripting;
+mod sprite;
 mod surface;
 pub mod vertex;
+mod video_texture;
+mod video_wallpaper;
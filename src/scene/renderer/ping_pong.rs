@@ -1,8 +1,11 @@
 //! Pair of render targets for ping-pong post-processing.
 //!
 //! Effects are applied by alternating between two textures: read from
-//! one, write to the other, then swap. Also provides NDC vertex/index
-//! buffers for rendering full-screen quads in effect passes.
+//! one, write to the other, then swap. The textures come from the render
+//! graph's target pool and may be shared with other layers whose passes
+//! never overlap. Also provides NDC vertex/index buffers for full-target
+//! copies (effect passes share the index buffer), an opaque alpha for
+//! copies with the image pipeline, and bind groups sampling each view.
 
 use wgpu::*;
 
@@ -10,45 +13,29 @@ use super::post_process::PostProcess;
 use super::vertex::{NDC_VERTICES, Vertex};
 
 pub struct PingPongTextures {
-    #[allow(dead_code)]
-    tex_a: Texture,
-    #[allow(dead_code)]
-    tex_b: Texture,
     pub view_a: TextureView,
     pub view_b: TextureView,
+    /// `view_a` and `view_b` with the post-process sampler.
+    pub bindgroup_a: BindGroup,
+    pub bindgroup_b: BindGroup,
     pub ndc_vbuf: Buffer,
     pub ndc_ibuf: Buffer,
+    /// Alpha 1.0, so copies with the image pipeline keep the layer's
+    /// pixels as they are.
+    pub ndc_alpha: Buffer,
 }
 
 impl PingPongTextures {
+    /// Wrap two pooled views of the same size.
     pub fn new(
         device: &Device,
         queue: &Queue,
-        _post_process: &PostProcess,
-        width: u32,
-        height: u32,
+        post_process: &PostProcess,
+        view_a: TextureView,
+        view_b: TextureView,
     ) -> Self {
-        let make_tex = |device: &Device| {
-            device.create_texture(&TextureDescriptor {
-                label: None,
-                size: Extent3d {
-                    width,
-                    height,
-                    depth_or_array_layers: 1,
-                },
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: TextureDimension::D2,
-                format: TextureFormat::Rgba8UnormSrgb,
-                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
-                view_formats: &[],
-            })
-        };
-
-        let tex_a = make_tex(device);
-        let tex_b = make_tex(device);
-        let view_a = tex_a.create_view(&Default::default());
-        let view_b = tex_b.create_view(&Default::default());
+        let bindgroup_a = make_bindgroup(device, post_process, &view_a);
+        let bindgroup_b = make_bindgroup(device, post_process, &view_b);
 
         let ndc_vbuf = device.create_buffer(&BufferDescriptor {
             label: None,
@@ -66,37 +53,40 @@ impl PingPongTextures {
         });
         queue.write_buffer(&ndc_ibuf, 0, bytemuck::bytes_of(&[0u32, 2, 1, 0, 3, 2]));
 
+        let ndc_alpha = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: std::mem::size_of::<f32>() as u64,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&ndc_alpha, 0, bytemuck::bytes_of(&1.0f32));
+
         Self {
-            tex_a,
-            tex_b,
             view_a,
             view_b,
+            bindgroup_a,
+            bindgroup_b,
             ndc_vbuf,
             ndc_ibuf,
+            ndc_alpha,
         }
     }
+}
 
-    /// Create a bind group from the given view (used for both final and intermediate passes).
-    pub fn make_bindgroup(
-        &self,
-        device: &Device,
-        layout: &BindGroupLayout,
-        sampler: &Sampler,
-        view: &TextureView,
-    ) -> BindGroup {
-        device.create_bind_group(&BindGroupDescriptor {
-            label: None,
-            layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(view),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(sampler),
-                },
-            ],
-        })
-    }
+/// Bind group sampling `view` (used for both final and intermediate passes).
+fn make_bindgroup(device: &Device, post_process: &PostProcess, view: &TextureView) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout: &post_process.layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(&post_process.sampler),
+            },
+        ],
+    })
 }
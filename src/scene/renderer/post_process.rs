@@ -1,13 +1,21 @@
+use std::rc::Rc;
+
 use wgpu::*;
 
+use super::{color::COLOR_FORMAT, mipmap::MipGenerator, post_processor::shader_cache::ShaderCache};
+
 pub struct PostProcess {
     pub sampler: Sampler,
     pub layout: BindGroupLayout,
     pub blank_texture: Texture,
+    /// Builds the mip chains of uploaded textures.
+    pub mipmaps: MipGenerator,
+    /// Translated shaders and compiled pipelines from earlier launches.
+    pub shaders: Rc<ShaderCache>,
 }
 
 impl PostProcess {
-    pub fn new(device: &Device, queue: &Queue, res: [u32; 2]) -> Self {
+    pub fn new(device: &Device, queue: &Queue, res: [u32; 2], shaders: Rc<ShaderCache>) -> Self {
         let sampler = device.create_sampler(&SamplerDescriptor {
             label: None,
             address_mode_u: AddressMode::ClampToEdge,
@@ -15,7 +23,7 @@ impl PostProcess {
             address_mode_w: AddressMode::ClampToEdge,
             mag_filter: FilterMode::Linear,
             min_filter: FilterMode::Linear,
-            mipmap_filter: MipmapFilterMode::Nearest,
+            mipmap_filter: MipmapFilterMode::Linear,
             ..Default::default()
         });
 
@@ -51,7 +59,7 @@ impl PostProcess {
             mip_level_count: 1,
             sample_count: 1,
             dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba8UnormSrgb,
+            format: COLOR_FORMAT,
             usage: TextureUsages::RENDER_ATTACHMENT
                 | TextureUsages::TEXTURE_BINDING
                 | TextureUsages::COPY_DST,
@@ -80,10 +88,14 @@ impl PostProcess {
             },
         );
 
+        let mipmaps = MipGenerator::new(device, &layout);
+
         Self {
             sampler,
             layout,
             blank_texture,
+            mipmaps,
+            shaders,
         }
     }
 }
@@ -1,14 +1,52 @@
 //! GPU uniform buffer layout and per-frame value population.
 //!
 //! Parses shader uniform declarations into an [`UniformLayout`] (offsets
-//! + sizes), then fills and uploads the buffer each frame with system
-//! values (time, projection, cursor) and material constants.
+//! + sizes), then fills each step's block of the per-frame staging buffer
+//! with system values (time, matrices, cursor) and material constants.
 
 use std::collections::BTreeMap;
 
+use glam::{Mat4, Quat, Vec2, Vec3};
+
+use crate::scene::{audio::AudioSpectrum, renderer::draw::LayerTransform};
+
+/// Scalar type underlying a GLSL uniform type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Component {
+    Float,
+    Int,
+    Uint,
+    /// Stored as a 32-bit 0/1 under std140.
+    Bool,
+}
+
+impl Component {
+    fn of(ty: &str) -> Self {
+        match ty {
+            "int" | "ivec2" | "ivec3" | "ivec4" => Component::Int,
+            "uint" | "uvec2" | "uvec3" | "uvec4" => Component::Uint,
+            "bool" | "bvec2" | "bvec3" | "bvec4" => Component::Bool,
+            _ => Component::Float,
+        }
+    }
+}
+
+/// Placement of one uniform in the std140 block.
+#[derive(Debug, Clone, Copy)]
+struct UniformEntry {
+    component: Component,
+    offset: u64,
+    /// Size of a single element.
+    size: u64,
+    /// Distance between array elements (16-byte aligned under std140).
+    stride: u64,
+    /// Element count, 1 for non-arrays.
+    len: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct UniformLayout {
-    offsets: BTreeMap<String, (u64, u64)>,
+    offsets: BTreeMap<String, UniformEntry>,
     total_size: u64,
 }
 
@@ -18,10 +56,37 @@ impl UniformLayout {
         let mut offset: u64 = 0;
 
         for (name, ty) in decls {
-            offset = align_up(offset, type_align(ty));
-            let size = type_size(ty);
-            offsets.insert(name.clone(), (offset, size));
-            offset += size;
+            let Some((base, array_len)) = split_array_type(ty) else {
+                log::warn!("uniform '{}': unresolved array length in '{}', skipped", name, ty);
+                continue;
+            };
+            let size = type_size(base);
+            let entry = match array_len {
+                // std140: array elements are padded to a multiple of vec4
+                // and the array itself is vec4-aligned.
+                Some(len) => {
+                    offset = align_up(offset, align_up(type_align(base), 16));
+                    UniformEntry {
+                        component: Component::of(base),
+                        offset,
+                        size,
+                        stride: align_up(size, 16),
+                        len,
+                    }
+                }
+                None => {
+                    offset = align_up(offset, type_align(base));
+                    UniformEntry {
+                        component: Component::of(base),
+                        offset,
+                        size,
+                        stride: size,
+                        len: 1,
+                    }
+                }
+            };
+            offsets.insert(name.clone(), entry);
+            offset += entry.stride * entry.len;
         }
 
         let total_size = align_up(offset, 16).max(16);
@@ -35,23 +100,65 @@ impl UniformLayout {
         self.total_size
     }
 
+    /// Names of all uniforms in the layout.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.offsets.keys().map(String::as_str)
+    }
+
     /// Write a value into the uniform buffer at the offset for `name`.
     /// Returns false if `name` is not in the layout (no-op for optional uniforms).
     fn write(&self, buf: &mut [u8], name: &str, data: &[u8]) -> bool {
-        if let Some(&(off, size)) = self.offsets.get(name) {
-            let end = off as usize + size as usize;
-            if end <= buf.len() && data.len() == size as usize {
-                buf[off as usize..end].copy_from_slice(data);
-                return true;
-            }
+        match self.offsets.get(name) {
+            Some(entry) if entry.len == 1 => write_at(buf, entry.offset, entry.size, data),
+            _ => false,
         }
-        false
+    }
+
+    /// Write consecutive elements of a scalar or vector array uniform,
+    /// one element's worth of components from `data` per element.  Extra
+    /// values are ignored; missing ones keep their previous contents.
+    fn write_array(&self, buf: &mut [u8], name: &str, component: Component, data: &[u8]) -> bool {
+        let Some(entry) = self.offsets.get(name) else {
+            return false;
+        };
+        // Matrix columns are padded, so their components are not packed.
+        if entry.component != component || entry.size > 16 {
+            return false;
+        }
+        let element_size = entry.size as usize;
+        let mut written = false;
+        for (i, chunk) in data.chunks_exact(element_size).take(entry.len as usize).enumerate() {
+            written |= write_at(buf, entry.offset + i as u64 * entry.stride, entry.size, chunk);
+        }
+        written
     }
 
     pub fn write_f32(&self, buf: &mut [u8], name: &str, value: f32) -> bool {
         self.write(buf, name, &value.to_le_bytes())
     }
 
+    /// Write a `float[N]` … `vec4[N]` uniform from the components of its
+    /// elements, one after the other (each element padded to 16 bytes).
+    pub fn write_f32_slice(&self, buf: &mut [u8], name: &str, values: &[f32]) -> bool {
+        self.write_array(buf, name, Component::Float, bytemuck::cast_slice(values))
+    }
+
+    /// Write an `int[N]` … `ivec4[N]` uniform, as [`write_f32_slice`](Self::write_f32_slice).
+    pub fn write_i32_slice(&self, buf: &mut [u8], name: &str, values: &[i32]) -> bool {
+        self.write_array(buf, name, Component::Int, bytemuck::cast_slice(values))
+    }
+
+    /// Write a `uint[N]` … `uvec4[N]` uniform, as [`write_f32_slice`](Self::write_f32_slice).
+    pub fn write_u32_slice(&self, buf: &mut [u8], name: &str, values: &[u32]) -> bool {
+        self.write_array(buf, name, Component::Uint, bytemuck::cast_slice(values))
+    }
+
+    /// Write a `bool[N]` … `bvec4[N]` uniform, one 32-bit 0/1 per component.
+    pub fn write_bool_slice(&self, buf: &mut [u8], name: &str, values: &[bool]) -> bool {
+        let values: Vec<u32> = values.iter().map(|&b| u32::from(b)).collect();
+        self.write_array(buf, name, Component::Bool, bytemuck::cast_slice(&values))
+    }
+
     pub fn write_vec2(&self, buf: &mut [u8], name: &str, value: [f32; 2]) -> bool {
         self.write(buf, name, bytemuck::bytes_of(&value))
     }
@@ -64,6 +171,47 @@ impl UniformLayout {
         self.write(buf, name, bytemuck::bytes_of(&value))
     }
 
+    pub fn write_i32(&self, buf: &mut [u8], name: &str, value: i32) -> bool {
+        self.write(buf, name, &value.to_le_bytes())
+    }
+
+    pub fn write_u32(&self, buf: &mut [u8], name: &str, value: u32) -> bool {
+        self.write(buf, name, &value.to_le_bytes())
+    }
+
+    /// GLSL `bool` occupies 4 bytes in std140 (0 or 1).
+    pub fn write_bool(&self, buf: &mut [u8], name: &str, value: bool) -> bool {
+        self.write_u32(buf, name, value as u32)
+    }
+
+    /// Write an `ivec2`/`ivec3`/`ivec4`.
+    pub fn write_ivec<const N: usize>(&self, buf: &mut [u8], name: &str, value: [i32; N]) -> bool {
+        self.write(buf, name, bytemuck::cast_slice(&value))
+    }
+
+    /// Write a `uvec2`/`uvec3`/`uvec4`.
+    pub fn write_uvec<const N: usize>(&self, buf: &mut [u8], name: &str, value: [u32; N]) -> bool {
+        self.write(buf, name, bytemuck::cast_slice(&value))
+    }
+
+    /// Write a `bvec2`/`bvec3`/`bvec4`, one 32-bit 0/1 per component.
+    pub fn write_bvec<const N: usize>(&self, buf: &mut [u8], name: &str, value: [bool; N]) -> bool {
+        self.write_uvec(buf, name, value.map(u32::from))
+    }
+
+    /// Write a mat3 in column-major order.  Under std140 each column is
+    /// padded to a vec4, so the matrix occupies 48 bytes.
+    pub fn write_mat3(&self, buf: &mut [u8], name: &str, value: &[[f32; 3]; 3]) -> bool {
+        let mut flat = [0u8; 48];
+        for (col, row_data) in value.iter().enumerate() {
+            for (row, &v) in row_data.iter().enumerate() {
+                let idx = col * 16 + row * 4;
+                flat[idx..idx + 4].copy_from_slice(&v.to_le_bytes());
+            }
+        }
+        self.write(buf, name, &flat)
+    }
+
     /// Write a mat4 in column-major order (GLSL convention).
     pub fn write_mat4(&self, buf: &mut [u8], name: &str, value: &[[f32; 4]; 4]) -> bool {
         // Flatten column-major: value[col][row]
@@ -87,13 +235,13 @@ impl UniformLayout {
         constants: &BTreeMap<String, serde_json::Value>,
         material_keys: &BTreeMap<String, String>,
         time: f32,
-        projection: &[[f32; 4]; 4],
         sys: &SystemUniforms,
     ) {
         self.write_all_defaults(buf);
 
+        let obj = &sys.object;
         self.write_f32(buf, "g_Time", time);
-        self.write_mat4(buf, "g_ModelViewProjectionMatrix", projection);
+        self.write_mat4(buf, "g_ModelViewProjectionMatrix", &obj.pass.to_cols_array_2d());
 
         self.write(
             buf,
@@ -105,24 +253,49 @@ impl UniformLayout {
             ]),
         );
 
-        self.write_mat4(buf, "g_EffectTextureProjectionMatrix", projection);
+        self.write_mat4(
+            buf,
+            "g_ModelViewProjectionMatrixInverse",
+            &obj.pass.inverse().to_cols_array_2d(),
+        );
+        let texture_projection = obj.view_projection * obj.model;
+        self.write_mat4(
+            buf,
+            "g_EffectTextureProjectionMatrix",
+            &texture_projection.to_cols_array_2d(),
+        );
         self.write_mat4(
             buf,
             "g_EffectTextureProjectionMatrixInverse",
-            &[
-                [1.0, 0.0, 0.0, 0.0],
-                [0.0, 1.0, 0.0, 0.0],
-                [0.0, 0.0, 1.0, 0.0],
-                [0.0, 0.0, 0.0, 1.0],
-            ],
+            &texture_projection.inverse().to_cols_array_2d(),
         );
 
+        self.write_mat4(buf, "g_ModelMatrix", &obj.model.to_cols_array_2d());
+        self.write_mat4(buf, "g_ModelMatrixInverse", &obj.model.inverse().to_cols_array_2d());
+        self.write_mat4(
+            buf,
+            "g_ViewProjectionMatrix",
+            &obj.view_projection.to_cols_array_2d(),
+        );
+        self.write_mat4(
+            buf,
+            "g_ViewProjectionMatrixInverse",
+            &obj.view_projection.inverse().to_cols_array_2d(),
+        );
+        self.write_vec4(buf, "g_Texture0Rotation", obj.texture_rotation);
+        self.write_vec2(buf, "g_Texture0Translation", obj.texture_translation);
+
         self.write_vec2(buf, "g_ParallaxPosition", sys.cursor_position);
 
-        for (name, res) in &sys.tex_resolutions {
+        for (name, res) in sys.tex_resolutions {
             self.write_vec4(buf, name, *res);
         }
 
+        // g_AudioSpectrum{16,32,64}{Left,Right}
+        for (name, bands) in sys.audio_spectrum.uniforms() {
+            self.write_f32_slice(buf, &name, bands);
+        }
+
         for (material_key, value) in constants {
             let uniform_name = material_keys
                 .get(material_key)
@@ -139,81 +312,226 @@ impl UniformLayout {
             };
             let value = resolved.as_ref().unwrap_or(value);
 
-            match value {
-                serde_json::Value::Number(n) => {
-                    if let Some(v) = n.as_f64() {
-                        let _ = self.write_f32(buf, &uniform_name, v as f32);
-                    }
-                }
-                serde_json::Value::String(s) => {
-                    let parts: Vec<f32> = s
-                        .split_whitespace()
-                        .filter_map(|p| p.parse::<f32>().ok())
-                        .collect();
-                    match parts.len() {
-                        2 => {
-                            let _ = self.write_vec2(buf, &uniform_name, [parts[0], parts[1]]);
-                        }
-                        3 => {
-                            let _ = self.write_vec3(
-                                buf,
-                                &uniform_name,
-                                [parts[0], parts[1], parts[2]],
-                            );
-                        }
-                        4 => {
-                            let _ = self.write_vec4(
-                                buf,
-                                &uniform_name,
-                                [parts[0], parts[1], parts[2], parts[3]],
-                            );
-                        }
-                        _ => {}
-                    }
-                }
-                _ => {}
-            }
+            let parts: Vec<f64> = match value {
+                serde_json::Value::Number(n) => n.as_f64().into_iter().collect(),
+                serde_json::Value::Bool(b) => vec![*b as u8 as f64],
+                serde_json::Value::String(s) => s
+                    .split_whitespace()
+                    .filter_map(|p| p.parse::<f64>().ok())
+                    .collect(),
+                _ => continue,
+            };
+            let _ = self.write_components(buf, &uniform_name, &parts);
+        }
+    }
+
+    /// Write a material value given as a list of numbers, packed according
+    /// to the uniform's declared type (float, int, uint or bool components;
+    /// 9 values fill a mat3 and 16 a mat4).  Arrays take the components of
+    /// their elements one after the other.
+    fn write_components(&self, buf: &mut [u8], name: &str, values: &[f64]) -> bool {
+        let Some(entry) = self.offsets.get(name) else {
+            return false;
+        };
+        let f = |i: usize| values[i] as f32;
+        let i = |i: usize| values[i].round() as i32;
+        let u = |i: usize| values[i].round().max(0.0) as u32;
+        let b = |i: usize| values[i] != 0.0;
+
+        if entry.len > 1 {
+            let all = 0..values.len();
+            return match entry.component {
+                Component::Float => self.write_f32_slice(buf, name, &all.map(f).collect::<Vec<_>>()),
+                Component::Int => self.write_i32_slice(buf, name, &all.map(i).collect::<Vec<_>>()),
+                Component::Uint => self.write_u32_slice(buf, name, &all.map(u).collect::<Vec<_>>()),
+                Component::Bool => self.write_bool_slice(buf, name, &all.map(b).collect::<Vec<_>>()),
+            };
+        }
+
+        match (entry.component, values.len()) {
+            (Component::Float, 1) => self.write_f32(buf, name, f(0)),
+            (Component::Float, 2) => self.write_vec2(buf, name, [f(0), f(1)]),
+            (Component::Float, 3) => self.write_vec3(buf, name, [f(0), f(1), f(2)]),
+            (Component::Float, 4) => self.write_vec4(buf, name, [f(0), f(1), f(2), f(3)]),
+            (Component::Float, 9) => self.write_mat3(
+                buf,
+                name,
+                &[[f(0), f(1), f(2)], [f(3), f(4), f(5)], [f(6), f(7), f(8)]],
+            ),
+            (Component::Float, 16) => self.write_mat4(
+                buf,
+                name,
+                &[
+                    [f(0), f(1), f(2), f(3)],
+                    [f(4), f(5), f(6), f(7)],
+                    [f(8), f(9), f(10), f(11)],
+                    [f(12), f(13), f(14), f(15)],
+                ],
+            ),
+            (Component::Int, 1) => self.write_i32(buf, name, i(0)),
+            (Component::Int, 2) => self.write_ivec(buf, name, [i(0), i(1)]),
+            (Component::Int, 3) => self.write_ivec(buf, name, [i(0), i(1), i(2)]),
+            (Component::Int, 4) => self.write_ivec(buf, name, [i(0), i(1), i(2), i(3)]),
+            (Component::Uint, 1) => self.write_u32(buf, name, u(0)),
+            (Component::Uint, 2) => self.write_uvec(buf, name, [u(0), u(1)]),
+            (Component::Uint, 3) => self.write_uvec(buf, name, [u(0), u(1), u(2)]),
+            (Component::Uint, 4) => self.write_uvec(buf, name, [u(0), u(1), u(2), u(3)]),
+            (Component::Bool, 1) => self.write_bool(buf, name, b(0)),
+            (Component::Bool, 2) => self.write_bvec(buf, name, [b(0), b(1)]),
+            (Component::Bool, 3) => self.write_bvec(buf, name, [b(0), b(1), b(2)]),
+            (Component::Bool, 4) => self.write_bvec(buf, name, [b(0), b(1), b(2), b(3)]),
+            _ => false,
         }
     }
 }
 
-pub struct SystemUniforms {
+/// Per-object transforms, shared by all effect steps of one object.
+#[derive(Debug, Clone, Copy)]
+pub struct ObjectUniforms {
+    /// Draws the effect quad, given in pass space (the layer's texture
+    /// size centred on the origin), over the whole effect target
+    /// (`g_ModelViewProjectionMatrix`).
+    pub pass: Mat4,
+    /// Maps the effect quad to the layer's placement in scene space
+    /// (`g_ModelMatrix`).  Followed by the camera, it gives the layer's
+    /// footprint on screen (`g_EffectTextureProjectionMatrix`).
+    pub model: Mat4,
+    /// Scene camera (`g_ViewProjectionMatrix`).
+    pub view_projection: Mat4,
+    /// Texture coordinate transform as a column-major mat2
+    /// (`g_Texture0Rotation`) plus offset (`g_Texture0Translation`).
+    pub texture_rotation: [f32; 4],
+    pub texture_translation: [f32; 2],
+}
+
+/// [`ObjectUniforms`] texture transform of a texture shown whole.
+pub const TEXTURE_IDENTITY: ([f32; 4], [f32; 2]) = ([1.0, 0.0, 0.0, 1.0], [0.0, 0.0]);
+
+impl ObjectUniforms {
+    /// `texture_size` sets the pass space of the effect quad, see
+    /// [`pass_vertices`]; `texture_transform` is the rotation and
+    /// translation of the layer's texture coordinates, see
+    /// [`DrawObject::texture_transform`].
+    ///
+    /// [`pass_vertices`]: crate::scene::renderer::vertex::pass_vertices
+    /// [`DrawObject::texture_transform`]: crate::scene::renderer::draw::DrawObject::texture_transform
+    pub fn new(
+        transform: &LayerTransform,
+        texture_size: [u32; 2],
+        texture_transform: ([f32; 4], [f32; 2]),
+        view_projection: &[[f32; 4]; 4],
+    ) -> Self {
+        let half_texture = Vec2::new(texture_size[0] as f32, texture_size[1] as f32) / 2.0;
+        let pass = Mat4::orthographic_rh(
+            -half_texture.x,
+            half_texture.x,
+            -half_texture.y,
+            half_texture.y,
+            -1.0,
+            1.0,
+        );
+
+        let half_extent = transform.size * transform.scale.truncate() / 2.0;
+        // Same placement as the object's quad in the vertex buffer, once
+        // the pass space is brought to [-1, 1].
+        let model = Mat4::from_scale_rotation_translation(
+            half_extent.extend(1.0),
+            Quat::from_rotation_z(transform.angles.z),
+            Vec3::new(transform.origin.x, transform.origin.y, transform.origin.z - 1.0),
+        ) * Mat4::from_scale(half_texture.recip().extend(1.0));
+
+        let (texture_rotation, texture_translation) = texture_transform;
+        Self {
+            pass,
+            model,
+            view_projection: Mat4::from_cols_array_2d(view_projection),
+            texture_rotation,
+            texture_translation,
+        }
+    }
+}
+
+/// Borrows the per-step and per-frame values, so filling the uniforms
+/// of every step does not copy them.
+pub struct SystemUniforms<'a> {
     pub screen_resolution: [u32; 2],
-    pub tex_resolutions: BTreeMap<String, [f32; 4]>,
+    pub tex_resolutions: &'a BTreeMap<String, [f32; 4]>,
     /// Normalized cursor position in [0, 1] range, (0,0) = top-left (UV space)
     pub cursor_position: [f32; 2],
+    pub audio_spectrum: &'a AudioSpectrum,
+    pub object: ObjectUniforms,
 }
 
-impl SystemUniforms {
+impl SystemUniforms<'static> {
     #[cfg(test)]
     pub fn with_resolution(res: [u32; 2]) -> Self {
+        static NO_TEXTURES: BTreeMap<String, [f32; 4]> = BTreeMap::new();
+        static SILENCE: std::sync::LazyLock<AudioSpectrum> =
+            std::sync::LazyLock::new(AudioSpectrum::default);
         SystemUniforms {
             screen_resolution: res,
-            tex_resolutions: BTreeMap::new(),
+            tex_resolutions: &NO_TEXTURES,
             cursor_position: [0.0, 0.0],
+            audio_spectrum: &SILENCE,
+            object: ObjectUniforms {
+                pass: Mat4::IDENTITY,
+                model: Mat4::IDENTITY,
+                view_projection: Mat4::IDENTITY,
+                texture_rotation: TEXTURE_IDENTITY.0,
+                texture_translation: TEXTURE_IDENTITY.1,
+            },
         }
     }
 }
 
+/// Split a declared uniform type into its element type and array length:
+/// `"float[16]"` → `Some(("float", Some(16)))`, `"vec4"` →
+/// `Some(("vec4", None))`.  `None` for an array whose length is not a
+/// number (`"float[COUNT]"`), whose layout cannot be known.
+pub fn split_array_type(ty: &str) -> Option<(&str, Option<u64>)> {
+    let Some((base, rest)) = ty.split_once('[') else {
+        return Some((ty, None));
+    };
+    match rest.trim_end_matches(']').parse::<u64>() {
+        Ok(len) if len > 0 => Some((base, Some(len))),
+        _ => None,
+    }
+}
+
+fn write_at(buf: &mut [u8], offset: u64, size: u64, data: &[u8]) -> bool {
+    let end = offset as usize + size as usize;
+    if end <= buf.len() && data.len() == size as usize {
+        buf[offset as usize..end].copy_from_slice(data);
+        return true;
+    }
+    false
+}
+
 fn align_up(val: u64, align: u64) -> u64 {
     (val + align - 1) & !(align - 1)
 }
 
+/// std140 base alignment.  Integer and bool vectors pack like their
+/// float counterparts.
 fn type_align(ty: &str) -> u64 {
     match ty {
-        "mat4" | "mat3" | "vec4" | "vec3" => 16,
-        "vec2" => 8,
+        "mat4" | "mat3" | "mat2" => 16,
+        "vec4" | "ivec4" | "uvec4" | "bvec4" => 16,
+        "vec3" | "ivec3" | "uvec3" | "bvec3" => 16,
+        "vec2" | "ivec2" | "uvec2" | "bvec2" => 8,
         _ => 4,
     }
 }
 
+/// std140 size.  Matrix columns are padded to a vec4.
 fn type_size(ty: &str) -> u64 {
     match ty {
         "mat4" => 64,
         "mat3" => 48,
-        "vec4" => 16,
-        "vec3" => 12,
-        "vec2" => 8,
+        "mat2" => 32,
+        "vec4" | "ivec4" | "uvec4" | "bvec4" => 16,
+        "vec3" | "ivec3" | "uvec3" | "bvec3" => 12,
+        "vec2" | "ivec2" | "uvec2" | "bvec2" => 8,
         _ => 4,
     }
 }
@@ -246,12 +564,6 @@ mod tests {
             &BTreeMap::new(),
             &BTreeMap::new(),
             1.5,
-            &[
-                [1.0, 0.0, 0.0, 0.0],
-                [0.0, 1.0, 0.0, 0.0],
-                [0.0, 0.0, 1.0, 0.0],
-                [0.0, 0.0, 0.0, 1.0],
-            ],
             &sys,
         );
 
@@ -268,4 +580,256 @@ mod tests {
             ]
         ));
     }
+
+    fn read_u32(buf: &[u8], at: u64) -> u32 {
+        let at = at as usize;
+        u32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+    }
+
+    fn read_f32(buf: &[u8], at: u64) -> f32 {
+        f32::from_bits(read_u32(buf, at))
+    }
+
+    #[test]
+    fn test_uniform_writers_int() {
+        let decls = vec![
+            ("g_Mode".to_string(), "int".to_string()),
+            ("g_Grid".to_string(), "ivec3".to_string()),
+            ("g_Cells".to_string(), "ivec2".to_string()),
+        ];
+        let layout = UniformLayout::new(&decls);
+        assert_eq!(layout.offsets["g_Grid"].offset, 16);
+        assert_eq!(layout.offsets["g_Cells"].offset, 32);
+
+        let mut buf = vec![0u8; layout.total_size() as usize];
+        assert!(layout.write_i32(&mut buf, "g_Mode", -3));
+        assert!(layout.write_ivec(&mut buf, "g_Grid", [1, -2, 3]));
+        assert!(layout.write_ivec(&mut buf, "g_Cells", [7, 8]));
+        assert!(!layout.write_ivec(&mut buf, "g_Cells", [7, 8, 9]));
+        assert_eq!(read_u32(&buf, 0) as i32, -3);
+        assert_eq!(read_u32(&buf, 20) as i32, -2);
+        assert_eq!(read_u32(&buf, 36), 8);
+
+        // Material values land as integers, not f32 bit patterns.
+        let constants = BTreeMap::from([
+            ("mode".to_string(), serde_json::json!(2)),
+            ("g_Grid".to_string(), serde_json::json!("4 5 6")),
+        ]);
+        let keys = BTreeMap::from([("mode".to_string(), "g_Mode".to_string())]);
+        layout.populate_effect_params(
+            &mut buf,
+            &constants,
+            &keys,
+            0.0,
+            &SystemUniforms::with_resolution([1, 1]),
+        );
+        assert_eq!(read_u32(&buf, 0), 2);
+        assert_eq!([read_u32(&buf, 16), read_u32(&buf, 20), read_u32(&buf, 24)], [4, 5, 6]);
+    }
+
+    #[test]
+    fn test_uniform_writers_uint() {
+        let decls = vec![
+            ("g_Count".to_string(), "uint".to_string()),
+            ("g_Size".to_string(), "uvec4".to_string()),
+        ];
+        let layout = UniformLayout::new(&decls);
+        let mut buf = vec![0u8; layout.total_size() as usize];
+        assert!(layout.write_u32(&mut buf, "g_Count", u32::MAX));
+        assert!(layout.write_uvec(&mut buf, "g_Size", [1, 2, 3, 4]));
+        assert_eq!(read_u32(&buf, 0), u32::MAX);
+        assert_eq!(read_u32(&buf, 28), 4);
+    }
+
+    #[test]
+    fn test_uniform_writers_bool() {
+        let decls = vec![
+            ("g_Enabled".to_string(), "bool".to_string()),
+            ("g_Flags".to_string(), "bvec2".to_string()),
+        ];
+        let layout = UniformLayout::new(&decls);
+        assert_eq!(layout.offsets["g_Flags"].offset, 8);
+
+        let mut buf = vec![0u8; layout.total_size() as usize];
+        assert!(layout.write_bool(&mut buf, "g_Enabled", true));
+        assert!(layout.write_bvec(&mut buf, "g_Flags", [false, true]));
+        assert_eq!([read_u32(&buf, 0), read_u32(&buf, 8), read_u32(&buf, 12)], [1, 0, 1]);
+
+        let constants = BTreeMap::from([("g_Enabled".to_string(), serde_json::json!(false))]);
+        layout.populate_effect_params(
+            &mut buf,
+            &constants,
+            &BTreeMap::new(),
+            0.0,
+            &SystemUniforms::with_resolution([1, 1]),
+        );
+        assert_eq!(read_u32(&buf, 0), 0);
+    }
+
+    #[test]
+    fn test_uniform_writers_mat3() {
+        let decls = vec![
+            ("g_Alpha".to_string(), "float".to_string()),
+            ("g_NormalMatrix".to_string(), "mat3".to_string()),
+            ("g_After".to_string(), "float".to_string()),
+        ];
+        let layout = UniformLayout::new(&decls);
+        assert_eq!(layout.offsets["g_NormalMatrix"].offset, 16);
+        assert_eq!(layout.offsets["g_After"].offset, 64);
+
+        let mut buf = vec![0u8; layout.total_size() as usize];
+        assert!(layout.write_f32(&mut buf, "g_After", 9.0));
+        assert!(layout.write_mat3(
+            &mut buf,
+            "g_NormalMatrix",
+            &[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]],
+        ));
+        // Columns start every 16 bytes; the fourth lane is padding.
+        for col in 0..3u64 {
+            for row in 0..3u64 {
+                let v = read_f32(&buf, 16 + col * 16 + row * 4);
+                assert_eq!(v, (col * 3 + row + 1) as f32);
+            }
+            assert_eq!(read_u32(&buf, 16 + col * 16 + 12), 0);
+        }
+        assert_eq!(read_f32(&buf, 64), 9.0);
+    }
+
+    #[test]
+    fn test_object_uniforms() {
+        let transform = LayerTransform {
+            origin: Vec3::new(100.0, 50.0, 0.0),
+            angles: Vec3::new(0.0, 0.0, std::f32::consts::FRAC_PI_2),
+            scale: Vec3::new(2.0, 1.0, 1.0),
+            size: glam::Vec2::new(40.0, 20.0),
+        };
+        let vp = Mat4::orthographic_rh(0.0, 1920.0, 0.0, 1080.0, -100.0, 100.0);
+        let texture = ([0.0, 0.5, -0.25, 0.0], [0.25, 0.5]);
+        let obj = ObjectUniforms::new(&transform, [64, 32], texture, &vp.to_cols_array_2d());
+
+        // The quad's top-right corner in pass space fills the target's
+        // corner, and lands where the vertex buffer puts the layer's
+        // top-right corner: half extent (40, 10) rotated by 90 degrees.
+        let pass_corner = Vec3::new(32.0, 16.0, 0.0);
+        assert!(obj.pass.project_point3(pass_corner).truncate().abs_diff_eq(glam::Vec2::ONE, 1e-6));
+        let corner = obj.model.transform_point3(pass_corner);
+        assert!((corner - Vec3::new(90.0, 90.0, -1.0)).length() < 1e-4);
+
+        let decls = vec![
+            ("g_ModelMatrixInverse".to_string(), "mat4".to_string()),
+            ("g_Texture0Rotation".to_string(), "vec4".to_string()),
+            ("g_ViewProjectionMatrix".to_string(), "mat4".to_string()),
+            ("g_EffectTextureProjectionMatrixInverse".to_string(), "mat4".to_string()),
+            ("g_ModelViewProjectionMatrix".to_string(), "mat4".to_string()),
+        ];
+        let layout = UniformLayout::new(&decls);
+        let mut buf = vec![0u8; layout.total_size() as usize];
+        let mut sys = SystemUniforms::with_resolution([1920, 1080]);
+        sys.object = obj;
+        layout.populate_effect_params(
+            &mut buf,
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            0.0,
+            &sys,
+        );
+
+        let read_mat4 = |at: u64| {
+            let cols: Vec<f32> = (0..16).map(|i| read_f32(&buf, at + i * 4)).collect();
+            Mat4::from_cols_slice(&cols)
+        };
+        assert!(read_mat4(0).transform_point3(corner).abs_diff_eq(pass_corner, 1e-4));
+        let rotation: Vec<f32> = (0..4).map(|i| read_f32(&buf, 64 + i * 4)).collect();
+        assert_eq!(rotation, [0.0, 0.5, -0.25, 0.0]);
+        assert_eq!(read_f32(&buf, 80), vp.x_axis.x);
+
+        // The texture projection inverse takes the layer's corner on
+        // screen back to the quad's corner.
+        let texture_inverse = read_mat4(144);
+        assert_ne!(texture_inverse, Mat4::IDENTITY);
+        let on_screen = vp.project_point3(corner);
+        let back = texture_inverse.project_point3(on_screen);
+        assert!(back.truncate().abs_diff_eq(pass_corner.truncate(), 1e-3));
+        assert_eq!(read_mat4(208), obj.pass);
+    }
+
+    #[test]
+    fn test_uniform_layout_std140_arrays() {
+        let decls = vec![
+            ("g_Time".to_string(), "float".to_string()),
+            ("g_AudioSpectrum16Left".to_string(), "float[16]".to_string()),
+            ("g_Points".to_string(), "vec2[3]".to_string()),
+            ("g_Alpha".to_string(), "float".to_string()),
+        ];
+        let layout = UniformLayout::new(&decls);
+
+        let spectrum = layout.offsets["g_AudioSpectrum16Left"];
+        assert_eq!((spectrum.offset, spectrum.stride, spectrum.len), (16, 16, 16));
+        let points = layout.offsets["g_Points"];
+        assert_eq!((points.offset, points.stride, points.len), (272, 16, 3));
+        assert_eq!(layout.offsets["g_Alpha"].offset, 320);
+        assert_eq!(layout.total_size(), 336);
+
+        let mut buf = vec![0u8; layout.total_size() as usize];
+        let values: Vec<f32> = (0..20).map(|i| i as f32).collect();
+        assert!(layout.write_f32_slice(&mut buf, "g_AudioSpectrum16Left", &values));
+        for i in 0..16 {
+            let at = 16 + i * 16;
+            let v = f32::from_le_bytes(buf[at..at + 4].try_into().unwrap());
+            assert_eq!(v, i as f32);
+        }
+        // Padding between elements and the uniform after the array are untouched.
+        assert!(buf[20..32].iter().all(|&b| b == 0));
+        assert!(buf[272..].iter().all(|&b| b == 0));
+
+        // Scalar writers do not apply to arrays.
+        assert!(!layout.write_f32(&mut buf, "g_AudioSpectrum16Left", 1.0));
+    }
+
+    #[test]
+    fn test_uniform_array_writers() {
+        let decls = vec![
+            ("g_Points".to_string(), "vec2[3]".to_string()),
+            ("g_Unknown".to_string(), "float[COUNT]".to_string()),
+            ("g_Colors".to_string(), "vec4[2]".to_string()),
+            ("g_Steps".to_string(), "int[2]".to_string()),
+        ];
+        let layout = UniformLayout::new(&decls);
+        // The array of unknown length takes no space.
+        assert!(!layout.offsets.contains_key("g_Unknown"));
+        assert_eq!(layout.offsets["g_Colors"].offset, 48);
+        assert_eq!(layout.offsets["g_Steps"].offset, 80);
+
+        let mut buf = vec![0u8; layout.total_size() as usize];
+        // A partial last element is not written.
+        let colors: Vec<f32> = (1..=10).map(|i| i as f32).collect();
+        assert!(layout.write_f32_slice(&mut buf, "g_Colors", &colors));
+        let written: Vec<f32> = (0..8).map(|i| read_f32(&buf, 48 + i * 4)).collect();
+        assert_eq!(written, colors[..8]);
+        assert_eq!(read_u32(&buf, 80), 0);
+        // Int arrays reject float data.
+        assert!(!layout.write_f32_slice(&mut buf, "g_Steps", &[1.0]));
+
+        let constants = BTreeMap::from([
+            ("g_Points".to_string(), serde_json::json!("1 2 3 4 5 6")),
+            ("g_Steps".to_string(), serde_json::json!("7 -8")),
+        ]);
+        layout.populate_effect_params(
+            &mut buf,
+            &constants,
+            &BTreeMap::new(),
+            0.0,
+            &SystemUniforms::with_resolution([1, 1]),
+        );
+        let points: Vec<f32> = [0, 4, 16, 20, 32, 36].iter().map(|&at| read_f32(&buf, at)).collect();
+        assert_eq!(points, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!([read_u32(&buf, 80) as i32, read_u32(&buf, 96) as i32], [7, -8]);
+    }
+
+    #[test]
+    fn test_split_array_type() {
+        assert_eq!(split_array_type("float[16]"), Some(("float", Some(16))));
+        assert_eq!(split_array_type("vec4"), Some(("vec4", None)));
+        assert_eq!(split_array_type("float[N]"), None);
+    }
 }
@@ -1,5 +1,7 @@
 //! Unified effect step model. Single-pass and multi-pass effects are
-//! flattened into [`EffectStep`]s with named FBOs allocated per-effect.
+//! flattened into [`EffectStep`]s.  Their named FBOs are only declared
+//! here; the render graph allocates them (see
+//! [`allocate_targets`](crate::scene::renderer::render_graph::allocate_targets)).
 
 use std::{collections::BTreeMap, rc::Rc};
 
@@ -8,16 +10,19 @@ use wgpu::*;
 
 use crate::scene::{
     loader::{
-        object::Effect,
+        object::{Effect, Instance},
         scene_loader::Scene,
     },
     renderer::{
         effect_bindgroup::EffectBindGroup,
+        effect_uniforms::EffectUniforms,
         post_process::PostProcess,
         post_processor::{
-            pipeline_handler::{self, EffectPipelineData, load_mask_texture},
+            pipeline_handler::{self, EffectPipelineData},
             shader_header::WM_SAMPLER_BINDING,
+            texture_slots::{self, SlotTexture},
         },
+        render_targets::TargetViews,
     },
 };
 
@@ -29,11 +34,14 @@ pub struct EffectStep {
     pub pipedata: EffectPipelineData,
     pub bind_inputs: Vec<(String, u32)>,
     pub target: Option<String>,
-}
-
-pub struct FboTexture {
-    #[allow(dead_code)] pub texture: Texture,
-    pub view: TextureView,
+    /// Scene id of the effect this step belongs to.
+    pub effect_id: i64,
+    /// Disabled steps are skipped when rendering but kept built, so an
+    /// effect can be toggled at runtime.
+    pub enabled: bool,
+    /// Bind groups reading ping-pong view A and B as the previous result,
+    /// built by [`DrawQueue::bind_targets`](crate::scene::renderer::draw::DrawQueue::bind_targets).
+    pub bindgroups: Option<[BindGroup; 2]>,
 }
 
 // ── Effect JSON deserialization ───────────────────────────────
@@ -47,11 +55,10 @@ struct EffectDefPass { material: String, #[serde(default)] target: Option<String
 // ── Public builder ────────────────────────────────────────────
 
 pub fn build_effect_steps(
-    device: &Device, queue: &Queue, effects: &[Effect], scene: &Scene,
+    device: &Device, queue: &Queue, effects: &[Effect], instance: Option<&Instance>, scene: &Scene,
     post_process: &PostProcess, pipelines: &mut BTreeMap<String, EffectPipelineData>,
-    proj_bgl: &BindGroupLayout, source_view: &TextureView,
-    source_w: u32, source_h: u32, no_effects: bool,
-) -> (Vec<EffectStep>, BTreeMap<String, FboTexture>, bool) {
+    proj_bgl: &BindGroupLayout, source_w: u32, source_h: u32, target: [u32; 2], no_effects: bool,
+) -> (Vec<EffectStep>, BTreeMap<String, [u32; 2]>, bool) {
     if no_effects { return (vec![], BTreeMap::new(), false); }
 
     let mut steps = Vec::new();
@@ -60,29 +67,28 @@ pub fn build_effect_steps(
     for effect in effects {
         let raw = match scene.jsons.get(&effect.file) { Some(r) => r, None => continue };
         let def: EffectDef = match serde_json::from_str(&raw[..]) { Ok(d) => d, Err(_) => continue };
+        if !effect.is_visible(&scene.user_properties) {
+            log::debug!("effect '{}' ({}) starts hidden", effect.name, effect.file);
+        }
 
         if def.passes.len() <= 1 && def.fbos.is_empty() {
-            if let Some(s) = build_step(device, queue, effect, effect.passes.first(), None,
-                                         scene, post_process, pipelines, proj_bgl, source_view, source_w, source_h) {
+            let material = def.passes.first().map(|p| p.material.as_str());
+            if let Some(s) = build_step(device, queue, effect, instance, effect.passes.first(), None, material,
+                                         scene, post_process, pipelines, proj_bgl, source_w, source_h) {
                 steps.push(s);
             }
         } else {
+            // An FBO's `scale` divides the effect target size: 2 is half size.
+            let max = device.limits().max_texture_dimension_2d;
             for fbo_def in &def.fbos {
-                let s = fbo_def.scale.unwrap_or(1.0).max(1.0);
-                let (w, h) = (((source_w as f32) / s).max(1.0) as u32, ((source_h as f32) / s).max(1.0) as u32);
-                let tex = device.create_texture(&TextureDescriptor {
-                    label: None, size: Extent3d { width: w, height: h, depth_or_array_layers: 1 },
-                    mip_level_count: 1, sample_count: 1, dimension: TextureDimension::D2,
-                    format: TextureFormat::Rgba8UnormSrgb,
-                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING, view_formats: &[],
-                });
-                let view = tex.create_view(&Default::default());
-                fbos.insert(fbo_def.name.clone(), FboTexture { texture: tex, view });
+                let s = fbo_def.scale.filter(|s| *s > 0.0).unwrap_or(1.0);
+                let size = target.map(|side| ((side as f32 / s).round() as u32).clamp(1, max));
+                fbos.insert(fbo_def.name.clone(), size);
             }
             for (i, def_pass) in def.passes.iter().enumerate() {
-                if let Some(s) = build_step(device, queue, effect, effect.passes.get(i),
-                                             Some(def_pass), scene, post_process, pipelines, proj_bgl,
-                                             source_view, source_w, source_h) {
+                if let Some(s) = build_step(device, queue, effect, instance, effect.passes.get(i),
+                                             Some(def_pass), Some(&def_pass.material), scene, post_process,
+                                             pipelines, proj_bgl, source_w, source_h) {
                     steps.push(s);
                 }
             }
@@ -94,17 +100,18 @@ pub fn build_effect_steps(
 
 // ── Shared step builder ───────────────────────────────────────
 
-/// Build one EffectStep. `def_pass` is Some for multi-pass internal steps.
+/// Build one EffectStep. `def_pass` is Some for multi-pass internal steps;
+/// `material` is the pass's material path.
 fn build_step(
-    device: &Device, queue: &Queue, effect: &Effect,
+    device: &Device, queue: &Queue, effect: &Effect, instance: Option<&Instance>,
     scene_pass: Option<&crate::scene::loader::object::Pass>,
-    def_pass: Option<&EffectDefPass>,
+    def_pass: Option<&EffectDefPass>, material: Option<&str>,
     scene: &Scene, post_process: &PostProcess,
     pipelines: &mut BTreeMap<String, EffectPipelineData>,
-    proj_bgl: &BindGroupLayout, source_view: &TextureView,
-    source_w: u32, source_h: u32,
+    proj_bgl: &BindGroupLayout, source_w: u32, source_h: u32,
 ) -> Option<EffectStep> {
     let scene_pass = scene_pass?;
+    let overrides = texture_slots::override_names(scene_pass, instance, &scene.user_properties);
 
     let (pipeline, pipedata, bind_inputs, target) = if let Some(dp) = def_pass {
         // Multi-pass step
@@ -113,8 +120,8 @@ fn build_step(
         let shader = mat_json["passes"][0]["shader"].as_str()?;
         let p = pipeline_handler::create_effect_pipeline_for_multipass(
             device, &format!("shaders/{}.frag", shader), &format!("shaders/{}.vert", shader),
-            &dp.material, &scene_pass.textures, scene_pass.combos.as_ref(),
-            pipelines, scene, proj_bgl,
+            &dp.material, &overrides, scene_pass.combos.as_ref(),
+            pipelines, &post_process.shaders, scene, proj_bgl,
         )?;
         let pd = pipelines.values().find(|d| Rc::ptr_eq(&d.pipeline, &p))?.clone();
         let bi: Vec<(String, u32)> = dp.bind.iter().map(|b| (b.name.clone(), b.index)).collect();
@@ -122,43 +129,36 @@ fn build_step(
     } else {
         // Single-pass
         let p = pipeline_handler::get_or_create_pipeline(
-            device, effect.file.clone(), &scene_pass.textures, scene_pass.combos.as_ref(),
-            pipelines, scene, proj_bgl,
+            device, effect.file.clone(), &overrides, scene_pass.combos.as_ref(),
+            pipelines, &post_process.shaders, scene, proj_bgl,
         )?;
         let pd = pipelines.values().find(|d| Rc::ptr_eq(&d.pipeline, &p))?.clone();
         (p.as_ref().clone(), pd, vec![("previous".to_string(), 0)], None)
     };
 
-    let (mask_tex, mask_view, noise_tex, noise_view) = load_mask_and_noise(device, queue, scene, scene_pass);
+    let material_textures = material.map(|m| texture_slots::material_textures(scene, m)).unwrap_or_default();
+    let textures = texture_slots::resolve_slots(device, queue, scene, &post_process.mipmaps, &pipedata.layout, &overrides, &material_textures);
     let bindgroup = EffectBindGroup::new(
-        device, post_process, &pipedata, source_view,
-        mask_view.as_ref(), noise_view.as_ref(), Rc::clone(&pipedata.pipeline),
+        post_process, &pipedata,
         pipedata.layout.uniform_material_keys.clone(),
         scene_pass.constantshadervalues.clone().unwrap_or_default(),
-        build_tex_resolutions(&pipedata, source_w, source_h, mask_tex.as_ref(), noise_tex.as_ref()),
-        mask_tex, noise_tex,
+        build_tex_resolutions(&pipedata, source_w, source_h, &textures),
+        textures,
     )?;
 
-    Some(EffectStep { pipeline, bindgroup, pipedata, bind_inputs, target })
-}
-
-fn load_mask_and_noise(device: &Device, queue: &Queue, scene: &Scene,
-    pass: &crate::scene::loader::object::Pass) -> (Option<Texture>, Option<TextureView>, Option<Texture>, Option<TextureView>)
-{
-    let load = |p: &str| load_mask_texture(device, queue, scene, p).map(|(t, v)| (Some(t), Some(v))).unwrap_or((None, None));
-    let (mt, mv) = pass.textures.get(1).and_then(|t| t.as_deref()).map_or((None, None), load);
-    let (nt, nv) = pass.textures.get(2).and_then(|t| t.as_deref()).map_or((None, None), load);
-    (mt, mv, nt, nv)
+    Some(EffectStep {
+        pipeline, bindgroup, pipedata, bind_inputs, target,
+        effect_id: effect.id, enabled: effect.is_visible(&scene.user_properties), bindgroups: None,
+    })
 }
 
 fn build_tex_resolutions(pipedata: &EffectPipelineData, sw: u32, sh: u32,
-    mask: Option<&Texture>, noise: Option<&Texture>) -> BTreeMap<String, [f32; 4]>
+    textures: &[SlotTexture]) -> BTreeMap<String, [f32; 4]>
 {
     let (sw, sh) = (sw as f32, sh as f32);
-    pipedata.layout.sampler_names.iter().enumerate().map(|(i, name)| {
-        let (w, h) = match i {
-            1 => mask.map(|t| (t.width() as f32, t.height() as f32)).unwrap_or((sw, sh)),
-            2 => noise.map(|t| (t.width() as f32, t.height() as f32)).unwrap_or((sw, sh)),
+    pipedata.layout.sampler_names.iter().zip(textures).map(|(name, slot)| {
+        let (w, h) = match slot {
+            SlotTexture::Image { texture, .. } => (texture.width() as f32, texture.height() as f32),
             _ => (sw, sh),
         };
         (format!("{}Resolution", name), [w, h, w, h])
@@ -169,31 +169,39 @@ fn build_tex_resolutions(pipedata: &EffectPipelineData, sw: u32, sh: u32,
 
 pub fn make_step_bindgroup(
     device: &Device, step: &EffectStep, source_view: &TextureView,
-    fbos: &BTreeMap<String, FboTexture>, sampler: &Sampler,
+    fbos: &BTreeMap<String, TextureView>, targets: &TargetViews, sampler: &Sampler,
+    uniforms: &EffectUniforms,
 ) -> BindGroup {
     let n = step.pipedata.layout.sampler_count();
     let mut entries: Vec<BindGroupEntry<'_>> = Vec::with_capacity(n + 2);
     for slot in 0..n {
-        let view = resolve_texture(slot as u32, step, source_view, fbos);
+        let view = resolve_texture(slot as u32, step, source_view, fbos, targets);
         entries.push(BindGroupEntry { binding: slot as u32 * 2, resource: BindingResource::TextureView(view) });
     }
     entries.push(BindGroupEntry { binding: WM_SAMPLER_BINDING, resource: BindingResource::Sampler(sampler) });
-    if let Some(ref buf) = step.bindgroup.uniform_buffer {
-        entries.push(BindGroupEntry { binding: step.pipedata.layout.uniform_binding, resource: buf.as_entire_binding() });
+    let size = step.bindgroup.uniform_layout.total_size();
+    if let Some(resource) = step.bindgroup.uniform_offset.and_then(|offset| uniforms.binding(offset, size)) {
+        entries.push(BindGroupEntry { binding: step.pipedata.layout.uniform_binding, resource });
     }
     device.create_bind_group(&BindGroupDescriptor { label: None, layout: &step.pipedata.bindgroup_layout, entries: &entries })
 }
 
 fn resolve_texture<'a>(slot: u32, step: &'a EffectStep, source_view: &'a TextureView,
-                        fbos: &'a BTreeMap<String, FboTexture>) -> &'a TextureView {
-    if let Some((name, _)) = step.bind_inputs.iter().find(|(_, i)| *i == slot) {
+                        fbos: &'a BTreeMap<String, TextureView>, targets: &TargetViews<'a>) -> &'a TextureView {
+    // Effect FBOs shadow scene targets of the same name.
+    let named = |name: &str| fbos.get(name).or_else(|| targets.get(name));
+    // `bind` entries in effect JSON address texture units, not slots.
+    let unit = step.pipedata.layout.sampler_names.get(slot as usize)
+        .and_then(|name| texture_slots::texture_unit(name))
+        .map_or(slot, |u| u as u32);
+    if let Some((name, _)) = step.bind_inputs.iter().find(|(_, i)| *i == unit) {
         if name == "previous" { return source_view; }
-        if let Some(fbo) = fbos.get(name) { return &fbo.view; }
+        if let Some(view) = named(name) { return view; }
     }
-    match slot {
-        0 => source_view,
-        1 => step.bindgroup.mask_view.as_ref().unwrap_or(&step.bindgroup.blank_view),
-        2 => step.bindgroup.noise_view.as_ref().unwrap_or(&step.bindgroup.blank_view),
+    match step.bindgroup.textures.get(slot as usize) {
+        Some(SlotTexture::Previous) => source_view,
+        Some(SlotTexture::Image { view, .. }) => view,
+        Some(SlotTexture::Target(name)) => named(name).unwrap_or(&step.bindgroup.blank_view),
         _ => &step.bindgroup.blank_view,
     }
 }
@@ -2,5 +2,7 @@ pub mod effect_param;
 pub mod effect_step;
 pub mod pipeline_handler;
 pub mod pipeline_helpers;
+pub mod shader_cache;
 pub mod shader_header;
+pub mod texture_slots;
 pub mod transform;
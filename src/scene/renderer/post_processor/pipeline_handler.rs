@@ -6,9 +6,13 @@ use wgpu::*;
 use crate::scene::{
     loader::scene_loader::Scene,
     renderer::{
+        color::{self, COLOR_FORMAT},
+        compressed,
+        mipmap::MipGenerator,
         post_processor::{
             effect_param::UniformLayout,
             pipeline_helpers,
+            shader_cache::{self, CachedShaders, ShaderCache},
             shader_header,
             transform::{EffectLayout, preprocess_pair},
         },
@@ -33,6 +37,7 @@ pub fn get_or_create_pipeline(
     pass_textures: &[Option<String>],
     pass_combos: Option<&BTreeMap<String, i64>>,
     pipelines: &mut BTreeMap<String, EffectPipelineData>,
+    shaders: &ShaderCache,
     scene: &Scene,
     projection_bgl: &BindGroupLayout,
 ) -> Option<Rc<RenderPipeline>> {
@@ -53,6 +58,7 @@ pub fn get_or_create_pipeline(
         material_json,
         pass_textures,
         pass_combos,
+        shaders,
         scene,
         projection_bgl,
     )?;
@@ -70,6 +76,7 @@ pub fn create_effect_pipeline_for_multipass(
     pass_textures: &[Option<String>],
     pass_combos: Option<&BTreeMap<String, i64>>,
     pipelines: &mut BTreeMap<String, EffectPipelineData>,
+    shaders: &ShaderCache,
     scene: &Scene,
     projection_bgl: &BindGroupLayout,
 ) -> Option<Rc<RenderPipeline>> {
@@ -81,7 +88,7 @@ pub fn create_effect_pipeline_for_multipass(
     let material_json: Value = serde_json::from_str(&scene.jsons.get(material_path)?[..]).ok()?;
     let data = compile_pipeline(
         device, frag_path, vert_path, material_json,
-        pass_textures, pass_combos, scene, projection_bgl,
+        pass_textures, pass_combos, shaders, scene, projection_bgl,
     )?;
     let rc = Rc::clone(&data.pipeline);
     pipelines.insert(cache_key, data);
@@ -118,6 +125,7 @@ fn compile_pipeline(
     material_json: Value,
     pass_textures: &[Option<String>],
     pass_combos: Option<&BTreeMap<String, i64>>,
+    shaders: &ShaderCache,
     scene: &Scene,
     projection_bgl: &BindGroupLayout,
 ) -> Option<EffectPipelineData> {
@@ -145,8 +153,19 @@ fn compile_pipeline(
 
     let define_refs: Vec<(&str, &str)> = defines.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
     let headers = shader_header::get_headers(&scene.misc);
-    let (vert_processed, frag_processed, layout) =
-        preprocess_pair(vert_source, frag_source, &headers, &defines);
+    let key = shader_cache::key(
+        [vert_source, frag_source]
+            .into_iter()
+            .chain(headers.iter().flat_map(|(name, source)| [name.as_str(), source.as_str()]))
+            .chain(define_refs.iter().flat_map(|&(name, value)| [name, value])),
+    );
+    let CachedShaders { vert: vert_processed, frag: frag_processed, layout } =
+        shaders.get(key).unwrap_or_else(|| {
+            let (vert, frag, layout) = preprocess_pair(vert_source, frag_source, &headers, &defines);
+            let translated = CachedShaders { vert, frag, layout };
+            shaders.put(key, &translated);
+            translated
+        });
 
     let vert_module = device.create_shader_module(ShaderModuleDescriptor {
         label: None,
@@ -173,12 +192,12 @@ fn compile_pipeline(
         fragment: Some(FragmentState {
             module: &frag_module, entry_point: Some("main"), compilation_options: Default::default(),
             targets: &[Some(ColorTargetState {
-                format: TextureFormat::Rgba8UnormSrgb,
+                format: COLOR_FORMAT,
                 blend: Some(BlendState { color: BlendComponent { src_factor: BlendFactor::SrcAlpha, dst_factor: BlendFactor::OneMinusSrcAlpha, operation: BlendOperation::Add }, alpha: BlendComponent::OVER }),
                 write_mask: ColorWrites::all(),
             })],
         }),
-        multiview_mask: None, cache: None,
+        multiview_mask: None, cache: shaders.pipelines(),
     });
 
     Some(EffectPipelineData {
@@ -191,33 +210,24 @@ fn compile_pipeline(
 
 // ── Texture loader ────────────────────────────────────────────
 
-pub fn load_mask_texture(
+/// Upload `materials/{path}.tex`, with mips, for sampling in an effect.
+pub fn load_effect_texture(
     device: &Device,
     queue: &Queue,
     scene: &Scene,
+    mipmaps: &MipGenerator,
     path: &str,
 ) -> Option<(Texture, TextureView)> {
     let tex_key = format!("materials/{}.tex", path);
     let tex = scene.textures.get(&tex_key)?;
+    if let Some(bc) = scene.textures.compressed(&tex_key) {
+        let texture = compressed::upload(device, queue, &bc);
+        let view = texture.create_view(&Default::default());
+        return Some((texture, view));
+    }
 
-    let (format, bpp) = match tex.extension.as_str() {
-        "r8" => (TextureFormat::R8Unorm, 1u32),
-        "rg88" => (TextureFormat::Rg8Unorm, 2u32),
-        _ => (TextureFormat::Rgba8Unorm, 4u32),
-    };
-
-    let texture = device.create_texture(&TextureDescriptor {
-        label: None,
-        size: Extent3d { width: tex.dimension[0], height: tex.dimension[1], depth_or_array_layers: 1 },
-        mip_level_count: 1, sample_count: 1, dimension: TextureDimension::D2,
-        format, usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST, view_formats: &[],
-    });
-    queue.write_texture(
-        TexelCopyTextureInfo { texture: &texture, mip_level: 0, origin: Origin3d::ZERO, aspect: TextureAspect::All },
-        &tex.payload,
-        TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(tex.dimension[0] * bpp), rows_per_image: None },
-        Extent3d { width: tex.dimension[0], height: tex.dimension[1], depth_or_array_layers: 1 },
-    );
+    let format = color::texture_format(&tex.extension);
+    let texture = mipmaps.upload(device, queue, format, tex.dimension, &tex.payload);
     let view = texture.create_view(&Default::default());
     Some((texture, view))
 }
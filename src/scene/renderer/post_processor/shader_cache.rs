@@ -0,0 +1,241 @@
+//! On-disk cache of translated effect shaders and compiled pipelines.
+//!
+//! Lives in `$XDG_CACHE_HOME/linux-wallpaper-engine` (or
+//! `~/.cache/linux-wallpaper-engine`).  Each effect pipeline stores its
+//! preprocessed GLSL pair and [`EffectLayout`] under a hash of the shader
+//! sources, headers and defines, so later launches skip `preprocess_pair`.
+//! Where the backend supports it, the driver's compiled pipelines are kept
+//! in a wgpu [`PipelineCache`] blob next to them, one per adapter.
+//!
+//! Entries are never invalidated other than by their key: the crate
+//! version and [`CACHE_FORMAT`] are part of it, so an upgrade or a change
+//! to the translation starts a fresh set.  Deleting the directory is
+//! always safe.
+
+use std::{
+    cell::Cell,
+    ffi::OsString,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+use wgpu::*;
+
+use super::transform::EffectLayout;
+
+const APP_DIR: &str = "linux-wallpaper-engine";
+
+/// Version of the cached translation.  Bump it with any change to
+/// `preprocess_pair`, the generated headers or [`CachedShaders`], so
+/// entries written by an older build are not served.
+const CACHE_FORMAT: &str = "1";
+
+/// Output of `preprocess_pair` for one pipeline.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedShaders {
+    pub vert: String,
+    pub frag: String,
+    pub layout: EffectLayout,
+}
+
+pub struct ShaderCache {
+    /// `None` when there is no usable cache directory.
+    dir: Option<PathBuf>,
+    /// Driver pipeline cache, when the backend supports one.
+    pipelines: Option<PipelineCache>,
+    /// File of `pipelines` in `dir`, named after the adapter.
+    pipelines_file: Option<String>,
+    hits: Cell<u32>,
+    misses: Cell<u32>,
+}
+
+impl ShaderCache {
+    /// Open the cache directory and load the pipeline cache of
+    /// `adapter`.  The device needs `PIPELINE_CACHE` for the latter.
+    pub fn open(device: &Device, adapter: &AdapterInfo) -> Self {
+        let dir = cache_dir(std::env::var_os("XDG_CACHE_HOME"), std::env::var_os("HOME"))
+            .map(|dir| dir.join("shaders"))
+            .filter(|dir| match fs::create_dir_all(dir) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::warn!("shader cache disabled: {}: {}", dir.display(), e);
+                    false
+                }
+            });
+
+        let pipelines_file = util::pipeline_cache_key(adapter)
+            .filter(|_| device.features().contains(Features::PIPELINE_CACHE));
+        let pipelines = dir.as_ref().zip(pipelines_file.as_ref()).map(|(dir, file)| {
+            let data = fs::read(dir.join(file)).ok();
+            log::debug!(
+                "pipeline cache '{}': {} bytes",
+                file,
+                data.as_ref().map_or(0, Vec::len)
+            );
+            // SAFETY: the blob was written by `PipelineCache::get_data`
+            // for this adapter (see `save`); drivers validate its header
+            // and `fallback` starts empty when it does not match.
+            unsafe {
+                device.create_pipeline_cache(&PipelineCacheDescriptor {
+                    label: Some("effect pipelines"),
+                    data: data.as_deref(),
+                    fallback: true,
+                })
+            }
+        });
+
+        Self {
+            dir,
+            pipelines,
+            pipelines_file,
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        }
+    }
+
+    /// The preprocessed shaders stored under `key`.
+    pub fn get(&self, key: u64) -> Option<CachedShaders> {
+        let path = self.entry(key)?;
+        let shaders = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+        match shaders {
+            Some(_) => self.hits.set(self.hits.get() + 1),
+            None => self.misses.set(self.misses.get() + 1),
+        }
+        shaders
+    }
+
+    pub fn put(&self, key: u64, shaders: &CachedShaders) {
+        let Some(path) = self.entry(key) else {
+            return;
+        };
+        let result = serde_json::to_vec(shaders)
+            .map_err(io::Error::other)
+            .and_then(|bytes| write_atomic(&path, &bytes));
+        if let Err(e) = result {
+            log::warn!("shader cache: cannot write {}: {}", path.display(), e);
+        }
+    }
+
+    /// For `RenderPipelineDescriptor::cache`.
+    pub fn pipelines(&self) -> Option<&PipelineCache> {
+        self.pipelines.as_ref()
+    }
+
+    /// Write the pipeline cache back, with everything compiled so far.
+    pub fn save(&self) {
+        log::info!(
+            "shader cache: {} hits, {} misses",
+            self.hits.get(),
+            self.misses.get()
+        );
+        let (Some(dir), Some(file), Some(pipelines)) = (
+            self.dir.as_ref(),
+            self.pipelines_file.as_ref(),
+            self.pipelines.as_ref(),
+        ) else {
+            return;
+        };
+        let Some(data) = pipelines.get_data() else {
+            return;
+        };
+        if let Err(e) = write_atomic(&dir.join(file), &data) {
+            log::warn!("pipeline cache: cannot write '{}': {}", file, e);
+        }
+    }
+
+    fn entry(&self, key: u64) -> Option<PathBuf> {
+        Some(self.dir.as_ref()?.join(format!("{:016x}.json", key)))
+    }
+}
+
+/// Cache key of a pipeline's shaders.  `parts` must hold everything the
+/// translation depends on: sources, headers, defines.
+pub fn key<'a>(parts: impl IntoIterator<Item = &'a str>) -> u64 {
+    // FNV-1a, which unlike `DefaultHasher` is stable across builds.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let version = [env!("CARGO_PKG_VERSION"), CACHE_FORMAT];
+    for part in version.into_iter().chain(parts) {
+        // Length-prefixed, so ("ab", "c") and ("a", "bc") differ.
+        let bytes = (part.len() as u64).to_le_bytes();
+        for byte in bytes.iter().chain(part.as_bytes()) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// `$XDG_CACHE_HOME/linux-wallpaper-engine`, falling back to
+/// `$HOME/.cache` when it is unset or not absolute.
+fn cache_dir(xdg_cache_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    let base = xdg_cache_home
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| Some(PathBuf::from(home?).join(".cache")))?;
+    Some(base.join(APP_DIR))
+}
+
+/// Write through a temporary file, so readers never see half an entry.
+/// The temporary name is unique to this process and call, so instances
+/// writing the same entry do not clobber each other's file.
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    static NEXT: AtomicU32 = AtomicU32::new(0);
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp = PathBuf::from(name);
+    let result = fs::write(&temp, bytes).and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        assert_eq!(key(["a", "b"]), key(["a", "b"]));
+        assert_ne!(key(["ab", "c"]), key(["a", "bc"]));
+        assert_ne!(key(["main", "BLUR=1"]), key(["main", "BLUR=2"]));
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = std::env::temp_dir().join(format!("shader-cache-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("entry.json");
+        write_atomic(&path, b"one").unwrap();
+        write_atomic(&path, b"two").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"two");
+        // No temporary file is left behind.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_dir() {
+        let dir = |xdg: Option<&str>, home: Option<&str>| {
+            cache_dir(xdg.map(OsString::from), home.map(OsString::from))
+        };
+        assert_eq!(
+            dir(Some("/tmp/cache"), Some("/home/me")),
+            Some(PathBuf::from("/tmp/cache/linux-wallpaper-engine"))
+        );
+        assert_eq!(
+            dir(Some("relative"), Some("/home/me")),
+            Some(PathBuf::from("/home/me/.cache/linux-wallpaper-engine"))
+        );
+        assert_eq!(dir(None, None), None);
+    }
+}
@@ -0,0 +1,169 @@
+//! Texture slot resolution for effect steps.
+//!
+//! A `g_TextureN` sampler is bound to texture unit N.  Unit 0 is the
+//! step's input; every other unit takes, in order of precedence, the
+//! scene pass texture, a user texture, the layer's instance override,
+//! the effect material's texture and finally the default named in the
+//! sampler annotation.  Names starting with `_rt_` refer to render
+//! targets that are bound at draw time.
+
+use serde_json::Value;
+use wgpu::*;
+
+use crate::scene::{
+    loader::{
+        object::{Instance, Pass},
+        project::UserProperties,
+        scene_loader::Scene,
+    },
+    renderer::{
+        mipmap::MipGenerator,
+        post_processor::{pipeline_handler::load_effect_texture, transform::EffectLayout},
+    },
+};
+
+/// What an effect step samples through one slot.
+pub enum SlotTexture {
+    /// The step's input.
+    Previous,
+    /// A texture loaded from the package or the assets directory.
+    Image { texture: Texture, view: TextureView },
+    /// A render target looked up by name when the step is drawn.
+    Target(String),
+    /// Nothing resolved; the blank texture is bound.
+    Blank,
+}
+
+/// Texture unit of a sampler: `g_Texture3` → 3.
+pub fn texture_unit(name: &str) -> Option<usize> {
+    let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    name[prefix.len()..].parse().ok()
+}
+
+/// Textures explicitly chosen for a pass, by unit: the scene pass
+/// textures, then user textures, then the layer's instance override.
+/// These also enable texture combos such as `MASK`.
+pub fn override_names(
+    pass: &Pass,
+    instance: Option<&Instance>,
+    properties: &UserProperties,
+) -> Vec<Option<String>> {
+    let user = |entry: &Value| match properties.resolve(entry)? {
+        Value::String(s) if !s.is_empty() => Some(s),
+        _ => None,
+    };
+    let instance_textures = instance.map_or(&[][..], |i| &i.textures[..]);
+    let instance_user = instance.map_or(&[][..], |i| &i.usertextures[..]);
+    let units = pass
+        .textures
+        .len()
+        .max(pass.usertextures.len())
+        .max(instance_textures.len())
+        .max(instance_user.len());
+
+    (0..units)
+        .map(|unit| {
+            pass.textures
+                .get(unit)
+                .cloned()
+                .flatten()
+                .or_else(|| pass.usertextures.get(unit).and_then(user))
+                .or_else(|| instance_textures.get(unit).filter(|t| !t.is_empty()).cloned())
+                .or_else(|| instance_user.get(unit).and_then(user))
+        })
+        .collect()
+}
+
+/// Per-unit textures of a material's first pass.
+pub fn material_textures(scene: &Scene, material_path: &str) -> Vec<Option<String>> {
+    scene
+        .jsons
+        .get(material_path)
+        .and_then(|raw| serde_json::from_str::<Value>(&raw[..]).ok())
+        .and_then(|mat| {
+            let textures = mat["passes"][0]["textures"].as_array()?;
+            Some(textures.iter().map(|t| t.as_str().map(str::to_string)).collect())
+        })
+        .unwrap_or_default()
+}
+
+/// Resolve every sampler of `layout`, in binding order.
+pub fn resolve_slots(
+    device: &Device,
+    queue: &Queue,
+    scene: &Scene,
+    mipmaps: &MipGenerator,
+    layout: &EffectLayout,
+    overrides: &[Option<String>],
+    material: &[Option<String>],
+) -> Vec<SlotTexture> {
+    layout
+        .sampler_names
+        .iter()
+        .enumerate()
+        .map(|(slot, name)| {
+            let unit = texture_unit(name).unwrap_or(slot);
+            if unit == 0 {
+                return SlotTexture::Previous;
+            }
+            let texture = overrides
+                .get(unit)
+                .cloned()
+                .flatten()
+                .or_else(|| material.get(unit).cloned().flatten())
+                .or_else(|| layout.sampler_defaults.get(name).cloned());
+            match texture {
+                None => SlotTexture::Blank,
+                Some(t) if t.starts_with("_rt_") => SlotTexture::Target(t),
+                Some(t) => match load_effect_texture(device, queue, scene, mipmaps, &t) {
+                    Some((texture, view)) => SlotTexture::Image { texture, view },
+                    None => {
+                        log::debug!("{}: texture '{}' not found", name, t);
+                        SlotTexture::Blank
+                    }
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_texture_unit() {
+        assert_eq!(texture_unit("g_Texture0"), Some(0));
+        assert_eq!(texture_unit("g_Texture12"), Some(12));
+        assert_eq!(texture_unit("g_Mask"), None);
+    }
+
+    #[test]
+    fn test_override_precedence() {
+        let properties = UserProperties::parse(
+            r#"{"general":{"properties":{"overlay":{"type":"file","value":"custom/overlay"}}}}"#,
+        )
+        .unwrap();
+        let pass = Pass {
+            textures: vec![None, Some("masks/a".into()), None, None],
+            usertextures: vec![Value::Null, json!({"name": "overlay"}), json!({"name": "overlay"})],
+            ..Default::default()
+        };
+        let instance = Instance {
+            textures: vec![String::new(), "inst/b".into(), "inst/c".into(), "inst/d".into()],
+            ..Default::default()
+        };
+
+        let names = override_names(&pass, Some(&instance), &properties);
+        assert_eq!(
+            names,
+            vec![
+                None,
+                Some("masks/a".into()),
+                Some("custom/overlay".into()),
+                Some("inst/d".into()),
+            ]
+        );
+    }
+}
@@ -1,8 +1,15 @@
 use std::collections::BTreeMap;
 
-#[derive(Debug, Clone)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EffectLayout {
     pub sampler_names: Vec<String>,
+    /// Texture a sampler falls back to when nothing is bound, from its
+    /// annotation (`// {"default": "util/noise"}`).
+    pub sampler_defaults: BTreeMap<String, String>,
+    /// `(name, type)` of every non-sampler uniform.  Arrays keep their
+    /// length in the type, e.g. `("g_AudioSpectrum16Left", "float[16]")`.
     pub uniform_decls: Vec<(String, String)>,
     pub uniform_material_keys: BTreeMap<String, String>,
     pub uniform_binding: u32,
@@ -27,6 +34,7 @@ pub fn collect_layout(source1: &str, source2: &str, headers: &BTreeMap<String, S
     let mut varying_types: BTreeMap<String, String> = BTreeMap::new();
     let mut attribute_set: BTreeMap<String, u32> = BTreeMap::new();
     let mut material_keys: BTreeMap<String, String> = BTreeMap::new();
+    let mut sampler_defaults: BTreeMap<String, String> = BTreeMap::new();
 
     // Track vertex varyings for fragment input validation.
     // wgpu requires all fragment inputs to have corresponding vertex outputs.
@@ -42,6 +50,7 @@ pub fn collect_layout(source1: &str, source2: &str, headers: &BTreeMap<String, S
         &mut varying_types,
         &mut attribute_set,
         &mut material_keys,
+        &mut sampler_defaults,
     );
     // Snapshot all varyings found from vertex shader
     for (name, _) in varying_set.clone() {
@@ -58,6 +67,7 @@ pub fn collect_layout(source1: &str, source2: &str, headers: &BTreeMap<String, S
         &mut varying_types,
         &mut attribute_set,
         &mut material_keys,
+        &mut sampler_defaults,
     );
 
     sampler_names.sort();
@@ -87,6 +97,7 @@ pub fn collect_layout(source1: &str, source2: &str, headers: &BTreeMap<String, S
 
     EffectLayout {
         sampler_names,
+        sampler_defaults,
         uniform_decls,
         uniform_material_keys: material_keys,
         uniform_binding,
@@ -106,6 +117,7 @@ fn collect_from_source(
     varying_types: &mut BTreeMap<String, String>,
     attribute_set: &mut BTreeMap<String, u32>,
     material_keys: &mut BTreeMap<String, String>,
+    sampler_defaults: &mut BTreeMap<String, String>,
 ) {
 
     for line in source.lines() {
@@ -125,6 +137,7 @@ fn collect_from_source(
                             varying_types,
                             attribute_set,
                             material_keys,
+                            sampler_defaults,
                         );
                     }
                 }
@@ -167,23 +180,32 @@ fn collect_from_source(
                 if !sampler_names.contains(&name.to_string()) {
                     sampler_names.push(name.to_string());
                 }
+                if let Some(default) = extract_annotation(line, "default") {
+                    sampler_defaults.entry(name.to_string()).or_insert(default);
+                }
             } else {
                 let parts: Vec<&str> = rest.splitn(2, ' ').collect();
                 if parts.len() == 2 {
-                    let ty = parts[0].trim().to_string();
-                    let name = parts[1]
+                    let declarator = parts[1]
                         .trim()
                         .trim_end_matches(';')
                         .split('=')
                         .next()
                         .unwrap_or("")
-                        .split('[')
-                        .next()
-                        .unwrap_or("")
-                        .to_string();
+                        .trim();
+                    let (name, ty) = match declarator.split_once('[') {
+                        Some((name, len)) => {
+                            let len = len.trim_end_matches(']').trim();
+                            // A symbolic length is kept as written; the
+                            // uniform layout skips it.
+                            let ty = format!("{}[{}]", parts[0].trim(), len);
+                            (name.trim().to_string(), ty)
+                        }
+                        None => (declarator.to_string(), parts[0].trim().to_string()),
+                    };
                     if !name.is_empty() {
                         uniform_map.entry(name.clone()).or_insert(ty);
-                        if let Some(mk) = extract_material_key(line) {
+                        if let Some(mk) = extract_annotation(line, "material") {
                             material_keys.entry(mk).or_insert(name);
                         }
                     }
@@ -195,12 +217,13 @@ fn collect_from_source(
 
 
 
-fn extract_material_key(line: &str) -> Option<String> {
+/// String field of the JSON annotation trailing a declaration.
+fn extract_annotation(line: &str, key: &str) -> Option<String> {
     if let Some(comment_pos) = line.find("//") {
         let comment = line[comment_pos + 2..].trim();
         if let Ok(obj) = serde_json::from_str::<serde_json::Value>(comment) {
             return obj
-                .get("material")
+                .get(key)
                 .and_then(|v| v.as_str())
                 .map(|s| s.to_string());
         }
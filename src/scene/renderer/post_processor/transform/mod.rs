@@ -8,6 +8,8 @@ use wgpu::naga::ShaderStage;
 pub use layout::EffectLayout;
 pub use layout::collect_layout;
 
+use super::effect_param::split_array_type;
+
 // Re-export WM_SAMPLER_BINDING from shader_header for convenience
 pub use super::shader_header::WM_SAMPLER_BINDING;
 
@@ -420,7 +422,14 @@ fn emit_declarations(
             layout.uniform_binding
         ));
         for (name, ty) in &layout.uniform_decls {
-            result.push_str(&format!("    {} {};\n", ty, name));
+            // Arrays of unknown length are left out of the layout too.
+            match split_array_type(ty) {
+                Some((base, Some(len))) => {
+                    result.push_str(&format!("    {} {}[{}];\n", base, name, len))
+                }
+                Some((base, None)) => result.push_str(&format!("    {} {};\n", base, name)),
+                None => {}
+            }
         }
         result.push_str("};\n");
     }
@@ -1,7 +1,8 @@
 //! Orthographic camera projection for wallpaper scenes.
 //!
 //! Builds a combined projection×view matrix from the scene.json camera
-//! parameters (eye, center, up, orthogonal projection bounds).
+//! parameters (eye, center, up, orthogonal projection bounds).  Effect
+//! quads are already in NDC and use a second, identity projection.
 
 use crate::scene::{loader::scene::Root, renderer::buffer::Buffers};
 use bytemuck::bytes_of;
@@ -28,6 +29,8 @@ pub struct Projection {
 pub struct ProjectionBindGroups {
     pub projection_layout: BindGroupLayout,
     pub projection: Option<BindGroup>,
+    /// Identity matrix, for drawing NDC quads.
+    pub identity: Option<BindGroup>,
 }
 
 impl ProjectionBindGroups {
@@ -49,6 +52,7 @@ impl ProjectionBindGroups {
         Self {
             projection_layout: layout,
             projection: None,
+            identity: None,
         }
     }
 
@@ -69,6 +73,22 @@ impl ProjectionBindGroups {
         }));
 
         queue.write_buffer(&buffers.projection, 0, bytes_of(camera_uniform));
+
+        let identity = device.create_buffer(&BufferDescriptor {
+            label: Some("identity projection"),
+            size: std::mem::size_of::<CameraUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&identity, 0, bytes_of(&Mat4::IDENTITY.to_cols_array_2d()));
+        self.identity = Some(device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &self.projection_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: identity.as_entire_binding(),
+            }],
+        }));
     }
 }
 
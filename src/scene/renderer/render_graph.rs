@@ -0,0 +1,629 @@
+//! The frame as a graph of passes over named resources.
+//!
+//! Each node is one pass (a layer's source copy, an effect step, a
+//! background snapshot, a layer draw into the scene frame, the final blit)
+//! that declares the resources it reads and writes.  [`RenderGraph::build`]
+//! lays the nodes out in draw order for the current visibility, passes
+//! whose output never reaches the swapchain are culled, and
+//! [`RenderGraph::execute`] records the rest into one command encoder, so
+//! the whole frame is one submission.
+//!
+//! Layer ping-pong targets and effect FBOs are allocated from the same
+//! graph, built with every layer and step included: targets whose
+//! lifetimes do not overlap and that have the same size share a texture
+//! (see [`allocate_targets`]).
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+};
+
+use glam::Vec3;
+use wgpu::*;
+
+use super::{
+    buffer::Buffers,
+    color::COLOR_FORMAT,
+    draw::{DrawObject, DrawQueue},
+    intermediate_pass,
+    ping_pong::PingPongTextures,
+    post_process::PostProcess,
+    post_processor::{effect_step::EffectStep, texture_slots::SlotTexture},
+    projection::ProjectionBindGroups,
+    render_pass,
+    render_targets::{self, FULL_FRAME_BUFFER, SceneTargets, TargetViews},
+};
+
+/// One of a layer's ping-pong targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Half {
+    A,
+    B,
+}
+
+impl Half {
+    fn other(self) -> Self {
+        match self {
+            Half::A => Half::B,
+            Half::B => Half::A,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Resource {
+    /// The scene frame target.
+    Frame,
+    /// The snapshot of the frame, `_rt_FullFrameBuffer`.
+    Background,
+    /// A layer's ping-pong target, by scene id.
+    Layer(i64, Half),
+    /// An effect FBO, by draw queue index and name.
+    Fbo(usize, String),
+    /// The swapchain frame.
+    Surface,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Pass {
+    /// Copy a layer's texture (or the background under it) into its
+    /// target A.
+    LayerSource { object: usize },
+    /// Copy the background under a layer into its `_rt_FullFrameBuffer`.
+    LayerBackground { object: usize },
+    /// Run one effect step of a layer, reading ping-pong half `source`.
+    Effect { object: usize, step: usize, source: Half },
+    /// Copy a layer's result from target B back to A.
+    CopyBack { object: usize },
+    /// Copy the frame into the background target.
+    Snapshot,
+    /// Draw a range of the queue into the frame, clearing it first.
+    DrawLayers { range: Range<usize>, clear: bool },
+    /// Blit the frame to the swapchain.
+    Present,
+}
+
+#[derive(Debug)]
+struct Node {
+    pass: Pass,
+    reads: Vec<Resource>,
+    writes: Vec<Resource>,
+}
+
+pub struct RenderGraph {
+    nodes: Vec<Node>,
+}
+
+/// What laying out the graph reads of a layer.
+struct Layer<'a> {
+    id: i64,
+    visible: bool,
+    samples_background: bool,
+    copy_background: bool,
+    /// Drawn from its ping-pong pair (the layer has `intermediate_size`).
+    staged: bool,
+    /// The ping-pong pair exists.
+    allocated: bool,
+    fbo_sizes: &'a BTreeMap<String, [u32; 2]>,
+    steps: Vec<Step<'a>>,
+}
+
+struct Step<'a> {
+    enabled: bool,
+    /// The step's bind groups are built.
+    bound: bool,
+    target: Option<&'a str>,
+    /// Names of the targets the step samples, besides its input.
+    inputs: Vec<&'a str>,
+}
+
+impl<'a> Layer<'a> {
+    fn of(object: &'a DrawObject) -> Self {
+        Self {
+            id: object.id,
+            visible: object.visible,
+            samples_background: object.samples_background(),
+            copy_background: object.copy_background,
+            staged: object.intermediate_size.is_some(),
+            allocated: object.intermediates.is_some(),
+            fbo_sizes: &object.fbo_sizes,
+            steps: object.effect_steps.iter().map(Step::of).collect(),
+        }
+    }
+}
+
+impl<'a> Step<'a> {
+    fn of(step: &'a EffectStep) -> Self {
+        let targets = step.bindgroup.textures.iter().filter_map(|t| match t {
+            SlotTexture::Target(name) => Some(name.as_str()),
+            _ => None,
+        });
+        Self {
+            enabled: step.enabled,
+            bound: step.bindgroups.is_some(),
+            target: step.target.as_deref(),
+            inputs: step.bind_inputs.iter().map(|(name, _)| name.as_str()).chain(targets).collect(),
+        }
+    }
+}
+
+/// Everything the passes of a frame draw with.
+pub struct FrameResources<'a> {
+    pub draw_queue: &'a DrawQueue,
+    pub buffers: &'a Buffers,
+    pub projection: &'a ProjectionBindGroups,
+    pub targets: &'a SceneTargets,
+    pub views: &'a TargetViews<'a>,
+    pub clear_color: Vec3,
+    /// The swapchain frame being drawn.
+    pub surface: &'a TextureView,
+}
+
+impl RenderGraph {
+    /// The passes of one frame: visible layers and enabled steps.  Without
+    /// `effects`, staged layers still get their source pass (sprite frame,
+    /// background) but no effect steps.
+    pub fn build(objects: &[DrawObject], effects: bool) -> Self {
+        let layers: Vec<Layer> = objects.iter().map(Layer::of).collect();
+        Self::build_with(&layers, effects, false)
+    }
+
+    /// With `everything`, hidden layers and disabled steps are included
+    /// too and the queue is one segment, so every lifetime in the graph
+    /// covers the one in any frame scripts can produce.
+    fn build_with(layers: &[Layer], effects: bool, everything: bool) -> Self {
+        let mut graph = Self { nodes: Vec::new() };
+        let visible = |layer: &Layer| everything || layer.visible;
+        let segments = if everything {
+            std::iter::once(0..layers.len()).collect()
+        } else {
+            render_targets::segments(layers.iter().map(|l| l.visible && l.samples_background))
+        };
+
+        // A layer that samples the background starts a new segment after
+        // a frame snapshot.
+        let mut cleared = false;
+        for range in segments {
+            let needs_background = !everything
+                && layers
+                    .get(range.start)
+                    .is_some_and(|l| l.visible && l.samples_background);
+            if needs_background {
+                if !cleared {
+                    graph.push(
+                        Pass::DrawLayers {
+                            range: 0..0,
+                            clear: true,
+                        },
+                        Vec::new(),
+                        vec![Resource::Frame],
+                    );
+                    cleared = true;
+                }
+                graph.push(Pass::Snapshot, vec![Resource::Frame], vec![Resource::Background]);
+            }
+
+            let mut reads = Vec::new();
+            for (index, layer) in range.clone().zip(&layers[range.clone()]) {
+                // While loading, targets are allocated only once a phase
+                // is done; until then the layer is drawn as is.
+                let allocated = everything || layer.allocated;
+                if !visible(layer) || !layer.staged || !allocated {
+                    continue;
+                }
+                graph.push_layer(index, layer, effects, everything);
+                reads.push(Resource::Layer(layer.id, Half::A));
+            }
+            if cleared {
+                reads.push(Resource::Frame);
+            }
+            graph.push(
+                Pass::DrawLayers {
+                    range,
+                    clear: !cleared,
+                },
+                reads,
+                vec![Resource::Frame],
+            );
+            cleared = true;
+        }
+
+        graph.push(Pass::Present, vec![Resource::Frame], vec![Resource::Surface]);
+        graph
+    }
+
+    /// The effect chain of one layer, leaving its result in target A.
+    /// Without `effects` only its source is staged.
+    fn push_layer(&mut self, index: usize, layer: &Layer, effects: bool, everything: bool) {
+        let id = layer.id;
+        let source_reads = if layer.copy_background {
+            vec![Resource::Background]
+        } else {
+            Vec::new()
+        };
+        self.push(
+            Pass::LayerSource { object: index },
+            source_reads,
+            vec![Resource::Layer(id, Half::A)],
+        );
+        if !effects {
+            return;
+        }
+        if layer.fbo_sizes.contains_key(FULL_FRAME_BUFFER) {
+            self.push(
+                Pass::LayerBackground { object: index },
+                vec![Resource::Background],
+                vec![Resource::Fbo(index, FULL_FRAME_BUFFER.to_string())],
+            );
+        }
+
+        let mut current = Half::A;
+        for (step_index, step) in layer.steps.iter().enumerate() {
+            // Steps added since the last layout are not bound yet.
+            if !everything && (!step.enabled || !step.bound) {
+                continue;
+            }
+            let source = current;
+            let output = match step.target {
+                // FBO writes don't advance ping-pong state.
+                Some(name) if layer.fbo_sizes.contains_key(name) => {
+                    Resource::Fbo(index, name.to_string())
+                }
+                Some(name) => {
+                    log::error!("unknown FBO '{}', skipping step", name);
+                    continue;
+                }
+                None => {
+                    current = current.other();
+                    Resource::Layer(id, current)
+                }
+            };
+
+            let mut reads = vec![Resource::Layer(id, source)];
+            reads.extend(step_inputs(index, layer, step));
+            self.push(
+                Pass::Effect {
+                    object: index,
+                    step: step_index,
+                    source,
+                },
+                reads,
+                vec![output],
+            );
+        }
+
+        if current == Half::B {
+            self.push(
+                Pass::CopyBack { object: index },
+                vec![Resource::Layer(id, Half::B)],
+                vec![Resource::Layer(id, Half::A)],
+            );
+        }
+    }
+
+    fn push(&mut self, pass: Pass, reads: Vec<Resource>, writes: Vec<Resource>) {
+        self.nodes.push(Node { pass, reads, writes });
+    }
+
+    /// Resources read before any node writes them; they carry last
+    /// frame's contents.
+    fn persistent(&self) -> BTreeSet<&Resource> {
+        let mut written = BTreeSet::new();
+        let mut persistent = BTreeSet::new();
+        for node in &self.nodes {
+            persistent.extend(node.reads.iter().filter(|r| !written.contains(r)));
+            written.extend(&node.writes);
+        }
+        persistent
+    }
+
+    /// Indices of the nodes whose output reaches the swapchain or a
+    /// persistent resource, in order.
+    fn live_nodes(&self) -> Vec<usize> {
+        let mut live = self.persistent();
+        live.insert(&Resource::Surface);
+        let mut kept = Vec::new();
+        for (index, node) in self.nodes.iter().enumerate().rev() {
+            if node.writes.iter().any(|w| live.contains(w)) {
+                kept.push(index);
+                for write in &node.writes {
+                    live.remove(write);
+                }
+                live.extend(&node.reads);
+            }
+        }
+        kept.reverse();
+        kept
+    }
+
+    /// The node range each resource is used in.  Resources read before
+    /// they are written span the whole frame.
+    fn lifetimes(&self) -> BTreeMap<&Resource, Range<usize>> {
+        let mut lifetimes: BTreeMap<&Resource, Range<usize>> = BTreeMap::new();
+        for (position, node) in self.nodes.iter().enumerate() {
+            for resource in node.reads.iter().chain(&node.writes) {
+                lifetimes
+                    .entry(resource)
+                    .and_modify(|lifetime| lifetime.end = position + 1)
+                    .or_insert(position..position + 1);
+            }
+        }
+        for resource in self.persistent() {
+            lifetimes.insert(resource, 0..self.nodes.len());
+        }
+        lifetimes
+    }
+
+    /// Record the live passes into `encoder`.  Effect uniforms must have
+    /// been written for the frame already.
+    pub fn execute(&self, encoder: &mut CommandEncoder, frame: &FrameResources) {
+        let (Some(projection), Some(identity)) = (
+            frame.projection.projection.as_ref(),
+            frame.projection.identity.as_ref(),
+        ) else {
+            log::error!("ABORT: projection not bound");
+            return;
+        };
+        let objects = frame.draw_queue.queue.as_slice();
+        let pipeline = &frame.draw_queue.image_pipeline;
+
+        let live = self.live_nodes();
+        log::trace!("render graph: {} of {} passes live", live.len(), self.nodes.len());
+        for index in live {
+            match &self.nodes[index].pass {
+                Pass::LayerSource { object } => intermediate_pass::layer_source(
+                    encoder,
+                    pipeline,
+                    &objects[*object],
+                    frame.views.background_bindgroup(),
+                    identity,
+                ),
+                Pass::LayerBackground { object } => intermediate_pass::layer_background(
+                    encoder,
+                    pipeline,
+                    &objects[*object],
+                    frame.views.background_bindgroup(),
+                    identity,
+                ),
+                Pass::Effect {
+                    object,
+                    step,
+                    source,
+                } => intermediate_pass::effect_step(
+                    encoder,
+                    &objects[*object],
+                    *step,
+                    *source,
+                    identity,
+                ),
+                Pass::CopyBack { object } => {
+                    intermediate_pass::copy_back(encoder, pipeline, &objects[*object], identity)
+                }
+                Pass::Snapshot => frame.targets.snapshot_background(encoder),
+                Pass::DrawLayers { range, clear } => render_pass::draw_layers(
+                    encoder,
+                    &frame.targets.frame_view,
+                    frame.buffers,
+                    projection,
+                    frame.draw_queue,
+                    range.clone(),
+                    clear.then_some(frame.clear_color),
+                ),
+                Pass::Present => frame.targets.blit(encoder, frame.surface),
+            }
+        }
+    }
+}
+
+/// The resources an effect step samples by name, besides its input.
+fn step_inputs<'a>(
+    index: usize,
+    layer: &'a Layer,
+    step: &'a Step,
+) -> impl Iterator<Item = Resource> + 'a {
+    step.inputs.iter().filter_map(move |name| named_resource(index, layer, name))
+}
+
+/// The resource an effect step samples by name, if the graph tracks it.
+fn named_resource(index: usize, layer: &Layer, name: &str) -> Option<Resource> {
+    // Effect FBOs shadow scene targets of the same name.
+    if layer.fbo_sizes.contains_key(name) {
+        return Some(Resource::Fbo(index, name.to_string()));
+    }
+    if name == FULL_FRAME_BUFFER {
+        return Some(Resource::Background);
+    }
+    let (id, half) = render_targets::parse_layer_composite(name)?;
+    Some(Resource::Layer(id, if half == "b" { Half::B } else { Half::A }))
+}
+
+/// Create the ping-pong pairs and effect FBOs of every layer from one
+/// pool: targets of the same size that are never in use at the same time
+/// share a texture.
+pub fn allocate_targets(
+    device: &Device,
+    queue: &Queue,
+    post_process: &PostProcess,
+    objects: &mut [DrawObject],
+) {
+    // A composite sampled by another layer must still hold that layer's
+    // result when it is hidden and does not redraw it.
+    let (graph, composites) = {
+        let layers: Vec<Layer> = objects.iter().map(Layer::of).collect();
+        let composites: BTreeSet<Resource> = layers
+            .iter()
+            .enumerate()
+            .flat_map(|(index, layer)| {
+                layer.steps.iter().flat_map(move |step| step_inputs(index, layer, step))
+            })
+            .filter(|resource| matches!(resource, Resource::Layer(..)))
+            .collect();
+        (RenderGraph::build_with(&layers, true, true), composites)
+    };
+    let lifetimes = graph.lifetimes();
+
+    let mut targets: Vec<(Resource, [u32; 2])> = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+        if let Some(size) = object.intermediate_size {
+            targets.push((Resource::Layer(object.id, Half::A), size));
+            targets.push((Resource::Layer(object.id, Half::B), size));
+        }
+        targets.extend(
+            object
+                .fbo_sizes
+                .iter()
+                .map(|(name, &size)| (Resource::Fbo(index, name.clone()), size)),
+        );
+    }
+    let items: Vec<([u32; 2], Range<usize>)> = targets
+        .iter()
+        .map(|(resource, size)| {
+            let lifetime = if composites.contains(resource) {
+                0..graph.nodes.len()
+            } else {
+                lifetimes.get(resource).cloned().unwrap_or(0..0)
+            };
+            (*size, lifetime)
+        })
+        .collect();
+    let (slots, count) = alias(&items);
+    let mut bytes = vec![0u64; count];
+    for ((_, [width, height]), &slot) in targets.iter().zip(&slots) {
+        bytes[slot] = *width as u64 * *height as u64 * 4;
+    }
+
+    let mut views: Vec<Option<TextureView>> = vec![None; count];
+    let mut assigned: BTreeMap<Resource, TextureView> = BTreeMap::new();
+    for ((resource, size), slot) in targets.into_iter().zip(slots) {
+        let view = views[slot].get_or_insert_with(|| create_target(device, size));
+        assigned.insert(resource, view.clone());
+    }
+
+    for (index, object) in objects.iter_mut().enumerate() {
+        let pair = (
+            assigned.remove(&Resource::Layer(object.id, Half::A)),
+            assigned.remove(&Resource::Layer(object.id, Half::B)),
+        );
+        if let (Some(view_a), Some(view_b)) = pair {
+            object.intermediates =
+                Some(PingPongTextures::new(device, queue, post_process, view_a, view_b));
+        }
+        for name in object.fbo_sizes.keys() {
+            if let Some(view) = assigned.remove(&Resource::Fbo(index, name.clone())) {
+                object.fbos.insert(name.clone(), view);
+            }
+        }
+    }
+
+    log::debug!(
+        "render targets: {} declared, {} textures, {} MiB",
+        items.len(),
+        count,
+        bytes.iter().sum::<u64>() >> 20
+    );
+}
+
+fn create_target(device: &Device, [width, height]: [u32; 2]) -> TextureView {
+    device
+        .create_texture(&TextureDescriptor {
+            label: Some("effect target"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: COLOR_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+        .create_view(&Default::default())
+}
+
+/// Assign `(key, lifetime)` items to slots so that items sharing a slot
+/// have equal keys and lifetimes that do not overlap; items with an empty
+/// lifetime join any slot of their key.  Returns each item's slot and the
+/// number of slots.
+fn alias<K: PartialEq + Copy>(items: &[(K, Range<usize>)]) -> (Vec<usize>, usize) {
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    order.sort_by_key(|&i| (items[i].1.start == items[i].1.end, items[i].1.start, i));
+
+    // Key and end of the last lifetime of each slot.
+    let mut slots: Vec<(K, usize)> = Vec::new();
+    let mut assigned = vec![0; items.len()];
+    for i in order {
+        let (key, lifetime) = &items[i];
+        let free = slots
+            .iter()
+            .position(|(k, end)| k == key && (lifetime.start == lifetime.end || *end <= lifetime.start));
+        let slot = free.unwrap_or_else(|| {
+            slots.push((*key, 0));
+            slots.len() - 1
+        });
+        slots[slot].1 = slots[slot].1.max(lifetime.end);
+        assigned[i] = slot;
+    }
+    (assigned, slots.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alias() {
+        let items = [
+            ([512, 512], 0..3),
+            ([512, 512], 3..5),
+            ([256, 256], 3..5),
+            ([512, 512], 4..6),
+            ([512, 512], 0..0),
+            ([512, 512], 0..8),
+        ];
+        let (slots, count) = alias(&items);
+        assert_eq!(slots, vec![0, 0, 2, 3, 0, 1]);
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn test_build_without_effects() {
+        let no_fbos = BTreeMap::new();
+        let layer = |id, staged, steps| Layer {
+            id,
+            visible: true,
+            samples_background: false,
+            copy_background: false,
+            staged,
+            allocated: staged,
+            fbo_sizes: &no_fbos,
+            steps,
+        };
+        let step = || Step {
+            enabled: true,
+            bound: true,
+            target: None,
+            inputs: vec!["previous"],
+        };
+        // A sprite-sheet layer with one effect, then a plain layer.
+        let layers = [layer(7, true, vec![step()]), layer(8, false, Vec::new())];
+
+        // The sprite frame is still cut out; only the effect is skipped.
+        let graph = RenderGraph::build_with(&layers, false, false);
+        let passes: Vec<&Pass> = graph.live_nodes().into_iter().map(|i| &graph.nodes[i].pass).collect();
+        assert_eq!(
+            passes,
+            [
+                &Pass::LayerSource { object: 0 },
+                &Pass::DrawLayers { range: 0..2, clear: true },
+                &Pass::Present,
+            ]
+        );
+
+        let graph = RenderGraph::build_with(&layers, true, false);
+        let passes: Vec<&Pass> = graph.nodes.iter().map(|n| &n.pass).collect();
+        assert!(passes.contains(&&Pass::Effect { object: 0, step: 0, source: Half::A }));
+        assert!(passes.contains(&&Pass::CopyBack { object: 0 }));
+    }
+}
@@ -1,8 +1,10 @@
 //! Final rendering pass and effect uniform writing.
 //!
-//! This module handles the final render pass that draws objects to the
-//! swapchain surface, and writes per-frame uniforms (time, cursor, etc.)
-//! into effect bind group buffers.
+//! This module records the pass that draws objects into the scene frame
+//! target, acquires swapchain frames, and writes per-frame uniforms (time,
+//! cursor, etc.) of all effect steps in one upload.
+
+use std::ops::Range;
 
 use glam::Vec3;
 use log;
@@ -11,28 +13,96 @@ use wgpu::*;
 use super::{
     app::UserParams,
     buffer::Buffers,
-    draw::{DrawObject, DrawQueue},
-    post_process::PostProcess,
-    post_processor::effect_param::SystemUniforms,
-    projection::ProjectionBindGroups,
+    color,
+    draw::DrawQueue,
+    post_processor::effect_param::{ObjectUniforms, SystemUniforms},
     surface::AppSurface,
 };
 
-/// Render all draw objects to the swapchain surface.
+/// Record drawing a segment of the draw queue into the scene frame target.
 ///
 /// Each object is drawn with either its direct bind group (no post-processing)
-/// or the intermediate ping-pong texture (after applying effects).
-pub fn render_final_pass(
-    device: &Device,
-    queue: &Queue,
-    surface: &AppSurface,
+/// or its ping-pong target A, which holds the staged source and, unless
+/// effects are off, their result; both bind groups are built ahead of
+/// time.  The target is cleared to `clear_color` when given, otherwise
+/// drawn over.
+pub fn draw_layers(
+    encoder: &mut CommandEncoder,
+    target: &TextureView,
     buffers: &Buffers,
-    projection_bindgroup: &ProjectionBindGroups,
+    projection: &BindGroup,
     draw_queue: &DrawQueue,
-    post_process: &PostProcess,
-    clear_color: Vec3,
-) -> Option<()> {
-    // Acquire the next swapchain frame
+    range: Range<usize>,
+    clear_color: Option<Vec3>,
+) {
+    let load = match clear_color {
+        Some(clear_color) => LoadOp::Clear(color::clear_color(clear_color)),
+        None => LoadOp::Load,
+    };
+
+    log::trace!("drawing objects {:?}...", range);
+    let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: target,
+            depth_slice: None,
+            resolve_target: None,
+            ops: Operations {
+                load,
+                store: StoreOp::Store,
+            },
+        })],
+        ..Default::default()
+    });
+
+    render_pass.set_vertex_buffer(0, buffers.vertex.slice(..));
+    render_pass.set_vertex_buffer(1, buffers.alpha.slice(..));
+    render_pass.set_index_buffer(buffers.index.slice(..), IndexFormat::Uint32);
+    render_pass.set_bind_group(1, projection, &[]);
+
+    for draw_object in draw_queue.queue[range].iter() {
+        if !draw_object.visible {
+            continue;
+        }
+        render_pass.set_pipeline(&draw_queue.image_pipeline);
+
+        // Use the staged result (post-effects) if the layer has one,
+        // otherwise the original texture bind group.
+        let bg = match draw_object.intermediates {
+            Some(ref pp) => &pp.bindgroup_a,
+            None => &draw_object.bindgroup,
+        };
+        render_pass.set_bind_group(0, bg, &[]);
+        // The instance index picks the quad's alpha.
+        let quad = draw_object.vertex_start / 4;
+        render_pass.draw_indexed(
+            draw_object.index_range[0]..draw_object.index_range[1],
+            0,
+            quad..quad + 1,
+        );
+    }
+}
+
+/// Record clearing `target` to `clear_color`.
+pub fn clear(encoder: &mut CommandEncoder, target: &TextureView, clear_color: Vec3) {
+    encoder.begin_render_pass(&RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: target,
+            depth_slice: None,
+            resolve_target: None,
+            ops: Operations {
+                load: LoadOp::Clear(color::clear_color(clear_color)),
+                store: StoreOp::Store,
+            },
+        })],
+        ..Default::default()
+    });
+}
+
+/// Acquire the next swapchain frame and a view of it in the surface view
+/// format.  `None` when the surface had to be reconfigured or failed.
+pub fn acquire(device: &Device, surface: &AppSurface) -> Option<(SurfaceTexture, TextureView)> {
     let output = match surface.surface.get_current_texture() {
         Ok(frame) => {
             log::trace!("acquired swapchain texture");
@@ -56,102 +126,63 @@ pub fn render_final_pass(
 
     let view = output
         .texture
-        .create_view(&TextureViewDescriptor::default());
-    let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
-
-    log::trace!("drawing {} objects...", draw_queue.queue.len());
-    {
-        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-            label: None,
-            color_attachments: &[Some(RenderPassColorAttachment {
-                view: &view,
-                depth_slice: None,
-                resolve_target: None,
-                ops: Operations {
-                    load: LoadOp::Clear(Color {
-                        r: (clear_color.x / 255.0) as f64,
-                        g: (clear_color.y / 255.0) as f64,
-                        b: (clear_color.z / 255.0) as f64,
-                        a: 1.0,
-                    }),
-                    store: StoreOp::Store,
-                },
-            })],
+        .create_view(&TextureViewDescriptor {
+            format: Some(surface.view_format()),
             ..Default::default()
         });
-
-        render_pass.set_vertex_buffer(0, buffers.vertex.slice(..));
-        render_pass.set_index_buffer(buffers.index.slice(..), IndexFormat::Uint32);
-        render_pass.set_bind_group(1, projection_bindgroup.projection.as_ref(), &[]);
-
-        for draw_object in draw_queue.queue.iter() {
-            render_pass.set_pipeline(&draw_queue.image_pipeline);
-
-            // Use the intermediate result (post-effects) if available,
-            // otherwise use the original texture bind group
-            let bg = if let Some(ref pp) = draw_object.intermediates {
-                pp.make_bindgroup(
-                    device,
-                    &post_process.layout,
-                    &post_process.sampler,
-                    &pp.view_a,
-                )
-            } else {
-                draw_object.bindgroup.clone()
-            };
-            render_pass.set_bind_group(0, &bg, &[]);
-            render_pass.draw_indexed(
-                draw_object.index_range[0]..draw_object.index_range[1],
-                0,
-                0..1,
-            );
-        }
-    }
-
-    log::trace!("submitting to queue...");
-    queue.submit(Some(encoder.finish()));
-    log::trace!("presenting...");
-    output.present();
-    log::trace!("frame done");
-    Some(())
+    Some((output, view))
 }
 
-/// Write per-frame uniform data into effect bind group buffers.
+/// Write the per-frame uniforms of every effect step.
+///
+/// Each step's block in the draw queue's uniform buffer holds system
+/// values (time, projection, screen resolution, cursor position) and
+/// material constants.  All blocks are filled in one staging buffer and
+/// uploaded together, once per frame.  Steps that will not run (hidden
+/// objects, disabled effects) keep their previous values.
 ///
-/// Each effect bind group has a uniform buffer containing system values
-/// (time, projection, screen resolution, cursor position) and material
-/// constants. This function populates and uploads that data every frame.
+/// `view_projection` is the scene camera, used for the per-object
+/// model/view-projection and texture projection uniforms.
 pub fn write_effect_uniforms(
     queue: &Queue,
-    objects: &[DrawObject],
+    draw_queue: &DrawQueue,
     elapsed: f32,
-    projection: &[[f32; 4]; 4],
+    view_projection: &[[f32; 4]; 4],
     screen_res: [u32; 2],
     user_params: &UserParams,
 ) {
-    for draw_object in objects {
-        for step in &draw_object.effect_steps {
-            if let Some(ref buf) = step.bindgroup.uniform_buffer {
-                let buf_size = step.bindgroup.uniform_layout.total_size() as usize;
-                let mut staging = vec![0u8; buf_size];
+    draw_queue.uniforms.write(queue, |staging| {
+        for draw_object in draw_queue.queue.iter().filter(|o| o.visible) {
+            let object = ObjectUniforms::new(
+                &draw_object.transform,
+                draw_object.texture_size,
+                draw_object.texture_transform(),
+                view_projection,
+            );
+            for step in draw_object.effect_steps.iter().filter(|s| s.enabled) {
+                let Some(offset) = step.bindgroup.uniform_offset else {
+                    continue;
+                };
+                let layout = &step.bindgroup.uniform_layout;
+                let start = offset as usize;
+                let block = &mut staging[start..start + layout.total_size() as usize];
 
                 let sys = SystemUniforms {
                     screen_resolution: screen_res,
-                    tex_resolutions: step.bindgroup.tex_resolutions.clone(),
+                    tex_resolutions: &step.bindgroup.tex_resolutions,
                     cursor_position: user_params.cursor_position,
+                    audio_spectrum: &user_params.audio_spectrum,
+                    object,
                 };
 
-                step.bindgroup.uniform_layout.populate_effect_params(
-                    &mut staging,
+                layout.populate_effect_params(
+                    block,
                     &step.bindgroup.constants,
                     &step.bindgroup.material_keys,
                     elapsed,
-                    projection,
                     &sys,
                 );
-
-                queue.write_buffer(buf, 0, &staging);
             }
         }
-    }
+    });
 }
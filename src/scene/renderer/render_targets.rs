@@ -0,0 +1,317 @@
+//! Scene-level render targets that effects sample by name.
+//!
+//! The final pass draws into [`SceneTargets::frame_view`] rather than straight
+//! to the swapchain, which is blitted to at the end of the frame.  Layers
+//! that sample `_rt_FullFrameBuffer` or set `copybackground` split the
+//! frame into segments: everything drawn before such a layer is copied
+//! into the background target before its effects run.  A layer's effect
+//! output is exposed as `_rt_imageLayerComposite_<id>_a` (and `_b`).
+//! Layers see only the part of the background under their quad, cut out
+//! with [`screen_uvs`]: it is their source for `copybackground`, and their
+//! `_rt_FullFrameBuffer`, since effect quads fill the layer's own target.
+//! Both targets use [`COLOR_FORMAT`]; only the blit writes the surface
+//! format.  Their bind groups are built with the targets and rebuilt when
+//! a resize recreates them.
+
+use std::{collections::BTreeMap, ops::Range};
+
+use glam::Mat4;
+use wgpu::*;
+
+use super::{
+    buffer::texture_corners,
+    color::COLOR_FORMAT,
+    draw::{DrawObject, LayerTransform},
+    ping_pong::PingPongTextures,
+};
+
+pub const FULL_FRAME_BUFFER: &str = "_rt_FullFrameBuffer";
+const LAYER_COMPOSITE: &str = "_rt_imageLayerComposite_";
+
+pub struct SceneTargets {
+    frame: Texture,
+    pub frame_view: TextureView,
+    frame_bindgroup: BindGroup,
+    background: Texture,
+    pub background_view: TextureView,
+    pub background_bindgroup: BindGroup,
+    blit: RenderPipeline,
+    /// Post-process texture layout and sampler the bind groups use.
+    layout: BindGroupLayout,
+    sampler: Sampler,
+}
+
+impl SceneTargets {
+    /// `surface_format` is the format swapchain frames are viewed as.
+    pub fn new(
+        device: &Device,
+        surface_format: TextureFormat,
+        size: [u32; 2],
+        layout: &BindGroupLayout,
+        sampler: &Sampler,
+    ) -> Self {
+        let (frame, frame_view) = create_target(device, size, TextureUsages::COPY_SRC);
+        let (background, background_view) = create_target(device, size, TextureUsages::COPY_DST);
+
+        Self {
+            frame_bindgroup: create_bindgroup(device, layout, sampler, &frame_view),
+            background_bindgroup: create_bindgroup(device, layout, sampler, &background_view),
+            frame,
+            frame_view,
+            background,
+            background_view,
+            blit: create_blit_pipeline(device, surface_format, layout),
+            layout: layout.clone(),
+            sampler: sampler.clone(),
+        }
+    }
+
+    /// Recreate the targets for a new surface size.  Returns false when
+    /// the size did not change.
+    pub fn resize(&mut self, device: &Device, size: [u32; 2]) -> bool {
+        if [self.frame.width(), self.frame.height()] == size {
+            return false;
+        }
+        (self.frame, self.frame_view) = create_target(device, size, TextureUsages::COPY_SRC);
+        (self.background, self.background_view) =
+            create_target(device, size, TextureUsages::COPY_DST);
+        self.frame_bindgroup =
+            create_bindgroup(device, &self.layout, &self.sampler, &self.frame_view);
+        self.background_bindgroup =
+            create_bindgroup(device, &self.layout, &self.sampler, &self.background_view);
+        true
+    }
+
+    /// Copy everything drawn so far into the background target.
+    pub fn snapshot_background(&self, encoder: &mut CommandEncoder) {
+        encoder.copy_texture_to_texture(
+            self.frame.as_image_copy(),
+            self.background.as_image_copy(),
+            self.frame.size(),
+        );
+    }
+
+    /// Draw the frame target over `target`.
+    pub fn blit(&self, encoder: &mut CommandEncoder, target: &TextureView) {
+        self.blit_bindgroup(encoder, &self.frame_bindgroup, target);
+    }
+
+    /// Draw `source`, stretched, over `target` (which must have the
+    /// surface view format).
+    pub fn blit_view(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        source: &TextureView,
+        target: &TextureView,
+    ) {
+        let bindgroup = create_bindgroup(device, &self.layout, &self.sampler, source);
+        self.blit_bindgroup(encoder, &bindgroup, target);
+    }
+
+    fn blit_bindgroup(
+        &self,
+        encoder: &mut CommandEncoder,
+        bindgroup: &BindGroup,
+        target: &TextureView,
+    ) {
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                depth_slice: None,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK),
+                    store: StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        pass.set_pipeline(&self.blit);
+        pass.set_bind_group(0, bindgroup, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+/// Resolves `_rt_*` names to texture views for one frame.
+pub struct TargetViews<'a> {
+    background: &'a TextureView,
+    background_bindgroup: &'a BindGroup,
+    composites: BTreeMap<i64, &'a PingPongTextures>,
+}
+
+impl<'a> TargetViews<'a> {
+    pub fn new(targets: &'a SceneTargets, objects: &'a [DrawObject]) -> Self {
+        let composites = objects
+            .iter()
+            .filter_map(|o| Some((o.id, o.intermediates.as_ref()?)))
+            .collect();
+        Self {
+            background: &targets.background_view,
+            background_bindgroup: &targets.background_bindgroup,
+            composites,
+        }
+    }
+
+    pub fn background_bindgroup(&self) -> &'a BindGroup {
+        self.background_bindgroup
+    }
+
+    pub fn get(&self, name: &str) -> Option<&'a TextureView> {
+        if name == FULL_FRAME_BUFFER {
+            return Some(self.background);
+        }
+        let (id, half) = parse_layer_composite(name)?;
+        let pp = self.composites.get(&id)?;
+        Some(if half == "b" { &pp.view_b } else { &pp.view_a })
+    }
+}
+
+/// Split the draw queue before every visible layer that needs the
+/// background, given whether each layer is one.  Always yields at least
+/// one (possibly empty) range.
+pub fn segments(needs_background: impl IntoIterator<Item = bool>) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut len = 0;
+    for (i, needs) in needs_background.into_iter().enumerate() {
+        if i > start && needs {
+            ranges.push(start..i);
+            start = i;
+        }
+        len = i + 1;
+    }
+    ranges.push(start..len);
+    ranges
+}
+
+/// Where the corners of a layer's quad land on the frame, as UVs in the
+/// order of [`NDC_VERTICES`](super::vertex::NDC_VERTICES).  `view_projection`
+/// is the scene camera the layer is drawn with.
+pub fn screen_uvs(transform: &LayerTransform, view_projection: &Mat4) -> [[f32; 2]; 4] {
+    let corners = texture_corners(transform.origin, transform.angles, transform.scale, transform.size);
+    corners.map(|corner| {
+        let ndc = view_projection.project_point3(corner);
+        [ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5]
+    })
+}
+
+/// `_rt_imageLayerComposite_12_a` → `(12, "a")`.
+pub(super) fn parse_layer_composite(name: &str) -> Option<(i64, &str)> {
+    let rest = name.strip_prefix(LAYER_COMPOSITE)?;
+    let (id, half) = rest.split_once('_').unwrap_or((rest, "a"));
+    Some((id.parse().ok()?, half))
+}
+
+fn create_bindgroup(
+    device: &Device,
+    layout: &BindGroupLayout,
+    sampler: &Sampler,
+    view: &TextureView,
+) -> BindGroup {
+    device.create_bind_group(&BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
+
+fn create_target(device: &Device, size: [u32; 2], usage: TextureUsages) -> (Texture, TextureView) {
+    let texture = device.create_texture(&TextureDescriptor {
+        label: None,
+        size: Extent3d {
+            width: size[0].max(1),
+            height: size[1].max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: COLOR_FORMAT,
+        usage: usage | TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&Default::default());
+    (texture, view)
+}
+
+fn create_blit_pipeline(device: &Device, format: TextureFormat, layout: &BindGroupLayout) -> RenderPipeline {
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: None,
+        source: ShaderSource::Wgsl(include_str!("./shader/blit.wgsl").into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[layout],
+        immediate_size: 0,
+    });
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&pipeline_layout),
+        vertex: VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            compilation_options: Default::default(),
+            buffers: &[],
+        },
+        primitive: PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: MultisampleState::default(),
+        fragment: Some(FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            targets: &[Some(ColorTargetState {
+                format,
+                blend: None,
+                write_mask: ColorWrites::all(),
+            })],
+        }),
+        multiview_mask: None,
+        cache: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_layer_composite() {
+        assert_eq!(parse_layer_composite("_rt_imageLayerComposite_42_a"), Some((42, "a")));
+        assert_eq!(parse_layer_composite("_rt_imageLayerComposite_42_b"), Some((42, "b")));
+        assert_eq!(parse_layer_composite("_rt_imageLayerComposite_7"), Some((7, "a")));
+        assert_eq!(parse_layer_composite(FULL_FRAME_BUFFER), None);
+    }
+
+    #[test]
+    fn test_screen_uvs() {
+        let camera = Mat4::orthographic_rh(0.0, 1920.0, 0.0, 1080.0, -100.0, 100.0);
+        // A 100×50 layer at twice its size, centred at (480, 270).
+        let transform = LayerTransform {
+            origin: glam::Vec3::new(480.0, 270.0, 0.0),
+            angles: glam::Vec3::ZERO,
+            scale: glam::Vec3::new(2.0, 2.0, 1.0),
+            size: glam::Vec2::new(100.0, 50.0),
+        };
+        let uvs = screen_uvs(&transform, &camera);
+        let expected = [[380.0, 320.0], [580.0, 320.0], [580.0, 220.0], [380.0, 220.0]]
+            .map(|[x, y]: [f32; 2]| [x / 1920.0, 1.0 - y / 1080.0]);
+        for (uv, expected) in uvs.iter().zip(expected) {
+            assert!((uv[0] - expected[0]).abs() < 1e-5 && (uv[1] - expected[1]).abs() < 1e-5);
+        }
+        // The top-left corner is the one nearest the top of the frame.
+        assert!(uvs[0][1] < uvs[3][1]);
+    }
+}
@@ -0,0 +1,259 @@
+//! Glue between the [`ScriptHost`] and the draw queue.
+//!
+//! Registers every layer and every scripted property at load time, then
+//! applies script results each frame: layer transforms are rewritten into
+//! the vertex buffer, alpha into the alpha buffer, visibility toggles the
+//! draw object or effect, and scripted shader constants replace the step's
+//! static constant.
+
+use std::{collections::BTreeMap, rc::Rc};
+
+use glam::Vec3;
+use wgpu::Queue;
+
+use crate::scene::{
+    loader::{object_loader::TextureObject, project::UserProperties},
+    script::{FrameInfo, LayerState, PropertyScript, ScriptHost, ScriptId},
+};
+
+use super::{
+    buffer::Buffers,
+    draw::{DrawQueue, LayerTransform},
+};
+
+/// Where a layer lives in the draw queue and what it looked like at load.
+struct LayerBinding {
+    index: usize,
+    /// World transform at load time (after parent propagation).
+    base: LayerTransform,
+    /// Authored (parent-local) transform at load time.
+    local: LayerState,
+}
+
+/// A scripted shader constant: object index, step index, material key.
+struct ConstantBinding {
+    object: usize,
+    step: usize,
+    key: String,
+}
+
+/// A scripted effect visibility: object index and effect id.
+struct EffectBinding {
+    object: usize,
+    effect: i64,
+}
+
+pub struct SceneScripts {
+    host: ScriptHost,
+    layers: BTreeMap<i64, LayerBinding>,
+    constants: BTreeMap<ScriptId, ConstantBinding>,
+    /// Draw-queue index of the object owning each effect id.
+    effects: BTreeMap<i64, usize>,
+    effect_scripts: BTreeMap<ScriptId, EffectBinding>,
+}
+
+impl SceneScripts {
+    /// Register all layers, their effects and their property scripts.
+    /// `objects` must be in draw-queue order.
+    pub fn new(objects: &[TextureObject], properties: &UserProperties) -> Option<Self> {
+        let mut host = ScriptHost::new(properties)?;
+        let mut layers = BTreeMap::new();
+        let mut effects = BTreeMap::new();
+        let mut effect_scripts = BTreeMap::new();
+
+        for (index, obj) in objects.iter().enumerate() {
+            let local = LayerState {
+                origin: obj.local_origin.to_array(),
+                scale: obj.local_scale.to_array(),
+                angles: obj.local_angles.to_array(),
+                visible: obj.visible,
+                alpha: obj.alpha,
+            };
+            host.add_layer(obj.id, &obj.name, &local);
+            layers.insert(
+                obj.id,
+                LayerBinding {
+                    index,
+                    base: LayerTransform {
+                        origin: obj.origin,
+                        angles: obj.angles,
+                        scale: obj.scale,
+                        size: obj.size,
+                    },
+                    local,
+                },
+            );
+            for effect in &obj.effects {
+                host.add_effect(
+                    obj.id,
+                    effect.id,
+                    &effect.name,
+                    effect.is_visible(properties),
+                );
+                effects.insert(effect.id, index);
+            }
+        }
+
+        for obj in objects {
+            for (prop, script) in &obj.scripts {
+                if host.register_layer_property(obj.id, *prop, script).is_none() {
+                    log::warn!("layer '{}': {:?} script disabled", obj.name, prop);
+                }
+            }
+        }
+
+        for (index, obj) in objects.iter().enumerate() {
+            for effect in &obj.effects {
+                let Some(script) = effect.visible.as_ref().and_then(|v| v.script()) else {
+                    continue;
+                };
+                match host.register_value(obj.id, &script) {
+                    Some(id) => {
+                        effect_scripts.insert(
+                            id,
+                            EffectBinding {
+                                object: index,
+                                effect: effect.id,
+                            },
+                        );
+                    }
+                    None => log::warn!("effect '{}': visibility script disabled", effect.name),
+                }
+            }
+        }
+
+        Some(Self {
+            host,
+            layers,
+            constants: BTreeMap::new(),
+            effects,
+            effect_scripts,
+        })
+    }
+
+    /// Register scripted shader constants found in the built effect steps.
+    pub fn bind_constants(&mut self, draw_queue: &DrawQueue) {
+        for (object, draw_object) in draw_queue.queue.iter().enumerate() {
+            for (step, effect_step) in draw_object.effect_steps.iter().enumerate() {
+                for (key, value) in &effect_step.bindgroup.constants {
+                    let Some(script) = PropertyScript::from_value(value) else {
+                        continue;
+                    };
+                    if let Some(id) = self.host.register_value(draw_object.id, &script) {
+                        self.constants.insert(
+                            id,
+                            ConstantBinding {
+                                object,
+                                step,
+                                key: key.clone(),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.host.is_empty()
+    }
+
+    pub fn uses_audio(&self) -> bool {
+        self.host.uses_audio()
+    }
+
+    /// Run all scripts for one frame and apply their results.
+    pub fn update(
+        &mut self,
+        queue: &Queue,
+        buffers: &Buffers,
+        draw_queue: &mut DrawQueue,
+        frame: &FrameInfo,
+    ) {
+        let output = self.host.tick(frame);
+        if output.layers.is_empty() && output.values.is_empty() && output.effects.is_empty() {
+            return;
+        }
+
+        // The draw queue is only shared while a frame is being recorded.
+        let Some(objects) = Rc::get_mut(&mut draw_queue.queue) else {
+            log::warn!("scenescript: draw queue is shared, skipping update");
+            return;
+        };
+
+        for (id, state) in output.layers {
+            let Some(binding) = self.layers.get(&id) else {
+                continue;
+            };
+            let Some(draw_object) = objects.get_mut(binding.index) else {
+                continue;
+            };
+
+            let transform = apply_local_change(&binding.base, &binding.local, &state);
+            draw_object.transform = transform;
+            draw_object.visible = state.visible;
+            buffers.update_texture(
+                queue,
+                draw_object.vertex_start,
+                transform.origin,
+                transform.angles,
+                transform.scale,
+                transform.size,
+            );
+            buffers.update_alpha(queue, draw_object.vertex_start, state.alpha);
+        }
+
+        for (effect, visible) in output.effects {
+            if let Some(draw_object) = self.effects.get(&effect).and_then(|&i| objects.get_mut(i)) {
+                draw_object.set_effect_visible(effect, visible);
+            }
+        }
+
+        for (id, value) in output.values {
+            if let Some(binding) = self.effect_scripts.get(&id) {
+                if let (Some(draw_object), Some(visible)) =
+                    (objects.get_mut(binding.object), value.as_bool())
+                {
+                    draw_object.set_effect_visible(binding.effect, visible);
+                }
+                continue;
+            }
+            let Some(binding) = self.constants.get(&id) else {
+                continue;
+            };
+            if let Some(step) = objects
+                .get_mut(binding.object)
+                .and_then(|o| o.effect_steps.get_mut(binding.step))
+            {
+                step.bindgroup.constants.insert(binding.key.clone(), value);
+            }
+        }
+    }
+}
+
+/// Scripts edit the authored (parent-local) transform.  Apply the change
+/// relative to the load-time local values on top of the load-time world
+/// transform so parented layers keep their inherited offset.
+fn apply_local_change(
+    base: &LayerTransform,
+    local: &LayerState,
+    state: &LayerState,
+) -> LayerTransform {
+    let local_origin = Vec3::from_array(local.origin);
+    let local_angles = Vec3::from_array(local.angles);
+    let local_scale = Vec3::from_array(local.scale);
+    let new_scale = Vec3::from_array(state.scale);
+
+    let scale_ratio = Vec3::select(
+        local_scale.cmpeq(Vec3::ZERO),
+        new_scale,
+        new_scale / local_scale,
+    );
+
+    LayerTransform {
+        origin: base.origin + (Vec3::from_array(state.origin) - local_origin),
+        angles: base.angles + (Vec3::from_array(state.angles) - local_angles),
+        scale: base.scale * scale_ratio,
+        size: base.size,
+    }
+}
@@ -1,6 +1,7 @@
 struct VertexOutput {
     @builtin(position) clip_pos: vec4f,
     @location(0) uv: vec2f,
+    @location(1) alpha: f32,
 }
 
 @group(0) @binding(0) var tex: texture_2d<f32>;
@@ -8,16 +9,18 @@ struct VertexOutput {
 @group(1) @binding(0) var<uniform> projection_matrix: mat4x4f;
 
 @vertex
-fn vs_main(@location(0) pos: vec3f, @location(1) uv: vec2f) -> VertexOutput {
+fn vs_main(@location(0) pos: vec3f, @location(1) uv: vec2f, @location(2) alpha: f32) -> VertexOutput {
     var output: VertexOutput;
     output.clip_pos = projection_matrix * vec4f(pos, 1);
     output.uv = uv;
+    output.alpha = saturate(alpha);
     return output;
 }
 
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4f {
     var color = textureSample(tex, tex_sampler, input.uv);
+    color.a *= input.alpha;
 
     return color;
 }
@@ -0,0 +1,60 @@
+//! Sprite-sheet animation of layer textures.
+//!
+//! The sheet is uploaded once as the layer's texture.  Each frame the
+//! current cell is cut out by the source pass of the intermediate chain,
+//! which draws a full-screen quad whose UVs cover only that cell, so
+//! effects and the final pass see a single frame.
+
+use std::{cell::Cell, rc::Rc};
+
+use wgpu::*;
+
+use crate::scene::loader::tex_frames::{TexFrame, TexFrames};
+
+use super::vertex::{NDC_VERTICES, Vertex};
+
+pub struct SpriteAnimation {
+    pub frames: Rc<TexFrames>,
+    /// NDC quad with the current frame's UVs.
+    pub vbuf: Buffer,
+    current: Cell<Option<usize>>,
+}
+
+impl SpriteAnimation {
+    pub fn new(device: &Device, queue: &Queue, frames: Rc<TexFrames>) -> Self {
+        let vbuf = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: std::mem::size_of::<Vertex>() as u64 * 4,
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sprite = Self {
+            frames,
+            vbuf,
+            current: Cell::new(None),
+        };
+        sprite.update(queue, 0.0);
+        sprite
+    }
+
+    /// The frame on screen.
+    pub fn current_frame(&self) -> &TexFrame {
+        &self.frames.frames[self.current.get().unwrap_or(0)]
+    }
+
+    /// Show the frame due `seconds` into the animation.
+    pub fn update(&self, queue: &Queue, seconds: f32) {
+        let index = self.frames.frame_index(seconds);
+        if self.current.get() == Some(index) {
+            return;
+        }
+        self.current.set(Some(index));
+
+        let uvs = self.frames.uv_corners(&self.frames.frames[index]);
+        let quad: [Vertex; 4] = std::array::from_fn(|i| Vertex {
+            pos: NDC_VERTICES[i].pos,
+            uv: uvs[i],
+        });
+        queue.write_buffer(&self.vbuf, 0, bytemuck::bytes_of(&quad));
+    }
+}
@@ -3,6 +3,8 @@ use std::{fmt::Debug, sync::Arc};
 use raw_window_handle::{RawDisplayHandle, RawWindowHandle};
 use wgpu::*;
 
+use super::color;
+
 #[derive(Debug)]
 pub struct AppSurface {
     pub surface: Surface<'static>,
@@ -34,19 +36,26 @@ impl AppSurface {
         };
 
         let cap = wgpu_surface.get_capabilities(adapter);
+        let (format, view_format) = color::surface_formats(&cap.formats);
+        log::debug!("surface format {:?}, viewed as {:?}", format, view_format);
 
         Self {
             surface: wgpu_surface,
             config: SurfaceConfiguration {
                 usage: TextureUsages::RENDER_ATTACHMENT,
-                format: cap.formats[0],
+                format,
                 width: size[0],
                 height: size[1],
                 present_mode: PresentMode::Fifo,
                 alpha_mode: CompositeAlphaMode::Auto,
-                view_formats: vec![],
+                view_formats: if view_format == format { vec![] } else { vec![view_format] },
                 desired_maximum_frame_latency: 2,
             },
         }
     }
+
+    /// Format swapchain frames are viewed and rendered as.
+    pub fn view_format(&self) -> TextureFormat {
+        self.config.view_formats.first().copied().unwrap_or(self.config.format)
+    }
 }
@@ -19,6 +19,18 @@ pub const NDC_VERTICES: [Vertex; 4] = [
     },
 ];
 
+/// Effect quad in pass space: `size` pixels centred on the origin, with
+/// the UVs of [`NDC_VERTICES`].  Effect shaders see these positions in
+/// `a_Position`, and `g_ModelViewProjectionMatrix` maps them back onto
+/// the whole target.
+pub fn pass_vertices(size: [u32; 2]) -> [Vertex; 4] {
+    let half = [size[0] as f32 / 2.0, size[1] as f32 / 2.0];
+    NDC_VERTICES.map(|v| Vertex {
+        pos: [v.pos[0] * half[0], v.pos[1] * half[1], 0.0],
+        uv: v.uv,
+    })
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
 pub struct Vertex {
@@ -46,3 +58,17 @@ impl Vertex {
         }
     }
 }
+
+/// Layer alpha of the image pipeline: one `f32` per quad, read per
+/// instance so a quad's index selects its value.
+pub fn create_alpha_layout<'a>() -> VertexBufferLayout<'a> {
+    VertexBufferLayout {
+        array_stride: std::mem::size_of::<f32>() as BufferAddress,
+        step_mode: VertexStepMode::Instance,
+        attributes: &[VertexAttribute {
+            offset: 0,
+            shader_location: 2,
+            format: VertexFormat::Float32,
+        }],
+    }
+}
@@ -0,0 +1,73 @@
+//! GPU texture streamed from a [`VideoStream`].
+
+use std::cell::RefCell;
+
+use wgpu::*;
+
+use crate::scene::video::VideoStream;
+
+use super::color::COLOR_FORMAT;
+
+pub struct VideoTexture {
+    stream: RefCell<VideoStream>,
+    pub texture: Texture,
+}
+
+impl VideoTexture {
+    /// Start playing an mp4/webm payload.  `None` when the bytes are not
+    /// a video or cannot be decoded.
+    pub fn open(device: &Device, payload: &[u8]) -> Option<Self> {
+        let stream = VideoStream::open(payload)?;
+        Some(Self::new(device, stream))
+    }
+
+    pub fn new(device: &Device, stream: VideoStream) -> Self {
+        let [width, height] = stream.size();
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("video texture"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: COLOR_FORMAT,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        Self {
+            stream: RefCell::new(stream),
+            texture,
+        }
+    }
+
+    /// Upload the frame due at `clock` seconds, if it changed.
+    pub fn update(&self, queue: &Queue, clock: f64) {
+        let Some(frame) = self.stream.borrow_mut().frame_at(clock) else {
+            return;
+        };
+        let size = self.texture.size();
+        if frame.rgba.len() < (size.width * size.height * 4) as usize {
+            log::warn!("video: short frame ({} bytes), skipped", frame.rgba.len());
+            return;
+        }
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &frame.rgba,
+            TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(size.width * 4),
+                rows_per_image: None,
+            },
+            size,
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Video wallpapers (`"type": "video"` projects).
+//!
+//! The video is drawn over the whole scene frame; fitting it to the output
+//! is left to the adapter, which sizes the surface from the video
+//! resolution like it does for a scene's projection size.  The audio track
+//! plays through the app's [`AudioPlayback`] and loops with the video.
+
+use std::path::Path;
+
+use rodio::Source;
+use wgpu::*;
+
+use crate::scene::{audio::AudioPlayback, video::VideoStream};
+
+use super::{render_targets::SceneTargets, video_texture::VideoTexture};
+
+pub struct VideoWallpaper {
+    video: VideoTexture,
+    view: TextureView,
+}
+
+impl VideoWallpaper {
+    pub fn open(device: &Device, path: &Path, audio: &AudioPlayback) -> Option<Self> {
+        let stream = VideoStream::open_file(path)?;
+        let video = VideoTexture::new(device, stream);
+        let view = video.texture.create_view(&TextureViewDescriptor::default());
+        play_audio(path, audio);
+        Some(Self { video, view })
+    }
+
+    /// Video size in pixels.
+    pub fn size(&self) -> [u32; 2] {
+        let size = self.video.texture.size();
+        [size.width, size.height]
+    }
+
+    /// Upload the frame due at `clock` seconds and record drawing it into
+    /// the scene frame.
+    pub fn render(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        targets: &SceneTargets,
+        clock: f64,
+    ) {
+        self.video.update(queue, clock);
+        targets.blit_view(device, encoder, &self.view, &targets.frame_view);
+    }
+}
+
+/// Loop the file's audio track, if it has one rodio can decode.
+fn play_audio(path: &Path, audio: &AudioPlayback) {
+    let source = std::fs::File::open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|file| rodio::Decoder::try_from(file).map_err(|e| e.to_string()));
+    match source {
+        Ok(source) => audio.add(source.repeat_infinite()),
+        Err(e) => log::info!("video: no playable audio in '{}': {}", path.display(), e),
+    }
+}
@@ -0,0 +1,322 @@
+//! SceneScript runtime.
+//!
+//! Wallpaper Engine lets authors attach a JavaScript `update(value)`
+//! function to almost any property (layer transforms, visibility, shader
+//! constants).  [`ScriptHost`] embeds a sandboxed QuickJS context that
+//! evaluates those functions once per frame.
+//!
+//! The host knows nothing about the renderer: layers are registered with
+//! their id and initial state, scripts are registered against a layer
+//! property or as a free-standing value, and [`ScriptHost::tick`] returns
+//! the changed layer states and script values for the caller to apply.
+
+mod source;
+
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use rquickjs::{Context, Function, Runtime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::scene::{audio::AudioSpectrum, loader::project::UserProperties};
+
+pub use source::{PropertyScript, ValueKind};
+
+/// Upper bound on JS heap usage for one scene.
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+/// Scripts running longer than this in a single frame are interrupted.
+const FRAME_BUDGET: Duration = Duration::from_millis(50);
+
+/// Handle returned by [`ScriptHost::register_layer_property`] and
+/// [`ScriptHost::register_value`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ScriptId(pub u32);
+
+/// Layer property a script can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerProperty {
+    Origin,
+    Scale,
+    Angles,
+    Visible,
+    Alpha,
+}
+
+impl LayerProperty {
+    fn as_str(self) -> &'static str {
+        match self {
+            LayerProperty::Origin => "origin",
+            LayerProperty::Scale => "scale",
+            LayerProperty::Angles => "angles",
+            LayerProperty::Visible => "visible",
+            LayerProperty::Alpha => "alpha",
+        }
+    }
+
+    fn kind(self) -> ValueKind {
+        match self {
+            LayerProperty::Origin | LayerProperty::Scale | LayerProperty::Angles => ValueKind::Vec3,
+            LayerProperty::Visible => ValueKind::Bool,
+            LayerProperty::Alpha => ValueKind::Number,
+        }
+    }
+}
+
+/// Script-visible state of a layer (`thisLayer` / `thisScene.getLayer`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerState {
+    pub origin: [f32; 3],
+    pub scale: [f32; 3],
+    pub angles: [f32; 3],
+    pub visible: bool,
+    pub alpha: f32,
+}
+
+/// Per-frame inputs exposed through `engine` and `input`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameInfo {
+    /// Seconds since the wallpaper started.
+    pub runtime: f32,
+    /// Seconds since the previous frame.
+    pub frametime: f32,
+    pub screen: [u32; 2],
+    /// Scene (orthogonal projection) size.
+    pub canvas: [u32; 2],
+    /// Normalized cursor position, (0,0) = top-left.
+    pub cursor: [f32; 2],
+    /// Spectrum behind `engine.registerAudioBuffers`.
+    pub audio: AudioSpectrum,
+}
+
+/// Result of one [`ScriptHost::tick`].
+#[derive(Debug, Default)]
+pub struct ScriptOutput {
+    /// Layers whose state changed this frame.
+    pub layers: Vec<(i64, LayerState)>,
+    /// New values of scripts not bound to a layer property.
+    pub values: Vec<(ScriptId, Value)>,
+    /// Effects whose visibility changed this frame, by effect id.
+    pub effects: Vec<(i64, bool)>,
+}
+
+#[derive(Deserialize)]
+struct TickResult {
+    values: BTreeMap<u32, Value>,
+    /// Checked one by one, so a layer the scripts broke (a NaN turns
+    /// into `null`) does not cost the others their update.
+    layers: BTreeMap<i64, Value>,
+    #[serde(default)]
+    effects: BTreeMap<i64, bool>,
+    errors: Vec<String>,
+}
+
+pub struct ScriptHost {
+    _runtime: Runtime,
+    context: Context,
+    deadline: Rc<Cell<Option<Instant>>>,
+    next_id: u32,
+    /// A registered script calls `engine.registerAudioBuffers`.
+    uses_audio: bool,
+}
+
+impl ScriptHost {
+    /// `properties` are the wallpaper's user properties, exposed as
+    /// `engine.userProperties`.
+    pub fn new(properties: &UserProperties) -> Option<Self> {
+        let runtime = Runtime::new().ok()?;
+        runtime.set_memory_limit(MEMORY_LIMIT);
+
+        let deadline: Rc<Cell<Option<Instant>>> = Rc::new(Cell::new(None));
+        let interrupt_deadline = Rc::clone(&deadline);
+        runtime.set_interrupt_handler(Some(Box::new(move || {
+            interrupt_deadline
+                .get()
+                .is_some_and(|d| Instant::now() > d)
+        })));
+
+        let context = Context::full(&runtime).ok()?;
+        let loaded = context.with(|ctx| -> rquickjs::Result<()> {
+            ctx.globals().set(
+                "__wpeLog",
+                Function::new(ctx.clone(), |msg: String| {
+                    log::info!("scenescript: {}", msg);
+                })?,
+            )?;
+            ctx.eval::<(), _>(include_str!("./prelude.js"))?;
+            let set: Function = ctx.globals().get("__wpeSetUserProperties")?;
+            set.call((serde_json::to_string(properties).unwrap_or_default(),))
+        });
+        if let Err(e) = loaded {
+            log::error!("scenescript: failed to load prelude: {}", e);
+            return None;
+        }
+
+        Some(Self {
+            _runtime: runtime,
+            context,
+            deadline,
+            next_id: 0,
+            uses_audio: false,
+        })
+    }
+
+    /// Make a layer reachable through `thisLayer` / `thisScene.getLayer`.
+    pub fn add_layer(&mut self, id: i64, name: &str, state: &LayerState) {
+        let json = serde_json::json!({
+            "id": id,
+            "name": name,
+            "origin": vec3_string(state.origin),
+            "scale": vec3_string(state.scale),
+            "angles": vec3_string(state.angles),
+            "visible": state.visible,
+            "alpha": state.alpha,
+        })
+        .to_string();
+
+        let result = self.context.with(|ctx| -> rquickjs::Result<()> {
+            let add: Function = ctx.globals().get("__wpeAddLayer")?;
+            add.call((json,))
+        });
+        if let Err(e) = result {
+            log::warn!("scenescript: failed to add layer '{}': {}", name, e);
+        }
+    }
+
+    /// Make an effect reachable through `layer.getEffect`.
+    pub fn add_effect(&mut self, layer_id: i64, effect_id: i64, name: &str, visible: bool) {
+        let result = self.context.with(|ctx| -> rquickjs::Result<()> {
+            let add: Function = ctx.globals().get("__wpeAddEffect")?;
+            add.call((layer_id, effect_id, name, visible))
+        });
+        if let Err(e) = result {
+            log::warn!("scenescript: failed to add effect '{}': {}", name, e);
+        }
+    }
+
+    /// Register a script whose result is written to a layer property.
+    pub fn register_layer_property(
+        &mut self,
+        layer_id: i64,
+        property: LayerProperty,
+        script: &PropertyScript,
+    ) -> Option<ScriptId> {
+        self.register_inner(layer_id, Some(property.as_str()), property.kind(), script)
+    }
+
+    /// Register a script whose result is returned from [`tick`](Self::tick)
+    /// (shader constants and other non-layer values).
+    pub fn register_value(&mut self, layer_id: i64, script: &PropertyScript) -> Option<ScriptId> {
+        let kind = ValueKind::infer(&script.value);
+        self.register_inner(layer_id, None, kind, script)
+    }
+
+    fn register_inner(
+        &mut self,
+        layer_id: i64,
+        property: Option<&str>,
+        kind: ValueKind,
+        script: &PropertyScript,
+    ) -> Option<ScriptId> {
+        let id = ScriptId(self.next_id);
+        let wrapped = source::wrap_module(&script.source);
+        let initial = script.value.to_string();
+        let properties = script.properties.to_string();
+
+        self.deadline.set(Some(Instant::now() + FRAME_BUDGET));
+        let result = self.context.with(|ctx| -> rquickjs::Result<()> {
+            let factory: Function = ctx.eval(wrapped)?;
+            let register: Function = ctx.globals().get("__wpeRegister")?;
+            register.call((
+                id.0,
+                layer_id,
+                kind.as_str(),
+                property,
+                initial,
+                properties,
+                factory,
+            ))
+        });
+        self.deadline.set(None);
+
+        match result {
+            Ok(()) => {
+                self.next_id += 1;
+                self.uses_audio |= script.source.contains("registerAudioBuffers");
+                Some(id)
+            }
+            Err(e) => {
+                log::warn!("scenescript: failed to compile script for layer {}: {}", layer_id, e);
+                None
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next_id == 0
+    }
+
+    /// Whether any script reads the audio spectrum.
+    pub fn uses_audio(&self) -> bool {
+        self.uses_audio
+    }
+
+    /// Run `init` (first frame only) and `update` for every script.
+    pub fn tick(&mut self, frame: &FrameInfo) -> ScriptOutput {
+        let frame_json = serde_json::to_string(frame).unwrap_or_default();
+
+        self.deadline.set(Some(Instant::now() + FRAME_BUDGET));
+        let result = self.context.with(|ctx| -> rquickjs::Result<String> {
+            let tick: Function = ctx.globals().get("__wpeTick")?;
+            tick.call((frame_json,))
+        });
+        self.deadline.set(None);
+
+        let raw = match result {
+            Ok(raw) => raw,
+            Err(e) => {
+                log::warn!("scenescript: tick failed: {}", e);
+                return ScriptOutput::default();
+            }
+        };
+        let Ok(parsed) = serde_json::from_str::<TickResult>(&raw) else {
+            log::warn!("scenescript: malformed tick result");
+            return ScriptOutput::default();
+        };
+
+        for err in parsed.errors {
+            log::warn!("scenescript: {}", err);
+        }
+
+        let layers = parsed
+            .layers
+            .into_iter()
+            .filter_map(|(id, state)| match LayerState::deserialize(state) {
+                Ok(state) => Some((id, state)),
+                Err(e) => {
+                    log::warn!("scenescript: invalid state of layer {}, skipped: {}", id, e);
+                    None
+                }
+            })
+            .collect();
+
+        ScriptOutput {
+            layers,
+            values: parsed
+                .values
+                .into_iter()
+                .map(|(id, v)| (ScriptId(id), v))
+                .collect(),
+            effects: parsed.effects.into_iter().collect(),
+        }
+    }
+}
+
+fn vec3_string(v: [f32; 3]) -> String {
+    format!("{} {} {}", v[0], v[1], v[2])
+}
@@ -0,0 +1,402 @@
+// SceneScript host prelude.
+//
+// Evaluated once per scene before any property script is registered.
+// Provides the subset of the Wallpaper Engine scripting API that
+// wallpapers use in practice, plus the `__wpe*` entry points the Rust
+// side calls to register scripts and run a frame.
+
+'use strict';
+
+// ── Vectors ─────────────────────────────────────────────────────
+
+class Vec2 {
+    constructor(x, y) {
+        if (typeof x === 'string') {
+            const p = x.trim().split(/\s+/).map(Number);
+            x = p[0]; y = p[1];
+        } else if (x !== null && typeof x === 'object') {
+            y = x.y; x = x.x;
+        } else if (y === undefined) {
+            y = x;
+        }
+        this.x = Number(x) || 0;
+        this.y = Number(y) || 0;
+    }
+    copy() { return new Vec2(this.x, this.y); }
+    add(o) { o = __vecArg(o); return new Vec2(this.x + o.x, this.y + o.y); }
+    subtract(o) { o = __vecArg(o); return new Vec2(this.x - o.x, this.y - o.y); }
+    multiply(o) { o = __vecArg(o); return new Vec2(this.x * o.x, this.y * o.y); }
+    divide(o) { o = __vecArg(o); return new Vec2(this.x / o.x, this.y / o.y); }
+    negate() { return new Vec2(-this.x, -this.y); }
+    dot(o) { return this.x * o.x + this.y * o.y; }
+    length() { return Math.sqrt(this.dot(this)); }
+    lengthSqr() { return this.dot(this); }
+    normalize() { const l = this.length(); return l > 0 ? this.divide(l) : this.copy(); }
+    mix(o, t) { return new Vec2(this.x + (o.x - this.x) * t, this.y + (o.y - this.y) * t); }
+    equals(o) { return this.x === o.x && this.y === o.y; }
+    toString() { return this.x + ' ' + this.y; }
+}
+
+class Vec3 {
+    constructor(x, y, z) {
+        if (typeof x === 'string') {
+            const p = x.trim().split(/\s+/).map(Number);
+            x = p[0]; y = p[1]; z = p[2];
+        } else if (x !== null && typeof x === 'object') {
+            z = x.z; y = x.y; x = x.x;
+        } else if (y === undefined) {
+            y = x; z = x;
+        }
+        this.x = Number(x) || 0;
+        this.y = Number(y) || 0;
+        this.z = Number(z) || 0;
+    }
+    copy() { return new Vec3(this.x, this.y, this.z); }
+    add(o) { o = __vecArg(o); return new Vec3(this.x + o.x, this.y + o.y, this.z + (o.z || 0)); }
+    subtract(o) { o = __vecArg(o); return new Vec3(this.x - o.x, this.y - o.y, this.z - (o.z || 0)); }
+    multiply(o) { o = __vecArg(o); return new Vec3(this.x * o.x, this.y * o.y, this.z * (o.z === undefined ? 1 : o.z)); }
+    divide(o) { o = __vecArg(o); return new Vec3(this.x / o.x, this.y / o.y, this.z / (o.z === undefined ? 1 : o.z)); }
+    negate() { return new Vec3(-this.x, -this.y, -this.z); }
+    dot(o) { return this.x * o.x + this.y * o.y + this.z * o.z; }
+    cross(o) {
+        return new Vec3(
+            this.y * o.z - this.z * o.y,
+            this.z * o.x - this.x * o.z,
+            this.x * o.y - this.y * o.x,
+        );
+    }
+    length() { return Math.sqrt(this.dot(this)); }
+    lengthSqr() { return this.dot(this); }
+    normalize() { const l = this.length(); return l > 0 ? this.divide(l) : this.copy(); }
+    mix(o, t) {
+        return new Vec3(
+            this.x + (o.x - this.x) * t,
+            this.y + (o.y - this.y) * t,
+            this.z + (o.z - this.z) * t,
+        );
+    }
+    equals(o) { return this.x === o.x && this.y === o.y && this.z === o.z; }
+    toString() { return this.x + ' ' + this.y + ' ' + this.z; }
+}
+
+// Scalars broadcast to every component in vector arithmetic.
+function __vecArg(o) {
+    return typeof o === 'number' ? { x: o, y: o, z: o } : o;
+}
+
+// ── Modules reachable through `import` ──────────────────────────
+
+const WEMath = {
+    PI: Math.PI,
+    deg2rad: Math.PI / 180,
+    rad2deg: 180 / Math.PI,
+    clamp(v, lo, hi) { return Math.min(Math.max(v, lo), hi); },
+    lerp(a, b, t) { return a + (b - a) * t; },
+    mix(a, b, t) { return a + (b - a) * t; },
+    smoothStep(lo, hi, v) {
+        const t = WEMath.clamp((v - lo) / (hi - lo), 0, 1);
+        return t * t * (3 - 2 * t);
+    },
+    fract(v) { return v - Math.floor(v); },
+};
+
+const WEVector = {
+    Vec2,
+    Vec3,
+    angleVector2(deg) { const r = deg * WEMath.deg2rad; return new Vec2(Math.cos(r), Math.sin(r)); },
+    vectorAngle2(v) { return Math.atan2(v.y, v.x) * WEMath.rad2deg; },
+};
+
+const WEColor = {
+    rgb2hsv(c) {
+        const max = Math.max(c.x, c.y, c.z), min = Math.min(c.x, c.y, c.z);
+        const d = max - min;
+        let h = 0;
+        if (d > 0) {
+            if (max === c.x) h = ((c.y - c.z) / d) % 6;
+            else if (max === c.y) h = (c.z - c.x) / d + 2;
+            else h = (c.x - c.y) / d + 4;
+            h /= 6;
+            if (h < 0) h += 1;
+        }
+        return new Vec3(h, max > 0 ? d / max : 0, max);
+    },
+    hsv2rgb(c) {
+        const i = Math.floor(c.x * 6), f = c.x * 6 - i;
+        const p = c.z * (1 - c.y), q = c.z * (1 - f * c.y), t = c.z * (1 - (1 - f) * c.y);
+        switch (((i % 6) + 6) % 6) {
+            case 0: return new Vec3(c.z, t, p);
+            case 1: return new Vec3(q, c.z, p);
+            case 2: return new Vec3(p, c.z, t);
+            case 3: return new Vec3(p, q, c.z);
+            case 4: return new Vec3(t, p, c.z);
+            default: return new Vec3(c.z, p, q);
+        }
+    },
+};
+
+const __modules = { WEMath, WEVector, WEColor };
+
+// ── Global API objects ──────────────────────────────────────────
+
+const console = {
+    log(...args) { __wpeLog(args.map(String).join(' ')); },
+};
+console.info = console.log;
+console.warn = console.log;
+console.error = console.log;
+
+const engine = {
+    runtime: 0,
+    frametime: 0,
+    timeOfDay: 0,
+    screenResolution: new Vec2(0, 0),
+    canvasSize: new Vec2(0, 0),
+    userProperties: {},
+    isDesktopDevice() { return true; },
+    isMobileDevice() { return false; },
+    isWallpaper() { return true; },
+    isScreensaver() { return false; },
+    isRunningInEditor() { return false; },
+    registerAudioBuffers(resolution) {
+        const n = resolution || 16;
+        const buffers = {
+            resolution: n,
+            left: new Float32Array(n),
+            right: new Float32Array(n),
+            average: new Float32Array(n),
+        };
+        __audioBuffers.push(buffers);
+        return buffers;
+    },
+};
+
+// Buffers handed out by `engine.registerAudioBuffers`, refreshed every
+// tick from the host spectrum (16, 32 and 64 bands).
+const __audioBuffers = [];
+const __audioResolutions = [16, 32, 64];
+
+function __fillAudioBuffers(audio) {
+    for (const b of __audioBuffers) {
+        let i = __audioResolutions.findIndex((n) => n >= b.resolution);
+        if (i < 0) i = __audioResolutions.length - 1;
+        const left = audio.left[i], right = audio.right[i];
+        for (let k = 0; k < b.resolution; k++) {
+            const l = left[k] || 0, r = right[k] || 0;
+            b.left[k] = l;
+            b.right[k] = r;
+            b.average[k] = (l + r) / 2;
+        }
+    }
+}
+
+const input = {
+    cursorWorldPosition: new Vec3(0, 0, 0),
+    cursorScreenPosition: new Vec2(0, 0),
+    cursorLeftDown: false,
+};
+
+const shared = {};
+
+// ── Layers ──────────────────────────────────────────────────────
+
+const __layers = new Map();
+const __layersByName = new Map();
+const __snapshots = new Map();
+// Last visibility reported to the host, by effect id.
+const __effectSnapshots = new Map();
+
+function __layerSnapshot(l) {
+    return JSON.stringify([
+        l.origin.x, l.origin.y, l.origin.z,
+        l.scale.x, l.scale.y, l.scale.z,
+        l.angles.x, l.angles.y, l.angles.z,
+        !!l.visible, Number(l.alpha),
+    ]);
+}
+
+const thisScene = {
+    getLayer(name) { return __layersByName.get(name) || null; },
+    enumerateLayers() { return Array.from(__layers.values()); },
+    getLayerCount() { return __layers.size; },
+};
+
+// ── Script properties ───────────────────────────────────────────
+
+// Overrides from the scene's `scriptproperties` for the script that
+// is currently being registered.
+let __currentProperties = {};
+
+function createScriptProperties() {
+    const props = {};
+    const add = (def) => {
+        const user = __currentProperties[def.name];
+        props[def.name] = user && typeof user === 'object' && 'value' in user
+            ? user.value
+            : (user !== undefined ? user : def.value);
+        return builder;
+    };
+    const builder = {
+        addSlider: add,
+        addCheckbox: add,
+        addCombo: add,
+        addColor: add,
+        addText: add,
+        addTextInput: add,
+        addDirectory: add,
+        addFile: add,
+        finish() { return props; },
+    };
+    return builder;
+}
+
+// ── Value conversion ────────────────────────────────────────────
+
+function __fromHost(kind, v) {
+    switch (kind) {
+        case 'vec2': return new Vec2(v);
+        case 'vec3': return new Vec3(v);
+        case 'number': return Number(v);
+        case 'bool': return !!v;
+        default: return v;
+    }
+}
+
+function __toHost(kind, v) {
+    switch (kind) {
+        case 'vec2': { const o = new Vec2(v); return o.x + ' ' + o.y; }
+        case 'vec3': { const o = new Vec3(v); return o.x + ' ' + o.y + ' ' + o.z; }
+        case 'number': return Number(v);
+        case 'bool': return !!v;
+        default: return v;
+    }
+}
+
+// ── Host entry points ───────────────────────────────────────────
+
+const __scripts = [];
+
+function __wpeSetUserProperties(json) {
+    engine.userProperties = JSON.parse(json) || {};
+}
+
+function __wpeAddLayer(json) {
+    const s = JSON.parse(json);
+    const layer = {
+        id: s.id,
+        name: s.name,
+        origin: new Vec3(s.origin),
+        scale: new Vec3(s.scale),
+        angles: new Vec3(s.angles),
+        visible: s.visible,
+        alpha: s.alpha,
+        __effects: [],
+        getEffect(key) {
+            if (typeof key === 'number') return this.__effects[key] || null;
+            return this.__effects.find((e) => e.name === key) || null;
+        },
+        getEffectCount() { return this.__effects.length; },
+    };
+    __layers.set(s.id, layer);
+    if (!__layersByName.has(s.name)) __layersByName.set(s.name, layer);
+    __snapshots.set(s.id, __layerSnapshot(layer));
+}
+
+function __wpeAddEffect(layerId, id, name, visible) {
+    const layer = __layers.get(layerId);
+    if (!layer) return;
+    layer.__effects.push({ id, name, visible });
+    __effectSnapshots.set(id, visible);
+}
+
+// `factory` is the transformed script body wrapped in a function that
+// returns its `init`/`update` exports.
+function __wpeRegister(id, layerId, kind, property, initialJson, propertiesJson, factory) {
+    const layer = __layers.get(layerId) || null;
+    __currentProperties = JSON.parse(propertiesJson) || {};
+    let exports;
+    try {
+        exports = factory(layer);
+    } finally {
+        __currentProperties = {};
+    }
+    const script = {
+        id,
+        layer,
+        kind,
+        property,
+        value: __fromHost(kind, JSON.parse(initialJson)),
+        update: exports.update,
+        initialized: typeof exports.init !== 'function',
+        init: exports.init,
+        failed: false,
+    };
+    __scripts.push(script);
+}
+
+function __wpeTick(frameJson) {
+    const f = JSON.parse(frameJson);
+    engine.runtime = f.runtime;
+    engine.frametime = f.frametime;
+    const now = new Date();
+    engine.timeOfDay = (now.getHours() * 3600 + now.getMinutes() * 60 + now.getSeconds()) / 86400;
+    engine.screenResolution = new Vec2(f.screen[0], f.screen[1]);
+    engine.canvasSize = new Vec2(f.canvas[0], f.canvas[1]);
+    input.cursorScreenPosition = new Vec2(f.cursor[0] * f.screen[0], f.cursor[1] * f.screen[1]);
+    input.cursorWorldPosition = new Vec3(f.cursor[0] * f.canvas[0], (1 - f.cursor[1]) * f.canvas[1], 0);
+    __fillAudioBuffers(f.audio);
+
+    const values = {};
+    const errors = [];
+
+    for (const s of __scripts) {
+        if (s.failed) continue;
+        try {
+            if (!s.initialized) {
+                const v = s.init(s.value);
+                if (v !== undefined) s.value = __fromHost(s.kind, v);
+                s.initialized = true;
+            }
+            if (typeof s.update === 'function') {
+                const v = s.update(s.value);
+                if (v !== undefined) s.value = __fromHost(s.kind, v);
+            }
+            if (s.layer && s.property) {
+                s.layer[s.property] = s.value;
+            } else {
+                values[s.id] = __toHost(s.kind, s.value);
+            }
+        } catch (e) {
+            s.failed = true;
+            errors.push('script ' + s.id + ': ' + (e && e.stack ? e.stack : String(e)));
+        }
+    }
+
+    const layers = {};
+    for (const [id, l] of __layers) {
+        const snap = __layerSnapshot(l);
+        if (snap !== __snapshots.get(id)) {
+            __snapshots.set(id, snap);
+            layers[id] = {
+                origin: [l.origin.x, l.origin.y, l.origin.z],
+                scale: [l.scale.x, l.scale.y, l.scale.z],
+                angles: [l.angles.x, l.angles.y, l.angles.z],
+                visible: !!l.visible,
+                alpha: Number(l.alpha),
+            };
+        }
+    }
+
+    const effects = {};
+    for (const l of __layers.values()) {
+        for (const e of l.__effects) {
+            const visible = !!e.visible;
+            if (visible !== __effectSnapshots.get(e.id)) {
+                __effectSnapshots.set(e.id, visible);
+                effects[e.id] = visible;
+            }
+        }
+    }
+
+    return JSON.stringify({ values, layers, effects, errors });
+}
@@ -0,0 +1,169 @@
+//! SceneScript source handling.
+//!
+//! Scene JSON stores scripted properties as
+//! `{"script": "...", "scriptproperties": {...}, "value": ...}`.  The
+//! script itself is written as an ES module (`export function update`,
+//! `import * as WEMath from 'WEMath'`); [`wrap_module`] rewrites it into
+//! a plain function expression that the runtime can evaluate.
+
+use serde_json::Value;
+
+/// A property script extracted from the scene JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyScript {
+    pub source: String,
+    /// User overrides for values declared via `createScriptProperties()`.
+    pub properties: Value,
+    /// The static value the property falls back to without scripting.
+    pub value: Value,
+}
+
+impl PropertyScript {
+    /// Extract a script from a `{"script": ..., "value": ...}` wrapper.
+    /// Returns `None` for plain values and wrappers without a script.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let obj = value.as_object()?;
+        let source = obj.get("script")?.as_str()?;
+        if source.trim().is_empty() {
+            return None;
+        }
+        Some(Self {
+            source: source.to_string(),
+            properties: obj.get("scriptproperties").cloned().unwrap_or(Value::Null),
+            value: obj.get("value").cloned().unwrap_or(Value::Null),
+        })
+    }
+}
+
+/// How a property value is marshalled into and out of JavaScript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Number,
+    Bool,
+    Vec2,
+    Vec3,
+    /// Passed through unchanged (strings, colours with alpha, …).
+    Raw,
+}
+
+impl ValueKind {
+    /// Infer the kind from a static property value.
+    pub fn infer(value: &Value) -> Self {
+        match value {
+            Value::Number(_) => ValueKind::Number,
+            Value::Bool(_) => ValueKind::Bool,
+            Value::String(s) => match s.split_whitespace().count() {
+                2 => ValueKind::Vec2,
+                3 => ValueKind::Vec3,
+                _ => ValueKind::Raw,
+            },
+            _ => ValueKind::Raw,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ValueKind::Number => "number",
+            ValueKind::Bool => "bool",
+            ValueKind::Vec2 => "vec2",
+            ValueKind::Vec3 => "vec3",
+            ValueKind::Raw => "raw",
+        }
+    }
+}
+
+/// Rewrite an ES-module style SceneScript into a function expression
+/// `(function(thisLayer) { ... return { init, update }; })`.
+///
+/// `import` statements are mapped onto the prelude's built-in modules
+/// and `export` keywords are dropped.
+pub fn wrap_module(source: &str) -> String {
+    let mut body = String::with_capacity(source.len() + 256);
+
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with("import ") {
+            body.push_str(&rewrite_import(trimmed));
+            body.push('\n');
+            continue;
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("export default ") {
+            body.push_str(rest);
+        } else if let Some(rest) = trimmed.strip_prefix("export ") {
+            body.push_str(rest);
+        } else {
+            body.push_str(line);
+        }
+        body.push('\n');
+    }
+
+    format!(
+        "(function(thisLayer) {{\n{}\nreturn {{\n\
+         init: typeof init === 'function' ? init : undefined,\n\
+         update: typeof update === 'function' ? update : undefined,\n\
+         }};\n}})",
+        body
+    )
+}
+
+/// `import * as M from 'WEMath';`      → `const M = __modules['WEMath'] || {};`
+/// `import { a, b } from 'WEMath';`    → `const { a, b } = __modules['WEMath'] || {};`
+/// Anything else is commented out.
+fn rewrite_import(line: &str) -> String {
+    let stmt = line.trim().trim_end_matches(';');
+    let Some((binding, module)) = stmt["import ".len()..].rsplit_once(" from ") else {
+        return format!("// {}", line);
+    };
+    let module = module.trim().trim_matches(|c| c == '\'' || c == '"');
+    let binding = binding.trim();
+
+    if let Some(name) = binding.strip_prefix("* as ") {
+        return format!("const {} = __modules['{}'] || {{}};", name.trim(), module);
+    }
+    if binding.starts_with('{') {
+        let names = binding.replace(" as ", ": ");
+        return format!("const {} = __modules['{}'] || {{}};", names, module);
+    }
+    format!("// {}", line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_module_strips_exports_and_imports() {
+        let src = "'use strict';\n\
+                   import * as WEMath from 'WEMath';\n\
+                   export var scriptProperties = createScriptProperties().finish();\n\
+                   export function update(value) {\n    return value;\n}";
+        let wrapped = wrap_module(src);
+        assert!(wrapped.starts_with("(function(thisLayer) {"));
+        assert!(wrapped.contains("const WEMath = __modules['WEMath'] || {};"));
+        assert!(wrapped.contains("\nvar scriptProperties"));
+        assert!(wrapped.contains("\nfunction update(value) {"));
+        assert!(!wrapped.contains("export "));
+    }
+
+    #[test]
+    fn test_rewrite_named_import() {
+        assert_eq!(
+            rewrite_import("import { Vec2, angleVector2 as av } from \"WEVector\";"),
+            "const { Vec2, angleVector2: av } = __modules['WEVector'] || {};"
+        );
+    }
+
+    #[test]
+    fn test_property_script_from_value() {
+        let v: Value = serde_json::json!({
+            "script": "export function update(v) { return v; }",
+            "value": "1 2 3"
+        });
+        let script = PropertyScript::from_value(&v).unwrap();
+        assert_eq!(script.value, Value::String("1 2 3".into()));
+        assert_eq!(ValueKind::infer(&script.value), ValueKind::Vec3);
+        assert!(PropertyScript::from_value(&Value::from(1.0)).is_none());
+    }
+}
@@ -0,0 +1,189 @@
+//! FFmpeg decoder thread.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Once,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+    },
+    thread,
+    time::Duration,
+};
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg::{
+    format::Pixel,
+    media::Type,
+    software::scaling::{self, flag::Flags},
+    util::frame::video::Video,
+};
+
+use super::stream::{QUEUE_DEPTH, VideoFrame};
+
+/// How long to wait for the decoder to report the stream size.
+const OPEN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Why the decode loop ended.
+enum Stop {
+    /// The stream was dropped.
+    Closed,
+    Error(ffmpeg::Error),
+}
+
+impl From<ffmpeg::Error> for Stop {
+    fn from(e: ffmpeg::Error) -> Self {
+        Stop::Error(e)
+    }
+}
+
+/// Start decoding `path` on a new thread.  Returns the frame size and the
+/// frame queue once the stream is open.
+pub fn spawn(path: &Path, stop: Arc<AtomicBool>) -> Option<([u32; 2], Receiver<VideoFrame>)> {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        if let Err(e) = ffmpeg::init() {
+            log::warn!("video: ffmpeg init failed: {}", e);
+        }
+        ffmpeg::util::log::set_level(ffmpeg::util::log::Level::Error);
+    });
+
+    let (size_tx, size_rx) = mpsc::channel();
+    let (frame_tx, frame_rx) = mpsc::sync_channel(QUEUE_DEPTH);
+    let path: PathBuf = path.to_path_buf();
+    thread::spawn(move || match decode(&path, &size_tx, &frame_tx, &stop) {
+        Ok(()) | Err(Stop::Closed) => {}
+        Err(Stop::Error(e)) => log::warn!("video: decoding '{}' failed: {}", path.display(), e),
+    });
+
+    let size = size_rx.recv_timeout(OPEN_TIMEOUT).ok()?;
+    Some((size, frame_rx))
+}
+
+/// Decode in a loop, seeking back to the start at the end of the file.
+fn decode(
+    path: &Path,
+    size_tx: &mpsc::Sender<[u32; 2]>,
+    frames: &SyncSender<VideoFrame>,
+    stop: &AtomicBool,
+) -> Result<(), Stop> {
+    let mut input = ffmpeg::format::input(path)?;
+    let stream = input
+        .streams()
+        .best(Type::Video)
+        .ok_or(ffmpeg::Error::StreamNotFound)?;
+    let index = stream.index();
+    let time_base = f64::from(stream.time_base());
+    let mut decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .video()?;
+
+    let (width, height) = (decoder.width(), decoder.height());
+    let mut scaler = scaling::Context::get(
+        decoder.format(),
+        width,
+        height,
+        Pixel::RGBA,
+        width,
+        height,
+        Flags::BILINEAR,
+    )?;
+    let _ = size_tx.send([width, height]);
+
+    let mut clock = LoopClock::default();
+    loop {
+        for (stream, packet) in input.packets() {
+            if stop.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            if stream.index() == index {
+                decoder.send_packet(&packet)?;
+                drain(&mut decoder, &mut scaler, time_base, &mut clock, frames)?;
+            }
+        }
+        decoder.send_eof()?;
+        drain(&mut decoder, &mut scaler, time_base, &mut clock, frames)?;
+
+        if !clock.next_loop() {
+            // Nothing decoded in a whole pass; don't spin.
+            return Ok(());
+        }
+        input.seek(0, ..)?;
+        decoder.flush();
+    }
+}
+
+/// Send every frame the decoder has ready.
+fn drain(
+    decoder: &mut ffmpeg::decoder::Video,
+    scaler: &mut scaling::Context,
+    time_base: f64,
+    clock: &mut LoopClock,
+    frames: &SyncSender<VideoFrame>,
+) -> Result<(), Stop> {
+    let mut decoded = Video::empty();
+    let mut rgba = Video::empty();
+    while decoder.receive_frame(&mut decoded).is_ok() {
+        scaler.run(&decoded, &mut rgba)?;
+        let pts = clock.pts(decoded.timestamp().map(|t| t as f64 * time_base));
+        let frame = VideoFrame {
+            pts,
+            rgba: packed_rows(&rgba),
+        };
+        frames.send(frame).map_err(|_| Stop::Closed)?;
+    }
+    Ok(())
+}
+
+/// Copy plane 0 without row padding.
+fn packed_rows(frame: &Video) -> Vec<u8> {
+    let row = frame.width() as usize * 4;
+    let stride = frame.stride(0);
+    let data = frame.data(0);
+    if stride == row {
+        return data[..row * frame.height() as usize].to_vec();
+    }
+    data.chunks(stride)
+        .take(frame.height() as usize)
+        .flat_map(|line| &line[..row])
+        .copied()
+        .collect()
+}
+
+/// Maps stream timestamps onto a clock that keeps running across loops.
+#[derive(Default)]
+struct LoopClock {
+    /// Timestamp of the first frame of the file.
+    first: Option<f64>,
+    /// Added to every timestamp; grows by the file length each loop.
+    offset: f64,
+    last: f64,
+    /// Spacing of the last two frames, used as the length of the final
+    /// frame.
+    step: f64,
+    decoded_this_loop: bool,
+}
+
+impl LoopClock {
+    fn pts(&mut self, timestamp: Option<f64>) -> f64 {
+        let pts = match timestamp {
+            Some(t) => self.offset + t - *self.first.get_or_insert(t),
+            // No timestamp: assume the previous spacing.
+            None => self.last + self.step,
+        };
+        if self.decoded_this_loop {
+            self.step = (pts - self.last).max(0.0);
+        }
+        self.last = pts;
+        self.decoded_this_loop = true;
+        pts
+    }
+
+    /// Start the next loop after the last frame.  Returns `false` when the
+    /// pass produced no frames.
+    fn next_loop(&mut self) -> bool {
+        let decoded = std::mem::take(&mut self.decoded_this_loop);
+        self.offset = self.last + self.step.max(1.0 / 60.0);
+        decoded
+    }
+}
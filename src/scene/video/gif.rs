@@ -0,0 +1,310 @@
+//! Animated GIF payloads.
+//!
+//! Frames are decoded to RGBA by [`Video::extract_all_frames`], or one at
+//! a time by a [`GifStream`] for GIFs too large to keep decoded.  The delay
+//! of each frame lives in its Graphic Control Extension, which `Video` does
+//! not expose, so the block list is walked here.
+//!
+//! [`Video::extract_all_frames`]: pkg_parser::pkg_parser::video_parser::Video::extract_all_frames
+
+use std::{
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use pkg_parser::pkg_parser::video_parser::Video;
+
+/// Delay used for frames that declare none (or under 20 ms), matching
+/// what browsers do.
+const DEFAULT_DELAY: f32 = 0.1;
+
+/// Frames a [`GifStream`] decodes ahead of the one on screen.
+const STREAM_AHEAD: usize = 4;
+
+pub struct GifFrames {
+    pub size: [u32; 2],
+    /// Full-canvas RGBA frames; empty when only probed.
+    pub frames: Vec<Vec<u8>>,
+    /// Seconds each frame stays on screen.
+    pub delays: Vec<f32>,
+    /// Sum of all delays.
+    pub duration: f32,
+}
+
+impl GifFrames {
+    /// Read the size and frame delays of a GIF without decoding it.
+    pub fn probe(bytes: &[u8]) -> Option<Self> {
+        let video = Video::new(bytes)?;
+        if !video.is_gif() {
+            return None;
+        }
+        let (width, height) = video.dimensions?;
+        let delays = match frame_delays(bytes) {
+            Some(delays) if !delays.is_empty() => delays,
+            _ => vec![DEFAULT_DELAY; video.frame_count? as usize],
+        };
+        if delays.is_empty() {
+            return None;
+        }
+        Some(Self {
+            size: [width, height],
+            frames: Vec::new(),
+            duration: delays.iter().sum(),
+            delays,
+        })
+    }
+
+    /// Decode every frame.  Frames that fail to decode are left out.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let probed = Self::probe(bytes)?;
+        let frame_len = probed.frame_len()?;
+        let decoded = Video::new(bytes)?.extract_all_frames()?;
+        let (frames, delays) = keep_frames(decoded, &probed.delays, frame_len);
+        if frames.is_empty() {
+            log::warn!("gif: no decodable frames");
+            return None;
+        }
+        Some(Self {
+            size: probed.size,
+            frames,
+            duration: delays.iter().sum(),
+            delays,
+        })
+    }
+
+    /// Bytes of one RGBA frame; `None` when that overflows.
+    pub fn frame_len(&self) -> Option<usize> {
+        (self.size[0] as usize).checked_mul(self.size[1] as usize)?.checked_mul(4)
+    }
+
+    /// Index of the frame shown `seconds` into the loop.
+    pub fn frame_index(&self, seconds: f32) -> usize {
+        if self.duration <= 0.0 {
+            return 0;
+        }
+        let mut t = seconds.rem_euclid(self.duration);
+        for (i, delay) in self.delays.iter().enumerate() {
+            if t < *delay {
+                return i;
+            }
+            t -= delay;
+        }
+        self.delays.len() - 1
+    }
+}
+
+/// Frames of a GIF decoded on a background thread, in loop order and at
+/// most [`STREAM_AHEAD`] ahead, so only a few are ever in memory.
+pub struct GifStream {
+    frames: Receiver<(usize, Vec<u8>)>,
+    /// Next frame, received but not yet due.
+    pending: Option<(usize, Vec<u8>)>,
+    count: usize,
+}
+
+impl GifStream {
+    /// Start decoding the frames of `gif`, probed from `bytes`.  The
+    /// thread stops when the stream is dropped.
+    pub fn spawn(bytes: Vec<u8>, gif: &GifFrames) -> Option<Self> {
+        let count = gif.delays.len();
+        let frame_len = gif.frame_len()?;
+        let (sender, frames) = mpsc::sync_channel(STREAM_AHEAD);
+        let spawned = thread::Builder::new()
+            .name("gif decoder".to_string())
+            .spawn(move || {
+                let Some(video) = Video::new(&bytes) else {
+                    return;
+                };
+                let mut failed = 0;
+                for index in (0..count).cycle() {
+                    match video.extract_frame(index).filter(|f| f.len() == frame_len) {
+                        Some(rgba) => {
+                            failed = 0;
+                            if sender.send((index, rgba)).is_err() {
+                                return;
+                            }
+                        }
+                        None if failed + 1 >= count => {
+                            log::warn!("gif: no decodable frames");
+                            return;
+                        }
+                        None => failed += 1,
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            log::warn!("gif: cannot start decoder: {}", e);
+            return None;
+        }
+        Some(Self {
+            frames,
+            pending: None,
+            count,
+        })
+    }
+
+    /// Frame `index`, once it is decoded.  Frames before it are dropped;
+    /// `None` while it is still being decoded.
+    pub fn frame(&mut self, index: usize) -> Option<Vec<u8>> {
+        loop {
+            let (decoded, rgba) = match self.pending.take() {
+                Some(frame) => frame,
+                None => self.frames.try_recv().ok()?,
+            };
+            if decoded == index {
+                return Some(rgba);
+            }
+            if is_ahead(decoded, index, self.count) {
+                self.pending = Some((decoded, rgba));
+                return None;
+            }
+        }
+    }
+}
+
+/// Whether frame `decoded` comes after `index` within the next half loop
+/// of `count` frames, rather than being one that was skipped.
+fn is_ahead(decoded: usize, index: usize, count: usize) -> bool {
+    (decoded + count - index) % count <= count / 2
+}
+
+/// The frames of `frame_len` bytes, each with its delay from `declared`.
+/// Frames of another size are dropped along with their delay.
+fn keep_frames(
+    frames: Vec<Vec<u8>>,
+    declared: &[f32],
+    frame_len: usize,
+) -> (Vec<Vec<u8>>, Vec<f32>) {
+    frames
+        .into_iter()
+        .enumerate()
+        .filter(|(_, frame)| frame.len() == frame_len)
+        .map(|(index, frame)| (frame, declared.get(index).copied().unwrap_or(DEFAULT_DELAY)))
+        .unzip()
+}
+
+/// Delay of every image in a GIF, in seconds.
+fn frame_delays(bytes: &[u8]) -> Option<Vec<f32>> {
+    if !bytes.starts_with(b"GIF87a") && !bytes.starts_with(b"GIF89a") {
+        return None;
+    }
+    // Header and logical screen descriptor.
+    let mut pos = 13;
+    let packed = *bytes.get(10)?;
+    if packed & 0x80 != 0 {
+        pos += color_table_len(packed);
+    }
+
+    let mut delays = Vec::new();
+    let mut pending = None;
+    loop {
+        match *bytes.get(pos)? {
+            // Extension.
+            0x21 => {
+                let label = *bytes.get(pos + 1)?;
+                if label == 0xF9 && *bytes.get(pos + 2)? >= 4 {
+                    let centis = u16::from_le_bytes([*bytes.get(pos + 4)?, *bytes.get(pos + 5)?]);
+                    pending = Some(centis);
+                }
+                pos = skip_sub_blocks(bytes, pos + 2)?;
+            }
+            // Image descriptor.
+            0x2C => {
+                let packed = *bytes.get(pos + 9)?;
+                pos += 10;
+                if packed & 0x80 != 0 {
+                    pos += color_table_len(packed);
+                }
+                // LZW minimum code size, then the image data.
+                pos = skip_sub_blocks(bytes, pos + 1)?;
+                delays.push(match pending.take() {
+                    Some(centis) if centis >= 2 => centis as f32 / 100.0,
+                    _ => DEFAULT_DELAY,
+                });
+            }
+            // Trailer (0x3B), or a block we cannot walk.
+            _ => return Some(delays),
+        }
+    }
+}
+
+fn color_table_len(packed: u8) -> usize {
+    3 << ((packed & 0x07) + 1)
+}
+
+/// Position after the data sub-blocks starting at `pos`.
+fn skip_sub_blocks(bytes: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *bytes.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            return Some(pos);
+        }
+        pos += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1×1 GIF with three frames: 50 ms, no delay, 300 ms.
+    fn three_frames() -> Vec<u8> {
+        let mut gif = b"GIF89a".to_vec();
+        // 1×1, global color table with 2 entries.
+        gif.extend_from_slice(&[1, 0, 1, 0, 0x80, 0, 0]);
+        gif.extend_from_slice(&[0, 0, 0, 255, 255, 255]);
+        // Netscape looping extension.
+        gif.extend_from_slice(&[0x21, 0xFF, 11]);
+        gif.extend_from_slice(b"NETSCAPE2.0");
+        gif.extend_from_slice(&[3, 1, 0, 0, 0]);
+        for delay in [Some(5u16), None, Some(30)] {
+            if let Some(delay) = delay {
+                let [lo, hi] = delay.to_le_bytes();
+                gif.extend_from_slice(&[0x21, 0xF9, 4, 0, lo, hi, 0, 0]);
+            }
+            gif.extend_from_slice(&[0x2C, 0, 0, 0, 0, 1, 0, 1, 0, 0]);
+            gif.extend_from_slice(&[2, 2, 0x44, 0x01, 0]);
+        }
+        gif.push(0x3B);
+        gif
+    }
+
+    #[test]
+    fn test_frame_delays() {
+        assert_eq!(frame_delays(&three_frames()), Some(vec![0.05, 0.1, 0.3]));
+        assert_eq!(frame_delays(b"\x89PNG"), None);
+    }
+
+    #[test]
+    fn test_keep_frames() {
+        let frames = vec![vec![1; 4], vec![2; 3], vec![3; 4], vec![4; 4]];
+        let (kept, delays) = keep_frames(frames, &[0.05, 0.1, 0.3], 4);
+        assert_eq!(kept, vec![vec![1; 4], vec![3; 4], vec![4; 4]]);
+        assert_eq!(delays, vec![0.05, 0.3, DEFAULT_DELAY]);
+    }
+
+    #[test]
+    fn test_is_ahead() {
+        assert!(is_ahead(5, 3, 10));
+        assert!(!is_ahead(2, 3, 10));
+        // Wraps around the loop.
+        assert!(is_ahead(0, 9, 10));
+        assert!(!is_ahead(9, 0, 10));
+    }
+
+    #[test]
+    fn test_frame_index() {
+        let gif = GifFrames {
+            size: [1, 1],
+            frames: vec![vec![0; 4]; 3],
+            delays: vec![0.05, 0.1, 0.3],
+            duration: 0.45,
+        };
+        assert_eq!(gif.frame_index(0.0), 0);
+        assert_eq!(gif.frame_index(0.1), 1);
+        assert_eq!(gif.frame_index(0.2), 2);
+        // Loops after the last frame.
+        assert_eq!(gif.frame_index(0.52), 1);
+    }
+}
@@ -0,0 +1,50 @@
+//! Video decoding for video-backed textures and video wallpapers.
+//!
+//! Decoding uses FFmpeg (`ffmpeg-next`) and is only compiled with the
+//! `video` cargo feature; without it [`VideoStream::open`] logs a warning
+//! and returns `None`, so video layers stay blank.  A [`VideoStream`]
+//! decodes on a background thread into a short bounded queue and hands out
+//! RGBA frames as the playback clock reaches their timestamps, restarting
+//! from the beginning at the end of the file.
+//!
+//! Animated GIFs need no FFmpeg: [`gif`] decodes them up front through
+//! `pkg_parser`.
+
+#[cfg(feature = "video")]
+mod decoder;
+pub mod gif;
+mod stream;
+
+use std::path::Path;
+
+pub use stream::VideoStream;
+
+/// Whether `path` names a video file that can be played as a wallpaper.
+pub fn is_video_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("mp4") || e.eq_ignore_ascii_case("webm"))
+}
+
+/// Container format of a video payload, from its magic bytes.
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.len() >= 8 && &bytes[4..8] == b"ftyp" {
+        Some("mp4")
+    } else if bytes.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        Some("webm")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"\0\0\0\x20ftypisom"), Some("mp4"));
+        assert_eq!(sniff(&[0x1A, 0x45, 0xDF, 0xA3, 0x01]), Some("webm"));
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n"), None);
+    }
+}
@@ -0,0 +1,203 @@
+//! Clock-driven frame queue fed by the decoder thread.
+
+use std::{
+    collections::hash_map::RandomState,
+    fs::OpenOptions,
+    hash::BuildHasher,
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, TryRecvError},
+    },
+};
+
+/// Frames decoded ahead of the clock.  Keeps memory bounded for large
+/// videos while absorbing decoder hiccups.
+#[cfg_attr(not(feature = "video"), allow(dead_code))]
+pub(super) const QUEUE_DEPTH: usize = 4;
+
+/// One decoded frame, tightly packed RGBA.
+pub struct VideoFrame {
+    /// Presentation time in seconds from the start of playback.  Keeps
+    /// increasing across loops.
+    pub pts: f64,
+    pub rgba: Vec<u8>,
+}
+
+pub struct VideoStream {
+    size: [u32; 2],
+    frames: Receiver<VideoFrame>,
+    /// Next frame, received but not yet due.
+    pending: Option<VideoFrame>,
+    /// Clock value at which playback started.
+    start: Option<f64>,
+    stop: Arc<AtomicBool>,
+    /// Temporary copy of an in-memory payload, removed on drop.
+    temp_file: Option<PathBuf>,
+}
+
+impl VideoStream {
+    /// Decode an in-memory video (e.g. the payload of a `.tex`).  FFmpeg
+    /// reads from a file, so the bytes are written to a private file in
+    /// `$XDG_RUNTIME_DIR` (or the temp directory).
+    pub fn open(bytes: &[u8]) -> Option<Self> {
+        let extension = super::sniff(bytes)?;
+        let dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .unwrap_or_else(std::env::temp_dir);
+        let path = match write_private(&dir, extension, bytes) {
+            Ok(path) => path,
+            Err(e) => {
+                log::warn!("video: cannot write to '{}': {}", dir.display(), e);
+                return None;
+            }
+        };
+        match Self::open_file(&path) {
+            Some(mut stream) => {
+                stream.temp_file = Some(path);
+                Some(stream)
+            }
+            None => {
+                let _ = std::fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    /// Decode a video file.
+    #[cfg(feature = "video")]
+    pub fn open_file(path: &Path) -> Option<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (size, frames) = super::decoder::spawn(path, Arc::clone(&stop))?;
+        log::info!("video: playing '{}' ({}x{})", path.display(), size[0], size[1]);
+        Some(Self::from_parts(size, frames, stop))
+    }
+
+    #[cfg(not(feature = "video"))]
+    pub fn open_file(path: &Path) -> Option<Self> {
+        log::warn!(
+            "video: cannot play '{}', built without the `video` feature",
+            path.display()
+        );
+        None
+    }
+
+    #[cfg_attr(not(feature = "video"), allow(dead_code))]
+    fn from_parts(size: [u32; 2], frames: Receiver<VideoFrame>, stop: Arc<AtomicBool>) -> Self {
+        Self {
+            size,
+            frames,
+            pending: None,
+            start: None,
+            stop,
+            temp_file: None,
+        }
+    }
+
+    /// Frame size in pixels.
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Latest frame due at `clock` seconds, or `None` when the frame on
+    /// screen is still current.  Playback starts at the first call.
+    pub fn frame_at(&mut self, clock: f64) -> Option<VideoFrame> {
+        let position = clock - *self.start.get_or_insert(clock);
+        let mut latest = None;
+        loop {
+            let frame = match self.pending.take() {
+                Some(frame) => frame,
+                None => match self.frames.try_recv() {
+                    Ok(frame) => frame,
+                    Err(TryRecvError::Empty | TryRecvError::Disconnected) => break,
+                },
+            };
+            if frame.pts > position {
+                self.pending = Some(frame);
+                break;
+            }
+            latest = Some(frame);
+        }
+        latest
+    }
+}
+
+impl Drop for VideoStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(path) = self.temp_file.take() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Write `bytes` to a new file with a random name in `dir`, readable by
+/// this user only.  The file is created exclusively, so an existing file
+/// or symlink of the same name is never followed or truncated.
+fn write_private(dir: &Path, extension: &str, bytes: &[u8]) -> io::Result<PathBuf> {
+    let mut attempts = 0;
+    loop {
+        let name = RandomState::new().hash_one(std::process::id());
+        let path = dir.join(format!("linux-wallpaper-engine-{:016x}.{}", name, extension));
+        let created = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path);
+        match created {
+            Ok(mut file) => {
+                if let Err(e) = file.write_all(bytes) {
+                    let _ = std::fs::remove_file(&path);
+                    return Err(e);
+                }
+                return Ok(path);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempts < 8 => attempts += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::sync_channel;
+
+    #[test]
+    fn test_frames_follow_clock() {
+        let (tx, rx) = sync_channel(QUEUE_DEPTH);
+        let mut stream = VideoStream::from_parts([1, 1], rx, Arc::new(AtomicBool::new(false)));
+        for i in 0..4 {
+            tx.send(VideoFrame {
+                pts: i as f64 * 0.1,
+                rgba: vec![i; 4],
+            })
+            .unwrap();
+        }
+
+        // Playback starts at clock 10.0.
+        assert_eq!(stream.frame_at(10.0).unwrap().rgba[0], 0);
+        assert!(stream.frame_at(10.05).is_none());
+        // Frames that were skipped over are dropped.
+        assert_eq!(stream.frame_at(10.25).unwrap().rgba[0], 2);
+        assert_eq!(stream.frame_at(11.0).unwrap().rgba[0], 3);
+    }
+
+    #[test]
+    fn test_write_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir();
+        let a = write_private(&dir, "mp4", b"abc").unwrap();
+        let b = write_private(&dir, "mp4", b"abc").unwrap();
+        assert_ne!(a, b);
+        assert_eq!(std::fs::read(&a).unwrap(), b"abc");
+        assert_eq!(std::fs::metadata(&a).unwrap().permissions().mode() & 0o777, 0o600);
+        let _ = std::fs::remove_file(a);
+        let _ = std::fs::remove_file(b);
+    }
+}